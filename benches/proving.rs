@@ -0,0 +1,135 @@
+//! Benchmarks txn, agg and block proving, so that performance regressions of
+//! this crate or of plonky2_evm show up before they reach a prover fleet.
+//!
+//! The benchmarks prove a block without txns (ie. dummy txns). There are no
+//! fixtures of real txns (eg. transfers or contract calls), since their IR can
+//! only be captured from a devnet.
+//!
+//! Building the circuits takes a while, so they are cached in the file at
+//! `$BENCH_CIRCUIT_CACHE` if it is set.
+
+use std::env;
+
+use criterion::{
+    criterion_group, criterion_main, measurement::WallTime, BenchmarkGroup, BenchmarkId, Criterion,
+};
+use eth_trie_utils::partial_trie::{HashedPartialTrie, Node, PartialTrie};
+use ethereum_types::{H256, U256};
+use plonky2_evm::proof::{BlockHashes, BlockMetadata, TrieRoots};
+use plonky_block_proof_gen::{
+    dummy::{create_dummy_txn_ir, generate_dummy_txn_proof_after},
+    options::ProofGenOptions,
+    proof_gen::{generate_agg_proof, generate_genesis_block_proof, generate_txn_proof},
+    proof_types::AggregatableProof,
+    prover_state::{ProverState, ProverStateBuilder},
+    types::TxnIdx,
+};
+use proof_protocol_decoder::types::{BlockLevelData, OtherBlockData, TxnProofGenIR};
+
+fn prover_state() -> ProverState {
+    match env::var_os("BENCH_CIRCUIT_CACHE") {
+        Some(path) => ProverStateBuilder::default()
+            .load(&path)
+            .unwrap_or_else(|_| {
+                let p_state = ProverStateBuilder::default().build();
+                p_state.save(&path).expect("Failed to cache the circuits");
+                p_state
+            }),
+        None => ProverStateBuilder::default().build(),
+    }
+}
+
+/// The data of block 1 of a chain with an empty genesis state. Block 0 can not
+/// be proven without a parent block proof.
+fn empty_block_data() -> OtherBlockData {
+    OtherBlockData {
+        b_data: BlockLevelData {
+            b_meta: BlockMetadata {
+                block_number: U256::one(),
+                ..Default::default()
+            },
+            b_hashes: BlockHashes {
+                prev_hashes: vec![H256::zero(); 256],
+                cur_hash: H256::zero(),
+            },
+            withdrawals: Vec::new(),
+        },
+        genesis_state_trie_root: HashedPartialTrie::new(Node::Empty).hash(),
+    }
+}
+
+/// Benchmark the txn proof of `txn`, its aggregation with a dummy txn proof and
+/// the block proof of the aggregation.
+fn bench_block(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    p_state: &ProverState,
+    name: &str,
+    txn: &TxnProofGenIR,
+    other_data: &OtherBlockData,
+) {
+    let opts = ProofGenOptions::default();
+
+    group.bench_with_input(BenchmarkId::new("txn", name), txn, |b, txn| {
+        b.iter(|| generate_txn_proof(p_state, txn.clone(), &opts).unwrap())
+    });
+
+    // A block with a single txn gets padded with a dummy txn.
+    let txn_proof = generate_txn_proof(p_state, txn.clone(), &opts).unwrap();
+    let dummy_proof =
+        generate_dummy_txn_proof_after(p_state, &txn_proof, other_data, &opts).unwrap();
+    let (lhs, rhs): (AggregatableProof, AggregatableProof) = (txn_proof.into(), dummy_proof.into());
+
+    group.bench_with_input(
+        BenchmarkId::new("agg", name),
+        other_data,
+        |b, other_data| {
+            b.iter(|| generate_agg_proof(p_state, &lhs, &rhs, other_data.clone(), &opts).unwrap())
+        },
+    );
+
+    // The block is proven as the first block of a chain, which plonky2 links
+    // to a dummy parent at the height before it.
+    let agg_proof = generate_agg_proof(p_state, &lhs, &rhs, other_data.clone(), &opts).unwrap();
+    let state_root_before = agg_proof.common.roots_before.state_root;
+
+    group.bench_with_input(
+        BenchmarkId::new("block", name),
+        other_data,
+        |b, other_data| {
+            b.iter(|| {
+                generate_genesis_block_proof(
+                    p_state,
+                    &agg_proof,
+                    other_data.clone(),
+                    state_root_before,
+                    &opts,
+                )
+                .unwrap()
+            })
+        },
+    );
+}
+
+fn bench_proving(c: &mut Criterion) {
+    let p_state = prover_state();
+
+    let mut group = c.benchmark_group("proving");
+    // A single proof takes seconds to minutes.
+    group.sample_size(10);
+
+    // An empty block consists of dummy txns, which start and end with the
+    // (empty) genesis state.
+    let other_data = empty_block_data();
+    let empty_roots = TrieRoots {
+        state_root: other_data.genesis_state_trie_root,
+        transactions_root: HashedPartialTrie::new(Node::Empty).hash(),
+        receipts_root: HashedPartialTrie::new(Node::Empty).hash(),
+    };
+    let dummy_txn = create_dummy_txn_ir(TxnIdx(0), &empty_roots, U256::zero(), &other_data);
+    bench_block(&mut group, &p_state, "empty_block", &dummy_txn, &other_data);
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_proving);
+criterion_main!(benches);
@@ -0,0 +1,5 @@
+fn main() {
+    #[cfg(feature = "service")]
+    tonic_build::compile_protos("proto/prover.proto")
+        .expect("Failed to compile the prover service protos");
+}
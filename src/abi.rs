@@ -0,0 +1,110 @@
+//! EVM ABI encodings of the public values of proofs, matching what an on-chain
+//! verifier decodes with `abi.decode`.
+
+use ethabi::Token;
+use ethereum_types::H256;
+use plonky2_evm::proof::{BlockHashes, BlockMetadata, ExtraBlockData, PublicValues, TrieRoots};
+
+use crate::proof_types::{GeneratedBlockProof, ProofCommon};
+
+impl ProofCommon {
+    /// ABI encode the values common to all proofs as
+    /// `(uint256 blockNumber, bytes32[3] rootsBefore, bytes32[3] rootsAfter,
+    /// uint256 gasUsedBefore, uint256 gasUsedAfter)`.
+    ///
+    /// Trie roots are always ordered as `(state, transactions, receipts)`.
+    pub fn abi_encode(&self) -> Vec<u8> {
+        ethabi::encode(&[
+            Token::Uint(self.b_height.into()),
+            trie_roots_token(&self.roots_before),
+            trie_roots_token(&self.roots_after),
+            Token::Uint(self.deltas.gas_used_before),
+            Token::Uint(self.deltas.gas_used_after),
+        ])
+    }
+}
+
+impl GeneratedBlockProof {
+    /// ABI encode the public values of the block proof as
+    /// `(bytes32[3] rootsBefore, bytes32[3] rootsAfter, BlockMetadata metadata,
+    /// BlockHashes hashes, ExtraBlockData extra)`, where
+    ///
+    /// - `BlockMetadata` is `(address beneficiary, uint256 timestamp, uint256
+    ///   number, uint256 difficulty, bytes32 random, uint256 gasLimit, uint256
+    ///   chainId, uint256 baseFee, uint256 gasUsed, uint256[8] bloom)`.
+    /// - `BlockHashes` is `(bytes32[] prevHashes, bytes32 curHash)`.
+    /// - `ExtraBlockData` is `(bytes32 genesisStateRoot, uint256
+    ///   txnNumberBefore, uint256 txnNumberAfter, uint256 gasUsedBefore,
+    ///   uint256 gasUsedAfter)`.
+    pub fn abi_encode_public_values(&self) -> Vec<u8> {
+        abi_encode_public_values(&self.public_values)
+    }
+}
+
+fn abi_encode_public_values(p_vals: &PublicValues) -> Vec<u8> {
+    ethabi::encode(&[
+        trie_roots_token(&p_vals.trie_roots_before),
+        trie_roots_token(&p_vals.trie_roots_after),
+        block_metadata_token(&p_vals.block_metadata),
+        block_hashes_token(&p_vals.block_hashes),
+        extra_block_data_token(&p_vals.extra_block_data),
+    ])
+}
+
+fn trie_roots_token(roots: &TrieRoots) -> Token {
+    Token::FixedArray(vec![
+        h256_token(roots.state_root),
+        h256_token(roots.transactions_root),
+        h256_token(roots.receipts_root),
+    ])
+}
+
+fn block_metadata_token(b_meta: &BlockMetadata) -> Token {
+    Token::Tuple(vec![
+        Token::Address(b_meta.block_beneficiary),
+        Token::Uint(b_meta.block_timestamp),
+        Token::Uint(b_meta.block_number),
+        Token::Uint(b_meta.block_difficulty),
+        h256_token(b_meta.block_random),
+        Token::Uint(b_meta.block_gaslimit),
+        Token::Uint(b_meta.block_chain_id),
+        Token::Uint(b_meta.block_base_fee),
+        Token::Uint(b_meta.block_gas_used),
+        Token::FixedArray(
+            b_meta
+                .block_bloom
+                .iter()
+                .copied()
+                .map(Token::Uint)
+                .collect(),
+        ),
+    ])
+}
+
+fn block_hashes_token(b_hashes: &BlockHashes) -> Token {
+    Token::Tuple(vec![
+        Token::Array(
+            b_hashes
+                .prev_hashes
+                .iter()
+                .copied()
+                .map(h256_token)
+                .collect(),
+        ),
+        h256_token(b_hashes.cur_hash),
+    ])
+}
+
+fn extra_block_data_token(extra: &ExtraBlockData) -> Token {
+    Token::Tuple(vec![
+        h256_token(extra.genesis_state_trie_root),
+        Token::Uint(extra.txn_number_before),
+        Token::Uint(extra.txn_number_after),
+        Token::Uint(extra.gas_used_before),
+        Token::Uint(extra.gas_used_after),
+    ])
+}
+
+fn h256_token(hash: H256) -> Token {
+    Token::FixedBytes(hash.as_bytes().to_vec())
+}
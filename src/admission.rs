@@ -0,0 +1,172 @@
+//! Admission control for the proving services.
+//!
+//! A prover can only generate a few proofs at a time, and every accepted
+//! request holds its (large) inputs in memory until it is done. Without a
+//! limit, a burst of requests queues up until the prover runs out of memory, so
+//! the services reject requests beyond a configurable limit with
+//! `ProofGenError::Overloaded` instead.
+//!
+//! Admission control is also where the services drain on shutdown:
+//! [`AdmissionControl::shutdown`] rejects new and queued requests with
+//! `ProofGenError::ShuttingDown` and waits for the in-flight proofs to finish.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+use crate::proof_gen::{ProofGenError, ProofGenResult};
+
+/// The limits enforced by [`AdmissionControl`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AdmissionLimits {
+    /// The max number of proofs that are generated at the same time.
+    pub max_in_flight: usize,
+
+    /// The max number of accepted requests waiting for a proof to finish.
+    pub max_queued: usize,
+
+    /// How long rejected requests are told to wait before retrying.
+    pub retry_after: Duration,
+}
+
+impl Default for AdmissionLimits {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 1,
+            max_queued: 16,
+            retry_after: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Limits the proofs that are in flight and queued.
+#[derive(Debug)]
+pub struct AdmissionControl {
+    limits: AdmissionLimits,
+    in_flight: Arc<Semaphore>,
+
+    accepted: Arc<Accepted>,
+}
+
+/// The accepted requests, ie. the in-flight and the queued ones.
+#[derive(Debug, Default)]
+struct Accepted {
+    count: AtomicUsize,
+
+    /// Notified whenever the last accepted request is done.
+    drained: Notify,
+}
+
+impl Accepted {
+    fn release(&self) {
+        if self.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.drained.notify_waiters();
+        }
+    }
+}
+
+/// Holds a slot for an in-flight proof until it is dropped.
+#[derive(Debug)]
+pub struct AdmissionPermit {
+    _in_flight: OwnedSemaphorePermit,
+    accepted: Arc<Accepted>,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        self.accepted.release();
+    }
+}
+
+/// Releases the slot of a request that gave up while waiting in the queue.
+struct QueuedGuard<'a>(&'a Accepted);
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+impl AdmissionControl {
+    pub fn new(limits: AdmissionLimits) -> Self {
+        Self {
+            limits,
+            in_flight: Arc::new(Semaphore::new(limits.max_in_flight.max(1))),
+            accepted: Arc::new(Accepted::default()),
+        }
+    }
+
+    pub fn limits(&self) -> AdmissionLimits {
+        self.limits
+    }
+
+    /// The number of requests that are in flight or queued.
+    pub fn num_accepted(&self) -> usize {
+        self.accepted.count.load(Ordering::SeqCst)
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.in_flight.is_closed()
+    }
+
+    /// Wait for a slot to generate a proof in, or fail immediately with
+    /// `ProofGenError::Overloaded` if the queue is full.
+    pub async fn admit(&self) -> ProofGenResult<AdmissionPermit> {
+        if self.is_shut_down() {
+            return Err(ProofGenError::ShuttingDown);
+        }
+
+        let max_accepted = self.limits.max_in_flight.max(1) + self.limits.max_queued;
+        self.accepted
+            .count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |accepted| {
+                (accepted < max_accepted).then_some(accepted + 1)
+            })
+            .map_err(|_| ProofGenError::Overloaded {
+                retry_after: self.limits.retry_after,
+            })?;
+
+        // Dropping the future while queued (eg. because the client went away)
+        // has to give the slot back as well.
+        let queued = QueuedGuard(&self.accepted);
+        let in_flight = self
+            .in_flight
+            .clone()
+            .acquire_owned()
+            .await
+            // The semaphore is only closed on shutdown.
+            .map_err(|_| ProofGenError::ShuttingDown)?;
+        std::mem::forget(queued);
+
+        Ok(AdmissionPermit {
+            _in_flight: in_flight,
+            accepted: self.accepted.clone(),
+        })
+    }
+
+    /// Stop admitting requests and wait for the in-flight proofs to finish.
+    ///
+    /// New requests, and the ones waiting in the queue, fail with
+    /// `ProofGenError::ShuttingDown`, so that clients can retry them on
+    /// another prover right away. The proofs that are already being generated
+    /// are finished, so that a rolling deploy does not throw them away.
+    pub async fn shutdown(&self) {
+        self.in_flight.close();
+
+        loop {
+            // Register interest before checking, so that a release in between
+            // is not missed.
+            let drained = self.accepted.drained.notified();
+            if self.num_accepted() == 0 {
+                return;
+            }
+            drained.await;
+        }
+    }
+}
@@ -0,0 +1,581 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use proof_protocol_decoder::types::OtherBlockData;
+use rayon::{iter::ParallelIterator, slice::ParallelSlice};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::BlockProverBackend,
+    dummy::generate_dummy_txn_proof_after,
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_key::ProofKey,
+    proof_store::{ProofStore, ProofStoreExt},
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedTxnProof},
+    serialization::ProofKind,
+    types::TxnIdx,
+};
+
+/// The shape of the aggregation tree built over a set of proofs.
+///
+/// Every strategy performs the same number of aggregations (one less than the
+/// number of proofs), but they differ in how much of that work can run in
+/// parallel and in how early partial results become available.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum AggregationStrategy {
+    /// A balanced binary tree. Subtrees are aggregated in parallel, giving the
+    /// lowest latency when all proofs are available upfront.
+    #[default]
+    Balanced,
+
+    /// Folds the proofs one at a time from left to right. Runs sequentially,
+    /// but the left-most partial result grows one txn at a time.
+    LeftLeaning,
+
+    /// Pairs up adjacent proofs level by level (in parallel), carrying an
+    /// unpaired proof up to the next level.
+    GreedyAsAvailable,
+}
+
+/// Aggregate `proofs` into a single agg proof.
+///
+/// The proofs are combined with a balanced binary aggregation tree, so the
+/// depth of the tree is `log2(proofs.len())`. Independent subtrees (including
+/// every pair of leaves) are aggregated in parallel.
+///
+/// `proofs` must be in txn order. If only a single txn proof is given, it is
+/// aggregated with a dummy txn proof.
+pub fn aggregate_proofs<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    proofs: Vec<AggregatableProof>,
+    other_data: &OtherBlockData,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedAggProof> {
+    aggregate_proofs_with_strategy(
+        p_state,
+        proofs,
+        other_data,
+        AggregationStrategy::Balanced,
+        opts,
+    )
+}
+
+/// Same as [`aggregate_proofs`], but builds the aggregation tree with the given
+/// strategy.
+pub fn aggregate_proofs_with_strategy<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    proofs: Vec<AggregatableProof>,
+    other_data: &OtherBlockData,
+    strategy: AggregationStrategy,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedAggProof> {
+    if proofs.is_empty() {
+        return Err(ProofGenError::InvalidInput(
+            "Can not aggregate an empty set of proofs".to_string(),
+        ));
+    }
+
+    let root = match strategy {
+        AggregationStrategy::Balanced => aggregate_subtree(p_state, proofs, other_data, opts),
+        AggregationStrategy::LeftLeaning => {
+            aggregate_left_to_right(p_state, proofs, other_data, opts)
+        }
+        AggregationStrategy::GreedyAsAvailable => {
+            aggregate_levels(p_state, proofs, other_data, opts)
+        }
+    }?;
+
+    match root {
+        AggregatableProof::Agg(agg_proof) => Ok(agg_proof),
+        AggregatableProof::Txn(txn_proof) => {
+            let dummy = generate_dummy_txn_proof_after(p_state, &txn_proof, other_data, opts)?;
+
+            p_state.generate_agg_proof(&txn_proof.into(), &dummy.into(), other_data.clone(), opts)
+        }
+    }
+}
+
+/// Recursively aggregates each half of `proofs` and then the two resulting
+/// subtrees. Assumes that `proofs` is non-empty.
+fn aggregate_subtree<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    mut proofs: Vec<AggregatableProof>,
+    other_data: &OtherBlockData,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<AggregatableProof> {
+    if proofs.len() == 1 {
+        return Ok(proofs.remove(0));
+    }
+
+    let rhs_proofs = proofs.split_off(proofs.len() / 2);
+    let (lhs, rhs) = rayon::join(
+        || aggregate_subtree(p_state, proofs, other_data, opts),
+        || aggregate_subtree(p_state, rhs_proofs, other_data, opts),
+    );
+
+    p_state
+        .generate_agg_proof(&lhs?, &rhs?, other_data.clone(), opts)
+        .map(Into::into)
+}
+
+/// Folds `proofs` into the proof on their left, one at a time. Assumes that
+/// `proofs` is non-empty.
+fn aggregate_left_to_right<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    proofs: Vec<AggregatableProof>,
+    other_data: &OtherBlockData,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<AggregatableProof> {
+    let mut proofs = proofs.into_iter();
+    let mut acc = proofs.next().expect("Checked that proofs are non-empty");
+
+    for rhs in proofs {
+        acc = p_state
+            .generate_agg_proof(&acc, &rhs, other_data.clone(), opts)?
+            .into();
+    }
+
+    Ok(acc)
+}
+
+/// Aggregates adjacent pairs of `proofs` in parallel until a single proof
+/// remains. Assumes that `proofs` is non-empty.
+fn aggregate_levels<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    mut proofs: Vec<AggregatableProof>,
+    other_data: &OtherBlockData,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<AggregatableProof> {
+    while proofs.len() > 1 {
+        let unpaired = match proofs.len() % 2 {
+            0 => None,
+            _ => proofs.pop(),
+        };
+
+        let mut next_level = proofs
+            .par_chunks(2)
+            .map(|pair| {
+                p_state
+                    .generate_agg_proof(&pair[0], &pair[1], other_data.clone(), opts)
+                    .map(AggregatableProof::from)
+            })
+            .collect::<ProofGenResult<Vec<_>>>()?;
+        next_level.extend(unpaired);
+
+        proofs = next_level;
+    }
+
+    Ok(proofs.remove(0))
+}
+
+/// Aggregates proofs as they become available, in any order.
+///
+/// Every pushed proof is immediately aggregated with the partial results that
+/// it is adjacent to, so at any point the aggregator only holds one proof per
+/// contiguous txn range that it has seen so far. Once the partial results
+/// cover all of the txns of the block, the final agg proof is returned.
+///
+/// This is the natural shape for a distributed prover whose workers send their
+/// txn proofs back over a channel. Workers that each aggregate a part of the
+/// txns (see [`StreamingAggregator::new_partial`]) can be combined with
+/// [`StreamingAggregator::merge`].
+pub struct StreamingAggregator<'a, B: BlockProverBackend + ?Sized> {
+    p_state: &'a B,
+    other_data: OtherBlockData,
+    opts: ProofGenOptions,
+    txn_range: Range<TxnIdx>,
+
+    /// All of the txns of the block (ie. `0..num_txns`). Only a partial result
+    /// covering all of them becomes the final proof.
+    block_txns: Range<TxnIdx>,
+
+    /// The partial results by the first txn that they cover.
+    partials: BTreeMap<TxnIdx, AggregatableProof>,
+}
+
+impl<'a, B: BlockProverBackend + ?Sized> StreamingAggregator<'a, B> {
+    /// Aggregate the proofs of the txns in `txn_range`, which must be all of
+    /// the txns of the block (ie. `0..num_txns`). Use
+    /// [`StreamingAggregator::new_partial`] to aggregate only a part of them.
+    pub fn new(
+        p_state: &'a B,
+        txn_range: Range<TxnIdx>,
+        other_data: OtherBlockData,
+        opts: ProofGenOptions,
+    ) -> Self {
+        Self {
+            p_state,
+            other_data,
+            opts,
+            block_txns: txn_range.clone(),
+            txn_range,
+            partials: BTreeMap::new(),
+        }
+    }
+
+    /// Aggregate the proofs of the txns in `txn_range`, which is a part of the
+    /// `num_txns` txns of the block.
+    ///
+    /// Once every txn in the range is covered, the aggregator keeps the proof
+    /// of the range (instead of returning a final proof), so that it can be
+    /// merged with the aggregators of the other txns of the block with
+    /// [`StreamingAggregator::merge`]. The final proof is only returned once
+    /// the merged aggregators cover all of the txns.
+    pub fn new_partial(
+        p_state: &'a B,
+        txn_range: Range<TxnIdx>,
+        num_txns: usize,
+        other_data: OtherBlockData,
+        opts: ProofGenOptions,
+    ) -> ProofGenResult<Self> {
+        if txn_range.is_empty() || txn_range.end > TxnIdx(num_txns) {
+            return Err(ProofGenError::InvalidInput(format!(
+                "Txns {}..{} are not a part of the {} txns of the block",
+                txn_range.start, txn_range.end, num_txns
+            )));
+        }
+
+        Ok(Self {
+            block_txns: TxnIdx(0)..TxnIdx(num_txns),
+            ..Self::new(p_state, txn_range, other_data, opts)
+        })
+    }
+
+    /// Add `proof` to the aggregation. Returns the final agg proof once every
+    /// txn of the block has been covered.
+    ///
+    /// Dummy txn proofs can not be pushed, since a lone txn gets aggregated
+    /// with a dummy automatically. If an aggregation fails, every partial
+    /// result is kept, but `proof` may have to be pushed again (see
+    /// [`StreamingAggregator::missing_ranges`]).
+    ///
+    /// Proofs of txns that are already covered by a partial result (eg.
+    /// because two workers raced to prove the same txn) are dropped without
+    /// aggregating them again.
+    pub fn push(&mut self, proof: AggregatableProof) -> ProofGenResult<Option<GeneratedAggProof>> {
+        let range = proof.txn_range();
+        if range.is_empty() {
+            return Err(ProofGenError::InvalidInput(
+                "Can not stream dummy txn proofs into an aggregator".to_string(),
+            ));
+        }
+
+        if range.start < self.txn_range.start || range.end > self.txn_range.end {
+            return Err(ProofGenError::InvalidInput(format!(
+                "Proof of txns {}..{} is outside of the aggregated txns {}..{}",
+                range.start, range.end, self.txn_range.start, self.txn_range.end
+            )));
+        }
+
+        let prev_range = self
+            .partials
+            .range(..range.end)
+            .next_back()
+            .map(|(_, prev)| prev.txn_range())
+            .filter(|prev_range| prev_range.end > range.start);
+        if let Some(prev_range) = prev_range {
+            if prev_range.start <= range.start && range.end <= prev_range.end {
+                return Ok(None);
+            }
+
+            return Err(ProofGenError::InvalidInput(format!(
+                "Proof of txns {}..{} overlaps with an already aggregated proof",
+                range.start, range.end
+            )));
+        }
+
+        let mut acc = proof;
+
+        // The partial result directly before the proof (if there is one).
+        let lhs_start = self
+            .partials
+            .range(..range.start)
+            .next_back()
+            .filter(|(_, prev)| prev.txn_range().end == range.start)
+            .map(|(start, _)| *start);
+        if let Some(lhs_start) = lhs_start {
+            let lhs = &self.partials[&lhs_start];
+            acc = self.aggregate(lhs, &acc)?;
+            self.partials.remove(&lhs_start);
+        }
+
+        // The partial result directly after the proof (if there is one).
+        if let Some(rhs) = self.partials.get(&range.end) {
+            match self.aggregate(&acc, rhs) {
+                Ok(combined) => {
+                    self.partials.remove(&range.end);
+                    acc = combined;
+                }
+                Err(err) => {
+                    // Keep the aggregation with the lhs that did succeed.
+                    if lhs_start.is_some() {
+                        self.partials.insert(acc.txn_range().start, acc);
+                    }
+
+                    return Err(err);
+                }
+            }
+        }
+
+        self.add_partial(acc)
+    }
+
+    /// Merge the partial results of `other`, which must aggregate the txns
+    /// directly before or after the txns of this aggregator, into this
+    /// aggregator. Afterwards, this aggregator aggregates the txns of both.
+    ///
+    /// This combines the work of two workers that each aggregated a part of
+    /// the txns of a block. Returns the final agg proof if the merged partial
+    /// results cover all of the txns.
+    pub fn merge(
+        &mut self,
+        other: StreamingAggregator<'_, B>,
+    ) -> ProofGenResult<Option<GeneratedAggProof>> {
+        if self.is_complete() || other.is_complete() {
+            return Err(ProofGenError::InvalidInput(
+                "Can not merge an aggregator that already returned its final proof".to_string(),
+            ));
+        }
+
+        if self.block_txns != other.block_txns {
+            return Err(ProofGenError::InvalidInput(format!(
+                "Can not merge the aggregation of a block with {} txns with one of a block with \
+                 {} txns",
+                self.block_txns.end, other.block_txns.end
+            )));
+        }
+
+        let boundary = match (
+            self.txn_range.end == other.txn_range.start,
+            other.txn_range.end == self.txn_range.start,
+        ) {
+            (true, _) => self.txn_range.end,
+            (_, true) => self.txn_range.start,
+            _ => {
+                return Err(ProofGenError::InvalidInput(format!(
+                    "Can not merge the aggregation of txns {}..{} with the non-adjacent txns \
+                     {}..{}",
+                    self.txn_range.start,
+                    self.txn_range.end,
+                    other.txn_range.start,
+                    other.txn_range.end
+                )))
+            }
+        };
+
+        self.txn_range = self.txn_range.start.min(other.txn_range.start)
+            ..self.txn_range.end.max(other.txn_range.end);
+        self.partials.extend(other.partials);
+
+        // Each aggregator already combined its own adjacent partial results, so
+        // only the partial results meeting at the boundary are left to combine.
+        let lhs_start = self
+            .partials
+            .range(..boundary)
+            .next_back()
+            .filter(|(_, lhs)| lhs.txn_range().end == boundary)
+            .map(|(start, _)| *start);
+        let acc = match (lhs_start, self.partials.get(&boundary)) {
+            (Some(lhs_start), Some(rhs)) => {
+                let acc = self.aggregate(&self.partials[&lhs_start], rhs)?;
+                self.partials.remove(&lhs_start);
+                self.partials.remove(&boundary);
+                acc
+            }
+            _ => return Ok(None),
+        };
+
+        self.add_partial(acc)
+    }
+
+    /// Persist the partial results in `store`, so that the aggregation can be
+    /// continued with [`StreamingAggregator::restore`] (eg. after draining and
+    /// restarting a prover node).
+    ///
+    /// The returned snapshot only holds the keys of the partial results and is
+    /// small enough to be stored anywhere.
+    pub fn snapshot(&self, store: &dyn ProofStore) -> ProofGenResult<AggregatorSnapshot> {
+        let digests = self.p_state.circuit_digests();
+        let partials = self
+            .partials
+            .values()
+            .map(|partial| match partial {
+                AggregatableProof::Txn(txn_proof) => store.put_proof(txn_proof, &digests.root),
+                AggregatableProof::Agg(agg_proof) => {
+                    store.put_proof(agg_proof, &digests.aggregation)
+                }
+            })
+            .collect::<ProofGenResult<_>>()?;
+
+        Ok(AggregatorSnapshot {
+            txn_range: self.txn_range.clone(),
+            block_txns: Some(self.block_txns.clone()),
+            partials,
+        })
+    }
+
+    /// Continue the aggregation that `snapshot` was taken of, loading its
+    /// partial results from `store`.
+    pub fn restore(
+        p_state: &'a B,
+        snapshot: &AggregatorSnapshot,
+        store: &dyn ProofStore,
+        other_data: OtherBlockData,
+        opts: ProofGenOptions,
+    ) -> ProofGenResult<Self> {
+        let mut aggregator = Self::new(p_state, snapshot.txn_range.clone(), other_data, opts);
+        if let Some(block_txns) = &snapshot.block_txns {
+            aggregator.block_txns = block_txns.clone();
+        }
+
+        for key in &snapshot.partials {
+            let partial: Option<AggregatableProof> = match key.kind {
+                ProofKind::Txn => store.get_proof::<GeneratedTxnProof>(key)?.map(Into::into),
+                ProofKind::Agg => store.get_proof::<GeneratedAggProof>(key)?.map(Into::into),
+                ProofKind::Block => {
+                    return Err(ProofGenError::IncompatibleProof(format!(
+                        "Snapshot of an aggregator contains the key of the {}",
+                        key
+                    )))
+                }
+            };
+            let partial = partial.ok_or_else(|| {
+                ProofGenError::Storage(format!("The {} is missing from the store", key))
+            })?;
+
+            aggregator
+                .partials
+                .insert(partial.txn_range().start, partial);
+        }
+
+        Ok(aggregator)
+    }
+
+    /// Whether every txn has been covered (and the final proof was returned).
+    pub fn is_complete(&self) -> bool {
+        self.partials.is_empty() && self.txn_range.is_empty()
+    }
+
+    /// Whether a single partial result covers every txn in the range, ie.
+    /// whether a partial aggregator (see [`StreamingAggregator::new_partial`])
+    /// is ready to be merged.
+    pub fn is_covered(&self) -> bool {
+        self.partials
+            .get(&self.txn_range.start)
+            .is_some_and(|root| root.txn_range() == self.txn_range)
+    }
+
+    /// The txn ranges that no pushed proof has covered yet.
+    pub fn missing_ranges(&self) -> Vec<Range<TxnIdx>> {
+        let mut missing = Vec::new();
+        let mut next = self.txn_range.start;
+        for proof in self.partials.values() {
+            let range = proof.txn_range();
+            if range.start > next {
+                missing.push(next..range.start);
+            }
+            next = range.end;
+        }
+
+        if next < self.txn_range.end {
+            missing.push(next..self.txn_range.end);
+        }
+
+        missing
+    }
+
+    fn aggregate(
+        &self,
+        lhs: &AggregatableProof,
+        rhs: &AggregatableProof,
+    ) -> ProofGenResult<AggregatableProof> {
+        self.p_state
+            .generate_agg_proof(lhs, rhs, self.other_data.clone(), &self.opts)
+            .map(Into::into)
+    }
+
+    /// Retry turning the proof that covers every txn into the final proof,
+    /// after a previous attempt (from [`StreamingAggregator::push`] or
+    /// [`StreamingAggregator::merge`]) failed. Returns `None` if no proof
+    /// covers every txn yet.
+    ///
+    /// This only has to prove something if the block has a single txn, whose
+    /// proof gets aggregated with a dummy txn proof.
+    pub fn retry_finish(&mut self) -> ProofGenResult<Option<GeneratedAggProof>> {
+        match self.txn_range == self.block_txns && self.is_covered() {
+            true => {
+                let root = self.partials.remove(&self.txn_range.start).unwrap();
+                self.finish(root).map(Some)
+            }
+            false => Ok(None),
+        }
+    }
+
+    /// Keep `acc` as a partial result, or turn it into the final proof if it
+    /// covers every txn of the block.
+    fn add_partial(&mut self, acc: AggregatableProof) -> ProofGenResult<Option<GeneratedAggProof>> {
+        match acc.txn_range() == self.block_txns {
+            true => self.finish(acc).map(Some),
+            false => {
+                self.partials.insert(acc.txn_range().start, acc);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Turn `root`, which covers every txn, into the final proof. If that
+    /// fails, `root` is kept so that it can be retried with
+    /// [`StreamingAggregator::retry_finish`].
+    fn finish(&mut self, root: AggregatableProof) -> ProofGenResult<GeneratedAggProof> {
+        // Only a lone txn proof still has to be aggregated (with a dummy).
+        let aggregated = match &root {
+            AggregatableProof::Agg(_) => None,
+            AggregatableProof::Txn(txn_proof) => Some(
+                generate_dummy_txn_proof_after(
+                    self.p_state,
+                    txn_proof,
+                    &self.other_data,
+                    &self.opts,
+                )
+                .and_then(|dummy| {
+                    self.p_state.generate_agg_proof(
+                        &root,
+                        &dummy.into(),
+                        self.other_data.clone(),
+                        &self.opts,
+                    )
+                }),
+            ),
+        };
+
+        let final_proof = match (aggregated, root) {
+            (None, AggregatableProof::Agg(agg_proof)) => agg_proof,
+            (None, AggregatableProof::Txn(_)) => unreachable!("Txn proofs get aggregated"),
+            (Some(Ok(agg_proof)), _) => agg_proof,
+            (Some(Err(err)), root) => {
+                self.partials.insert(root.txn_range().start, root);
+                return Err(err);
+            }
+        };
+
+        // Nothing is left to aggregate.
+        self.txn_range = self.txn_range.end..self.txn_range.end;
+
+        Ok(final_proof)
+    }
+}
+
+/// The state of a [`StreamingAggregator`], taken with
+/// [`StreamingAggregator::snapshot`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AggregatorSnapshot {
+    pub txn_range: Range<TxnIdx>,
+
+    /// All of the txns of the block, if the aggregator only aggregates a part
+    /// of them. `None` in snapshots taken before partial aggregators existed.
+    #[serde(default)]
+    pub block_txns: Option<Range<TxnIdx>>,
+
+    /// The keys of the partial results in the proof store.
+    pub partials: Vec<ProofKey>,
+}
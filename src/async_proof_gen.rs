@@ -0,0 +1,68 @@
+//! Async wrappers around the proof generation functions in
+//! [`proof_gen`](crate::proof_gen).
+//!
+//! Proving is CPU-bound and blocks for a long time, so each call is moved onto
+//! tokio's blocking thread pool instead of running on the async executor.
+
+use std::sync::Arc;
+
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+use tokio::task::{self, JoinError};
+
+use crate::{
+    options::ProofGenOptions,
+    proof_gen::{
+        generate_agg_proof, generate_block_proof, generate_txn_proof, ProofGenError, ProofGenResult,
+    },
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    prover_state::ProverState,
+};
+
+impl From<JoinError> for ProofGenError {
+    fn from(v: JoinError) -> Self {
+        Self::TaskFailed(v.to_string())
+    }
+}
+
+/// Async version of [`generate_txn_proof`].
+pub async fn generate_txn_proof_async(
+    p_state: Arc<ProverState>,
+    start_info: TxnProofGenIR,
+    opts: ProofGenOptions,
+) -> ProofGenResult<GeneratedTxnProof> {
+    task::spawn_blocking(move || generate_txn_proof(&p_state, start_info, &opts)).await?
+}
+
+/// Async version of [`generate_agg_proof`].
+pub async fn generate_agg_proof_async(
+    p_state: Arc<ProverState>,
+    lhs_child: AggregatableProof,
+    rhs_child: AggregatableProof,
+    other_data: OtherBlockData,
+    opts: ProofGenOptions,
+) -> ProofGenResult<GeneratedAggProof> {
+    task::spawn_blocking(move || {
+        generate_agg_proof(&p_state, &lhs_child, &rhs_child, other_data, &opts)
+    })
+    .await?
+}
+
+/// Async version of [`generate_block_proof`].
+pub async fn generate_block_proof_async(
+    p_state: Arc<ProverState>,
+    prev_opt_parent_b_proof: Option<GeneratedBlockProof>,
+    curr_block_agg_proof: GeneratedAggProof,
+    other_data: OtherBlockData,
+    opts: ProofGenOptions,
+) -> ProofGenResult<GeneratedBlockProof> {
+    task::spawn_blocking(move || {
+        generate_block_proof(
+            &p_state,
+            prev_opt_parent_b_proof.as_ref(),
+            &curr_block_agg_proof,
+            other_data,
+            &opts,
+        )
+    })
+    .await?
+}
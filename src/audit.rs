@@ -0,0 +1,277 @@
+//! An append-only audit log of every generated proof.
+//!
+//! [`AuditedBackend`] wraps a [`BlockProverBackend`] and appends an
+//! [`AuditRecord`] to an [`AuditLog`] for every proof that it generates (or
+//! fails to generate), so that which prover produced which proof, and when,
+//! can be answered without scraping the text logs. [`JsonLinesAuditLog`]
+//! appends the records to a file as JSON lines.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::BlockProverBackend,
+    options::ProofGenOptions,
+    profile::ProverProfile,
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_key::ProofKey,
+    proof_store::StorableProof,
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    serialization::proof_hash,
+    types::{circuit_digest_hex, CircuitDigests},
+};
+
+/// The outcome of a proof generation.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum AuditOutcome {
+    Success,
+    Failure { error: String },
+}
+
+/// An entry of the audit log.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditRecord {
+    /// When the proof generation finished, in milliseconds since the Unix
+    /// epoch.
+    pub timestamp_ms: u64,
+
+    /// Identifies the prover that generated the proof (see
+    /// [`AuditedBackend::new`]).
+    pub prover_id: String,
+
+    /// The profile that the prover was built with, if it was built from one.
+    pub profile: Option<ProverProfile>,
+
+    /// The key of the generated (or attempted) proof.
+    pub key: ProofKey,
+
+    /// The digest of the circuit of the proof, as hex.
+    pub circuit_digest: String,
+
+    pub duration_ms: u64,
+
+    /// The hash of the proof in the versioned binary proof format (see
+    /// [`proof_hash`]), as hex. Only set for successful proofs.
+    pub proof_hash: Option<String>,
+
+    pub outcome: AuditOutcome,
+}
+
+/// Somewhere to append [`AuditRecord`]s to.
+pub trait AuditLog: Send + Sync {
+    /// Durably append `record` to the log.
+    fn append(&self, record: &AuditRecord) -> ProofGenResult<()>;
+}
+
+/// An [`AuditLog`] that appends every record as a line of JSON to a file.
+#[derive(Debug)]
+pub struct JsonLinesAuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl JsonLinesAuditLog {
+    /// Open the log at `path` for appending, creating it if it does not exist
+    /// yet.
+    pub fn open(path: impl AsRef<Path>) -> ProofGenResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| audit_err(&path, err))?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl AuditLog for JsonLinesAuditLog {
+    fn append(&self, record: &AuditRecord) -> ProofGenResult<()> {
+        let mut line = serde_json::to_vec(record)
+            .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+        line.push(b'\n');
+
+        // A single write per record keeps records from interleaving.
+        let mut file = self.file.lock().unwrap();
+        file.write_all(&line)
+            .and_then(|()| file.sync_data())
+            .map_err(|err| audit_err(&self.path, err))
+    }
+}
+
+/// A [`BlockProverBackend`] that records every proof generated by `backend`
+/// in an audit log.
+///
+/// If a record can not be appended, the proof generation fails with the error
+/// of the log, so that no proof leaves the prover without being audited.
+pub struct AuditedBackend<B, L> {
+    backend: B,
+    log: L,
+    prover_id: String,
+    profile: Option<ProverProfile>,
+}
+
+impl<B: BlockProverBackend, L: AuditLog> AuditedBackend<B, L> {
+    /// Record the proofs of `backend` in `log`, under `prover_id` (eg. the
+    /// host name or the deployment of the prover).
+    pub fn new(backend: B, log: L, prover_id: impl Into<String>) -> Self {
+        Self {
+            backend,
+            log,
+            prover_id: prover_id.into(),
+            profile: None,
+        }
+    }
+
+    /// Record that the prover was built with `profile`.
+    pub fn set_profile(mut self, profile: ProverProfile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Generate a proof with `generate` and append a record of it under
+    /// `key`.
+    fn audited<P: StorableProof>(
+        &self,
+        key: ProofKey,
+        generate: impl FnOnce() -> ProofGenResult<P>,
+    ) -> ProofGenResult<P> {
+        let started = Instant::now();
+        let result = generate();
+        let duration_ms = started.elapsed().as_millis() as u64;
+
+        let (proof_hash, outcome) = match &result {
+            Ok(proof) => {
+                let bytes = proof.to_bytes(&key.circuit_digest)?;
+                (
+                    Some(format!("{:x}", proof_hash(&bytes)?)),
+                    AuditOutcome::Success,
+                )
+            }
+            Err(err) => (
+                None,
+                AuditOutcome::Failure {
+                    error: err.to_string(),
+                },
+            ),
+        };
+
+        self.log.append(&AuditRecord {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_millis() as u64)
+                .unwrap_or_default(),
+            prover_id: self.prover_id.clone(),
+            profile: self.profile,
+            circuit_digest: circuit_digest_hex(&key.circuit_digest),
+            key,
+            duration_ms,
+            proof_hash,
+            outcome,
+        })?;
+
+        result
+    }
+}
+
+impl<B: BlockProverBackend, L: AuditLog> BlockProverBackend for AuditedBackend<B, L> {
+    fn generate_txn_proof(
+        &self,
+        start_info: TxnProofGenIR,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedTxnProof> {
+        let key = ProofKey::for_txn_ir(&start_info, self.circuit_digests().root);
+
+        self.audited(key, || self.backend.generate_txn_proof(start_info, opts))
+    }
+
+    fn generate_agg_proof(
+        &self,
+        lhs_child: &AggregatableProof,
+        rhs_child: &AggregatableProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedAggProof> {
+        let txns = lhs_child
+            .underlying_txns()
+            .combine(&rhs_child.underlying_txns());
+        let key = ProofKey::for_agg(
+            lhs_child.b_height(),
+            txns,
+            self.circuit_digests().aggregation,
+        );
+
+        self.audited(key, || {
+            self.backend
+                .generate_agg_proof(lhs_child, rhs_child, other_data, opts)
+        })
+    }
+
+    fn generate_block_proof(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: &GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        let key = ProofKey::for_block(
+            curr_block_agg_proof.common.b_height,
+            self.circuit_digests().block,
+        );
+
+        self.audited(key, || {
+            self.backend.generate_block_proof(
+                prev_opt_parent_b_proof,
+                curr_block_agg_proof,
+                other_data,
+                opts,
+            )
+        })
+    }
+
+    fn generate_block_proof_from_owned(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        let key = ProofKey::for_block(
+            curr_block_agg_proof.common.b_height,
+            self.circuit_digests().block,
+        );
+
+        self.audited(key, || {
+            self.backend.generate_block_proof_from_owned(
+                prev_opt_parent_b_proof,
+                curr_block_agg_proof,
+                other_data,
+                opts,
+            )
+        })
+    }
+
+    fn circuit_digests(&self) -> CircuitDigests {
+        self.backend.circuit_digests()
+    }
+}
+
+fn audit_err(path: &Path, err: std::io::Error) -> ProofGenError {
+    ProofGenError::Storage(format!("Audit log {}: {}", path.display(), err))
+}
@@ -0,0 +1,121 @@
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+
+use crate::{
+    options::ProofGenOptions,
+    proof_gen::{self, ProofGenResult},
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    prover_state::ProverState,
+    types::CircuitDigests,
+};
+
+/// The proving system behind the orchestration helpers (aggregation, the
+/// pipeline, chain proofs, ...).
+///
+/// [`ProverState`] (ie. plonky2_evm) is the default backend. Other backends
+/// must produce the same `Generated*Proof` types, so that they can be swapped
+/// in without changing any code downstream.
+pub trait BlockProverBackend: Send + Sync {
+    /// See [`generate_txn_proof`](crate::proof_gen::generate_txn_proof).
+    fn generate_txn_proof(
+        &self,
+        start_info: TxnProofGenIR,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedTxnProof>;
+
+    /// See [`generate_agg_proof`](crate::proof_gen::generate_agg_proof).
+    fn generate_agg_proof(
+        &self,
+        lhs_child: &AggregatableProof,
+        rhs_child: &AggregatableProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedAggProof>;
+
+    /// See [`generate_block_proof`](crate::proof_gen::generate_block_proof).
+    fn generate_block_proof(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: &GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof>;
+
+    /// See
+    /// [`generate_block_proof_from_owned`](crate::proof_gen::generate_block_proof_from_owned).
+    ///
+    /// Defaults to [`BlockProverBackend::generate_block_proof`] with a
+    /// reference to `curr_block_agg_proof`.
+    fn generate_block_proof_from_owned(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        self.generate_block_proof(
+            prev_opt_parent_b_proof,
+            &curr_block_agg_proof,
+            other_data,
+            opts,
+        )
+    }
+
+    /// The digests of the circuits that the generated proofs are proofs of.
+    fn circuit_digests(&self) -> CircuitDigests;
+}
+
+impl BlockProverBackend for ProverState {
+    fn generate_txn_proof(
+        &self,
+        start_info: TxnProofGenIR,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedTxnProof> {
+        proof_gen::generate_txn_proof(self, start_info, opts)
+    }
+
+    fn generate_agg_proof(
+        &self,
+        lhs_child: &AggregatableProof,
+        rhs_child: &AggregatableProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedAggProof> {
+        proof_gen::generate_agg_proof(self, lhs_child, rhs_child, other_data, opts)
+    }
+
+    fn generate_block_proof(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: &GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        proof_gen::generate_block_proof(
+            self,
+            prev_opt_parent_b_proof,
+            curr_block_agg_proof,
+            other_data,
+            opts,
+        )
+    }
+
+    fn generate_block_proof_from_owned(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        proof_gen::generate_block_proof_from_owned(
+            self,
+            prev_opt_parent_b_proof,
+            curr_block_agg_proof,
+            other_data,
+            opts,
+        )
+    }
+
+    fn circuit_digests(&self) -> CircuitDigests {
+        ProverState::circuit_digests(self)
+    }
+}
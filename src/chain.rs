@@ -0,0 +1,79 @@
+use std::borrow::Cow;
+
+use proof_protocol_decoder::types::OtherBlockData;
+
+use crate::{
+    backend::BlockProverBackend,
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_types::{GeneratedAggProof, GeneratedBlockProof, GeneratedChainProof},
+    prover_state::ProverState,
+};
+
+/// Generate a proof for a range of consecutive blocks.
+///
+/// Every block proof verifies the proof of its parent block, so each block in
+/// `blocks` gets proven on top of the block before it and the final block
+/// proof attests to the entire range. A settlement layer only needs to verify
+/// that single proof.
+///
+/// The first block is proven on top of `prev_opt_parent_b_proof`, which (like
+/// with [`generate_block_proof`](crate::proof_gen::generate_block_proof)) is
+/// able to be `None` on checkpoint heights.
+pub fn generate_chain_proof<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+    blocks: Vec<(GeneratedAggProof, OtherBlockData)>,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedChainProof> {
+    let start_b_height = match blocks.first() {
+        Some((agg_proof, _)) => agg_proof.common.b_height,
+        None => {
+            return Err(ProofGenError::InvalidInput(
+                "A chain proof requires at least one block".to_string(),
+            ))
+        }
+    };
+
+    // Block proofs are large, so the initial parent is only borrowed.
+    let mut parent = prev_opt_parent_b_proof.map(Cow::Borrowed);
+    for (agg_proof, other_data) in blocks {
+        if let Some(parent) = &parent {
+            if agg_proof.common.b_height != parent.b_height + 1 {
+                return Err(ProofGenError::InvalidInput(format!(
+                    "Block {} does not directly follow block {}",
+                    agg_proof.common.b_height, parent.b_height
+                )));
+            }
+        }
+
+        let b_proof = p_state.generate_block_proof_from_owned(
+            parent.as_deref(),
+            agg_proof,
+            other_data,
+            opts,
+        )?;
+        parent = Some(Cow::Owned(b_proof));
+    }
+
+    // We checked above that there is at least one block, so the last parent
+    // was proven here.
+    let last_b_proof = parent.unwrap().into_owned();
+
+    Ok(GeneratedChainProof {
+        start_b_height,
+        end_b_height: last_b_proof.b_height,
+        intern: last_b_proof.intern,
+    })
+}
+
+/// Verify a chain proof against the block circuit of the prover state.
+pub fn verify_chain_proof(
+    p_state: &ProverState,
+    proof: &GeneratedChainProof,
+) -> ProofGenResult<()> {
+    p_state
+        .state
+        .verify_block(&proof.intern)
+        .map_err(|err| ProofGenError::Verification(format!("{:#}", err)))
+}
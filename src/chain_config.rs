@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use ethereum_types::U256;
+use plonky2_evm::proof::BlockMetadata;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    proof_gen::{ProofGenError, ProofGenResult},
+    types::{BlockHeight, HardFork},
+};
+
+/// How the base fee of a block is checked.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum BaseFeeRule {
+    /// Any base fee is accepted. Use this for EIP-1559 chains, whose base fee
+    /// depends on the parent block (which is not part of the IR).
+    Unchecked,
+
+    /// Every block must have exactly this base fee.
+    Fixed(U256),
+
+    /// Every block must have at least this base fee.
+    Minimum(U256),
+}
+
+/// The configuration of the chain that a prover state proves blocks of.
+///
+/// Proofs of different chains can not be told apart by their circuits, so a
+/// service that proves several chains should give each of their prover states
+/// the config of its chain. The block metadata of every txn and block is then
+/// checked against it before proving, which keeps blocks of one chain from
+/// being proven (and aggregated) by the prover state of another.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ChainConfig {
+    pub chain_id: U256,
+
+    /// The hard fork active from every activation height on.
+    pub fork_schedule: BTreeMap<BlockHeight, HardFork>,
+
+    pub base_fee_rule: BaseFeeRule,
+}
+
+impl ChainConfig {
+    /// A chain that has been on `fork` since genesis.
+    pub fn new(chain_id: U256, fork: HardFork, base_fee_rule: BaseFeeRule) -> Self {
+        Self {
+            chain_id,
+            fork_schedule: BTreeMap::from([(BlockHeight(0), fork)]),
+            base_fee_rule,
+        }
+    }
+
+    /// Activate `fork` from `activation_height` on.
+    pub fn set_fork_activation(mut self, activation_height: BlockHeight, fork: HardFork) -> Self {
+        self.fork_schedule.insert(activation_height, fork);
+        self
+    }
+
+    /// The hard fork active at `b_height`, if any fork is.
+    pub fn fork_at(&self, b_height: BlockHeight) -> Option<HardFork> {
+        self.fork_schedule
+            .range(..=b_height)
+            .next_back()
+            .map(|(_, fork)| *fork)
+    }
+
+    /// Check that `b_meta` describes a block of this chain that can be proven.
+    pub fn validate_block_metadata(&self, b_meta: &BlockMetadata) -> ProofGenResult<()> {
+        let b_height = u64::try_from(b_meta.block_number)
+            .map(BlockHeight)
+            .map_err(|_| {
+                ProofGenError::InvalidInput(format!(
+                    "Block number {} does not fit a u64",
+                    b_meta.block_number
+                ))
+            })?;
+        let invalid = |reason: String| {
+            ProofGenError::InvalidInput(format!(
+                "Block {} does not match the chain config: {}",
+                b_height, reason
+            ))
+        };
+
+        if b_meta.block_chain_id != self.chain_id {
+            return Err(invalid(format!(
+                "chain id {} is not {}",
+                b_meta.block_chain_id, self.chain_id
+            )));
+        }
+
+        match self.fork_at(b_height) {
+            Some(fork) if HardFork::SUPPORTED.contains(&fork) => (),
+            Some(fork) => return Err(invalid(format!("hard fork {} is not supported", fork))),
+            None => return Err(invalid("no hard fork is active".to_string())),
+        }
+
+        match &self.base_fee_rule {
+            BaseFeeRule::Unchecked => (),
+            BaseFeeRule::Fixed(fee) if b_meta.block_base_fee != *fee => {
+                return Err(invalid(format!(
+                    "base fee {} is not {}",
+                    b_meta.block_base_fee, fee
+                )))
+            }
+            BaseFeeRule::Minimum(fee) if b_meta.block_base_fee < *fee => {
+                return Err(invalid(format!(
+                    "base fee {} is less than {}",
+                    b_meta.block_base_fee, fee
+                )))
+            }
+            BaseFeeRule::Fixed(_) | BaseFeeRule::Minimum(_) => (),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! The file format of circuits saved with
+//! [`ProverState::save`](crate::prover_state::ProverState::save).
+//!
+//! The serialized circuits are prefixed with a header holding a checksum of
+//! them, along with the versions and circuit parameters that they were built
+//! with. Loading circuits whose header does not match the current build fails
+//! with [`ProofGenError::StaleCircuitCache`] instead of failing deep inside of
+//! deserialization (or worse, loading circuits that produce proofs which no
+//! longer verify).
+
+use std::{marker::PhantomData, ops::Range};
+
+use ethereum_types::H256;
+use keccak_hash::keccak;
+use plonky2::{
+    plonk::config::PoseidonGoldilocksConfig,
+    util::serialization::{DefaultGateSerializer, DefaultGeneratorSerializer},
+};
+use plonky2_evm::{all_stark::NUM_TABLES, config::StarkConfig};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    metadata::PLONKY2_EVM_REV,
+    proof_gen::{ProofGenError, ProofGenResult},
+    types::AllRecursiveCircuits,
+};
+
+const MAGIC: [u8; 4] = *b"PBPS";
+
+/// Bumped whenever the layout of the saved circuits changes.
+const FORMAT_VERSION: u16 = 1;
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct CircuitCacheHeader {
+    format_version: u16,
+    crate_version: String,
+    plonky2_evm_rev: String,
+    params_fingerprint: H256,
+    checksum: H256,
+}
+
+/// A fingerprint of the parameters that the circuits are built from.
+///
+/// Note that `AllStark` is not part of the fingerprint, as there is no way to
+/// inspect it.
+pub(crate) fn circuit_params_fingerprint(
+    circuit_sizes: &[Range<usize>; NUM_TABLES],
+    stark_config: &StarkConfig,
+) -> H256 {
+    // Destructured so that fields added to `StarkConfig` can not be missed.
+    let StarkConfig {
+        security_bits,
+        num_challenges,
+        fri_config,
+    } = stark_config;
+
+    keccak(format!(
+        "{:?}/{}/{}/{:?}",
+        circuit_sizes, security_bits, num_challenges, fri_config
+    ))
+}
+
+pub(crate) fn circuits_to_bytes(
+    circuits: &AllRecursiveCircuits,
+    params_fingerprint: H256,
+) -> ProofGenResult<Vec<u8>> {
+    let body = circuits
+        .to_bytes(false, &DefaultGateSerializer, &generator_serializer())
+        .map_err(|err| {
+            ProofGenError::Serialization(format!("Failed to serialize circuits: {:?}", err))
+        })?;
+
+    let header = bincode::serialize(&CircuitCacheHeader {
+        format_version: FORMAT_VERSION,
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        plonky2_evm_rev: PLONKY2_EVM_REV.to_string(),
+        params_fingerprint,
+        checksum: keccak(&body),
+    })
+    .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 8 + header.len() + body.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&(header.len() as u64).to_le_bytes());
+    bytes.extend(header);
+    bytes.extend(body);
+
+    Ok(bytes)
+}
+
+/// Deserializes circuits created by `circuits_to_bytes`, checking that they are
+/// intact and were built by this version of the crate with the expected
+/// circuit parameters.
+pub(crate) fn circuits_from_bytes(
+    bytes: &[u8],
+    expected_params_fingerprint: H256,
+) -> ProofGenResult<AllRecursiveCircuits> {
+    if bytes.len() < MAGIC.len() + 8 || bytes[..MAGIC.len()] != MAGIC {
+        return Err(stale_err(
+            "File does not contain saved circuits".to_string(),
+        ));
+    }
+
+    let header_len =
+        u64::from_le_bytes(bytes[MAGIC.len()..MAGIC.len() + 8].try_into().unwrap()) as usize;
+    let header_bytes = bytes[MAGIC.len() + 8..]
+        .get(..header_len)
+        .ok_or_else(|| stale_err("Saved circuits are truncated".to_string()))?;
+    let body = &bytes[MAGIC.len() + 8 + header_len..];
+
+    // Parse the format version on its own first, as the rest of the header may
+    // have a different layout in other versions. A header that can not be
+    // decoded means that the file is corrupted, which rebuilding fixes too.
+    let format_version: u16 = bincode::deserialize(header_bytes).map_err(|err| {
+        stale_err(format!(
+            "Failed to decode the saved circuits header: {}",
+            err
+        ))
+    })?;
+    if format_version != FORMAT_VERSION {
+        return Err(stale_err(format!(
+            "Saved circuits have format version {} but expected version {}",
+            format_version, FORMAT_VERSION
+        )));
+    }
+
+    let header: CircuitCacheHeader = bincode::deserialize(header_bytes).map_err(|err| {
+        stale_err(format!(
+            "Failed to decode the saved circuits header: {}",
+            err
+        ))
+    })?;
+
+    if header.crate_version != env!("CARGO_PKG_VERSION")
+        || header.plonky2_evm_rev != PLONKY2_EVM_REV
+    {
+        return Err(stale_err(format!(
+            "Circuits were saved by version {} (plonky2 {}) but this is version {} (plonky2 {})",
+            header.crate_version,
+            header.plonky2_evm_rev,
+            env!("CARGO_PKG_VERSION"),
+            PLONKY2_EVM_REV
+        )));
+    }
+
+    if header.params_fingerprint != expected_params_fingerprint {
+        return Err(stale_err(
+            "Circuits were built with different circuit sizes or STARK config".to_string(),
+        ));
+    }
+
+    if keccak(body) != header.checksum {
+        return Err(stale_err(
+            "Checksum of the saved circuits does not match (the file is corrupted)".to_string(),
+        ));
+    }
+
+    AllRecursiveCircuits::from_bytes(body, false, &DefaultGateSerializer, &generator_serializer())
+        .map_err(|err| {
+            ProofGenError::Serialization(format!("Failed to deserialize circuits: {:?}", err))
+        })
+}
+
+fn generator_serializer() -> DefaultGeneratorSerializer<PoseidonGoldilocksConfig, 2> {
+    DefaultGeneratorSerializer {
+        _phantom: PhantomData,
+    }
+}
+
+fn stale_err(msg: String) -> ProofGenError {
+    ProofGenError::StaleCircuitCache(msg)
+}
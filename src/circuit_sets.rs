@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+
+use proof_protocol_decoder::types::OtherBlockData;
+
+use crate::{
+    backend::BlockProverBackend,
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_types::{GeneratedAggProof, GeneratedBlockProof},
+    prover_state::{LazyProverState, ProverState, ProverStateBuilder},
+    types::BlockHeight,
+};
+
+/// Multiple circuit sets (eg. one for every hard fork), each of which is used
+/// to prove the blocks from its activation height until the activation height
+/// of the next set.
+///
+/// Every block is proven entirely by the circuit set of its height, so txn and
+/// agg proofs are never mixed between sets. A block proof can also only have
+/// a parent block proof from the same set (a block circuit can only verify its
+/// own proofs), so the first block of every set needs to be a checkpoint
+/// height of that set. Every set is only built the first time it is used.
+pub struct CircuitSets {
+    sets: BTreeMap<BlockHeight, LazyProverState>,
+}
+
+impl CircuitSets {
+    /// Use the circuits of `builder` for every block starting from genesis.
+    pub fn new(builder: ProverStateBuilder) -> Self {
+        Self {
+            sets: BTreeMap::from([(BlockHeight(0), builder.into())]),
+        }
+    }
+
+    /// Use the circuits of `builder` for every block starting from
+    /// `activation_height` (until the activation height of the next set).
+    pub fn add_circuit_set(
+        mut self,
+        activation_height: BlockHeight,
+        builder: ProverStateBuilder,
+    ) -> Self {
+        self.sets.insert(activation_height, builder.into());
+        self
+    }
+
+    /// The activation height of the circuit set that proves `b_height`.
+    pub fn activation_height(&self, b_height: BlockHeight) -> BlockHeight {
+        *self.entry(b_height).0
+    }
+
+    /// The prover state to prove block `b_height` with. Pass it to any of the
+    /// `generate_*_proof` functions or orchestration helpers.
+    ///
+    /// Fails if the prover state has not been built yet and building it fails
+    /// (see [`LazyProverState::get`]).
+    pub fn for_block(&self, b_height: BlockHeight) -> ProofGenResult<&ProverState> {
+        self.entry(b_height).1.get()
+    }
+
+    /// Generate a block proof with the circuit set of the block.
+    ///
+    /// Fails with `ProofGenError::InvalidInput` if the parent block proof was
+    /// generated by a different circuit set.
+    pub fn generate_block_proof(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: &GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        let b_height = curr_block_agg_proof.common.b_height;
+        if let Some(parent) = prev_opt_parent_b_proof {
+            if self.activation_height(parent.b_height) != self.activation_height(b_height) {
+                return Err(ProofGenError::InvalidInput(format!(
+                    "Block {} is proven by a different circuit set than its parent block {} (the \
+                     first block of a circuit set must be proven without a parent)",
+                    b_height, parent.b_height
+                )));
+            }
+        }
+
+        self.for_block(b_height)?.generate_block_proof(
+            prev_opt_parent_b_proof,
+            curr_block_agg_proof,
+            other_data,
+            opts,
+        )
+    }
+
+    fn entry(&self, b_height: BlockHeight) -> (&BlockHeight, &LazyProverState) {
+        self.sets
+            .range(..=b_height)
+            .next_back()
+            .expect("There is always a set active from genesis")
+    }
+}
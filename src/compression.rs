@@ -0,0 +1,187 @@
+use ethereum_types::{Address, U256};
+use plonky2_evm::proof::PublicValues;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    estimation::TraceSizes,
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_types::{GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof, ProofCommon},
+    types::{
+        BlockHeight, CircuitDigest, CompressedPlonkyProofIntern, PlonkyCommonData,
+        ProofUnderlyingTxns, TxnIdx,
+    },
+};
+
+// Compressing merges the (often redundant) Merkle paths of the FRI query
+// rounds, which significantly shrinks the large recursive proofs. Both
+// directions need the digest and common data of the circuit that the proof is
+// a proof of (eg. `p_state.state.block.circuit.common` for block proofs).
+
+/// A txn proof with a compressed underlying plonky2 proof.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CompressedGeneratedTxnProof {
+    pub txn_idx: TxnIdx,
+    pub common: ProofCommon,
+    pub intern: CompressedPlonkyProofIntern,
+    pub trace_sizes: Option<TraceSizes>,
+
+    /// See `GeneratedTxnProof::peak_rss_bytes`.
+    #[serde(skip)]
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// An agg proof with a compressed underlying plonky2 proof.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CompressedGeneratedAggProof {
+    pub underlying_txns: ProofUnderlyingTxns,
+    pub common: ProofCommon,
+    pub intern: CompressedPlonkyProofIntern,
+
+    /// See `GeneratedTxnProof::peak_rss_bytes`.
+    #[serde(skip)]
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// A block proof with a compressed underlying plonky2 proof.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CompressedGeneratedBlockProof {
+    pub b_height: BlockHeight,
+    pub intern: CompressedPlonkyProofIntern,
+    pub public_values: PublicValues,
+    pub security_bits: usize,
+    pub withdrawals: Vec<(Address, U256)>,
+
+    /// See `GeneratedTxnProof::peak_rss_bytes`.
+    #[serde(skip)]
+    pub peak_rss_bytes: Option<u64>,
+}
+
+impl GeneratedTxnProof {
+    /// Compress the proof. `circuit_digest` and `common_data` must be those of
+    /// the root circuit.
+    pub fn compress(
+        self,
+        circuit_digest: &CircuitDigest,
+        common_data: &PlonkyCommonData,
+    ) -> ProofGenResult<CompressedGeneratedTxnProof> {
+        Ok(CompressedGeneratedTxnProof {
+            txn_idx: self.txn_idx,
+            common: self.common,
+            intern: self
+                .intern
+                .compress(circuit_digest, common_data)
+                .map_err(compression_err)?,
+            trace_sizes: self.trace_sizes,
+            peak_rss_bytes: self.peak_rss_bytes,
+        })
+    }
+}
+
+impl CompressedGeneratedTxnProof {
+    /// Decompress the proof. `circuit_digest` and `common_data` must be those
+    /// of the root circuit.
+    pub fn decompress(
+        self,
+        circuit_digest: &CircuitDigest,
+        common_data: &PlonkyCommonData,
+    ) -> ProofGenResult<GeneratedTxnProof> {
+        Ok(GeneratedTxnProof {
+            txn_idx: self.txn_idx,
+            common: self.common,
+            intern: self
+                .intern
+                .decompress(circuit_digest, common_data)
+                .map_err(compression_err)?,
+            trace_sizes: self.trace_sizes,
+            peak_rss_bytes: self.peak_rss_bytes,
+        })
+    }
+}
+
+impl GeneratedAggProof {
+    /// Compress the proof. `circuit_digest` and `common_data` must be those of
+    /// the aggregation circuit.
+    pub fn compress(
+        self,
+        circuit_digest: &CircuitDigest,
+        common_data: &PlonkyCommonData,
+    ) -> ProofGenResult<CompressedGeneratedAggProof> {
+        Ok(CompressedGeneratedAggProof {
+            underlying_txns: self.underlying_txns,
+            common: self.common,
+            intern: self
+                .intern
+                .compress(circuit_digest, common_data)
+                .map_err(compression_err)?,
+            peak_rss_bytes: self.peak_rss_bytes,
+        })
+    }
+}
+
+impl CompressedGeneratedAggProof {
+    /// Decompress the proof. `circuit_digest` and `common_data` must be those
+    /// of the aggregation circuit.
+    pub fn decompress(
+        self,
+        circuit_digest: &CircuitDigest,
+        common_data: &PlonkyCommonData,
+    ) -> ProofGenResult<GeneratedAggProof> {
+        Ok(GeneratedAggProof {
+            underlying_txns: self.underlying_txns,
+            common: self.common,
+            intern: self
+                .intern
+                .decompress(circuit_digest, common_data)
+                .map_err(compression_err)?,
+            peak_rss_bytes: self.peak_rss_bytes,
+        })
+    }
+}
+
+impl GeneratedBlockProof {
+    /// Compress the proof. `circuit_digest` and `common_data` must be those of
+    /// the block circuit.
+    pub fn compress(
+        self,
+        circuit_digest: &CircuitDigest,
+        common_data: &PlonkyCommonData,
+    ) -> ProofGenResult<CompressedGeneratedBlockProof> {
+        Ok(CompressedGeneratedBlockProof {
+            b_height: self.b_height,
+            intern: self
+                .intern
+                .compress(circuit_digest, common_data)
+                .map_err(compression_err)?,
+            public_values: self.public_values,
+            security_bits: self.security_bits,
+            withdrawals: self.withdrawals,
+            peak_rss_bytes: self.peak_rss_bytes,
+        })
+    }
+}
+
+impl CompressedGeneratedBlockProof {
+    /// Decompress the proof. `circuit_digest` and `common_data` must be those
+    /// of the block circuit.
+    pub fn decompress(
+        self,
+        circuit_digest: &CircuitDigest,
+        common_data: &PlonkyCommonData,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        Ok(GeneratedBlockProof {
+            b_height: self.b_height,
+            intern: self
+                .intern
+                .decompress(circuit_digest, common_data)
+                .map_err(compression_err)?,
+            public_values: self.public_values,
+            security_bits: self.security_bits,
+            withdrawals: self.withdrawals,
+            peak_rss_bytes: self.peak_rss_bytes,
+        })
+    }
+}
+
+fn compression_err(err: anyhow::Error) -> ProofGenError {
+    ProofGenError::Serialization(format!("Failed to (de)compress proof: {:#}", err))
+}
@@ -0,0 +1,121 @@
+use eth_trie_utils::partial_trie::{HashedPartialTrie, Node, PartialTrie};
+use ethereum_types::{H256, U256};
+use plonky2_evm::{
+    generation::{GenerationInputs, TrieInputs},
+    proof::TrieRoots,
+};
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+
+use crate::{
+    backend::BlockProverBackend,
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_types::{GeneratedAggProof, GeneratedTxnProof},
+    types::TxnIdx,
+};
+
+/// Create the IR for a dummy txn that does not execute anything.
+///
+/// The dummy txn starts (and ends) with the tries having the roots in `roots`
+/// and with `gas_used` gas used so far in the block. Because nothing is
+/// executed, the tries can be fully hashed out.
+pub fn create_dummy_txn_ir(
+    txn_idx: TxnIdx,
+    roots: &TrieRoots,
+    gas_used: U256,
+    other_data: &OtherBlockData,
+) -> TxnProofGenIR {
+    let gen_inputs = GenerationInputs {
+        txn_number_before: txn_idx.into(),
+        gas_used_before: gas_used,
+        gas_used_after: gas_used,
+        signed_txn: None,
+        tries: TrieInputs {
+            state_trie: create_hashed_out_trie(roots.state_root),
+            transactions_trie: create_hashed_out_trie(roots.transactions_root),
+            receipts_trie: create_hashed_out_trie(roots.receipts_root),
+            storage_tries: Vec::new(),
+        },
+        trie_roots_after: roots.clone(),
+        genesis_state_trie_root: other_data.genesis_state_trie_root,
+        block_metadata: other_data.b_data.b_meta.clone(),
+        block_hashes: other_data.b_data.b_hashes.clone(),
+        ..Default::default()
+    };
+
+    TxnProofGenIR {
+        txn_idx: txn_idx.into(),
+        gen_inputs,
+    }
+}
+
+/// Generate a dummy txn proof that directly follows `prev_txn_proof`.
+///
+/// This gives a lone txn proof a partner to be aggregated with.
+pub fn generate_dummy_txn_proof_after<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    prev_txn_proof: &GeneratedTxnProof,
+    other_data: &OtherBlockData,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedTxnProof> {
+    let dummy_ir = create_dummy_txn_ir(
+        prev_txn_proof.txn_idx + 1,
+        &prev_txn_proof.common.roots_after,
+        prev_txn_proof.common.deltas.gas_used_after,
+        other_data,
+    );
+
+    p_state.generate_txn_proof(dummy_ir, opts)
+}
+
+/// Generate an agg proof for a block that contains no txns.
+///
+/// Empty blocks have no txn IR, so this aggregates two dummy txns that leave
+/// the state trie with root `state_root` untouched. The txn and receipt tries
+/// of a block start out empty.
+///
+/// Processing withdrawals would need the accounts that they pay out to, which
+/// the hashed out state trie of the dummy txns does not have, so empty blocks
+/// with withdrawals are rejected with `ProofGenError::InvalidInput`.
+pub fn generate_empty_block_agg_proof<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    state_root: H256,
+    other_data: &OtherBlockData,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedAggProof> {
+    if !other_data.b_data.withdrawals.is_empty() {
+        return Err(ProofGenError::InvalidInput(format!(
+            "Can not prove an empty block with {} withdrawal(s), the withdrawals need the IR of a \
+             txn that processes them",
+            other_data.b_data.withdrawals.len()
+        )));
+    }
+
+    let roots = TrieRoots {
+        state_root,
+        transactions_root: empty_trie_root(),
+        receipts_root: empty_trie_root(),
+    };
+
+    // Both children are the same no-op txn, so the dummy only needs to be
+    // proven once.
+    let dummy = p_state.generate_txn_proof(
+        create_dummy_txn_ir(TxnIdx(0), &roots, U256::zero(), other_data),
+        opts,
+    )?;
+
+    p_state.generate_agg_proof(
+        &dummy.clone().into(),
+        &dummy.into(),
+        other_data.clone(),
+        opts,
+    )
+}
+
+fn empty_trie_root() -> H256 {
+    HashedPartialTrie::new(Node::Empty).hash()
+}
+
+fn create_hashed_out_trie(root: H256) -> HashedPartialTrie {
+    HashedPartialTrie::new(Node::Hash(root))
+}
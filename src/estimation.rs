@@ -0,0 +1,331 @@
+use std::ops::Range;
+
+use plonky2::util::timing::TimingTree;
+use plonky2_evm::{
+    all_stark::{Table, NUM_TABLES},
+    config::StarkConfig,
+    generation::generate_traces,
+};
+use proof_protocol_decoder::types::TxnProofGenIR;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    proof_gen::{catch_prover_panic, ProofGenError, ProofGenResult},
+    prover_state::ProverState,
+    types::TABLE_NAMES,
+};
+
+/// Size in bytes of a single Goldilocks field element.
+const FIELD_ELEMENT_BYTES: usize = 8;
+
+/// Accounts for everything besides the trace LDEs that is alive at the peak of
+/// STARK proving (auxiliary lookup columns, the quotient polynomials and the
+/// Merkle trees over all of them).
+const PEAK_MEMORY_OVERHEAD_FACTOR: usize = 2;
+
+/// The size of the trace of a single STARK table.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TableTraceSize {
+    /// The number of rows (this is always a power of two).
+    pub len: usize,
+
+    /// The number of columns.
+    pub width: usize,
+}
+
+impl TableTraceSize {
+    pub fn degree_bits(&self) -> usize {
+        self.len.trailing_zeros() as usize
+    }
+}
+
+/// The trace sizes of every STARK table for a single txn.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TraceSizes {
+    /// Indexed in the same order as [`TABLE_NAMES`].
+    pub tables: [TableTraceSize; NUM_TABLES],
+}
+
+impl TraceSizes {
+    /// Iterate over the trace sizes along with the name of their table.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &TableTraceSize)> {
+        TABLE_NAMES.into_iter().zip(self.tables.iter())
+    }
+
+    /// The degree bits of every table, indexed like [`TABLE_NAMES`].
+    pub fn degree_bits(&self) -> [usize; NUM_TABLES] {
+        self.tables.map(|size| size.degree_bits())
+    }
+
+    /// The number of cycles the CPU ran for.
+    ///
+    /// This includes the cycles that plonky2 spends in its halt loop to pad the
+    /// CPU trace to a power of two, so it is always equal to the length of the
+    /// CPU trace. Plonky2 does not expose the cycle at which the txn halted.
+    pub fn cpu_cycles(&self) -> usize {
+        self.tables[Table::Cpu as usize].len
+    }
+
+    /// The names of the tables whose degree bits fall outside of
+    /// `circuit_sizes`. Proving traces of these sizes with circuits of those
+    /// sizes will fail.
+    pub fn unsupported_tables(
+        &self,
+        circuit_sizes: &[Range<usize>; NUM_TABLES],
+    ) -> Vec<&'static str> {
+        self.iter()
+            .zip(circuit_sizes.iter())
+            .filter(|((_, size), supported)| !supported.contains(&size.degree_bits()))
+            .map(|((name, _), _)| name)
+            .collect()
+    }
+
+    /// A rough estimate of the peak memory (in bytes) needed to STARK prove
+    /// traces of these sizes with `config`.
+    ///
+    /// The estimate is dominated by the low-degree extensions of the traces,
+    /// which are `2^rate_bits` times larger than the traces themselves.
+    pub fn estimated_proving_memory(&self, config: &StarkConfig) -> usize {
+        let lde_factor = 1 << config.fri_config.rate_bits;
+
+        self.tables
+            .iter()
+            .map(|size| size.len * size.width * FIELD_ELEMENT_BYTES * (1 + lde_factor))
+            .sum::<usize>()
+            * PEAK_MEMORY_OVERHEAD_FACTOR
+    }
+}
+
+/// Run witness generation for `ir` (but no proving) to get the trace size of
+/// every STARK table.
+///
+/// This is far cheaper than proving, but still executes the txn.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "witness_generation", skip_all, err)
+)]
+pub fn compute_trace_sizes(
+    p_state: &ProverState,
+    ir: &TxnProofGenIR,
+) -> ProofGenResult<TraceSizes> {
+    // Witness generation panics on the same edge cases as proving.
+    let (traces, ..) = catch_prover_panic(|| {
+        generate_traces(
+            &p_state.all_stark,
+            ir.gen_inputs.clone(),
+            &p_state.stark_config,
+            &mut TimingTree::default(),
+        )
+    })?
+    .map_err(|err| ProofGenError::TxnProving {
+        b_height: ir.b_height().into(),
+        txn_idx: ir.txn_idx.into(),
+        msg: format!("{:#}", err),
+    })?;
+
+    let mut sizes = TraceSizes::default();
+    for (size, trace) in sizes.tables.iter_mut().zip(traces.iter()) {
+        *size = TableTraceSize {
+            len: trace.first().map(|col| col.len()).unwrap_or_default(),
+            width: trace.len(),
+        };
+    }
+
+    Ok(sizes)
+}
+
+/// The result of simulating a txn with [`simulate_txn`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TxnSimulation {
+    pub trace_sizes: TraceSizes,
+
+    /// See [`TraceSizes::cpu_cycles`].
+    pub cpu_cycles: usize,
+
+    /// The degree bits that every table would be proven with, indexed like
+    /// [`TABLE_NAMES`].
+    pub degree_bits: [usize; NUM_TABLES],
+
+    /// The tables whose degree bits are not supported by the circuits of the
+    /// prover state.
+    pub unsupported_tables: Vec<&'static str>,
+
+    /// See [`TraceSizes::estimated_proving_memory`].
+    pub estimated_proving_memory: usize,
+}
+
+impl TxnSimulation {
+    /// Whether the circuits of the prover state that simulated the txn are
+    /// able to prove it.
+    pub fn fits_circuits(&self) -> bool {
+        self.unsupported_tables.is_empty()
+    }
+}
+
+/// Simulate proving `ir` by only running witness generation (ie. executing the
+/// txn) without any STARK proving.
+///
+/// This is usually enough to reject txns that would fail to prove (or would be
+/// too expensive to prove) with this prover state before spending minutes on
+/// proving them.
+pub fn simulate_txn(p_state: &ProverState, ir: &TxnProofGenIR) -> ProofGenResult<TxnSimulation> {
+    let trace_sizes = compute_trace_sizes(p_state, ir)?;
+
+    Ok(TxnSimulation {
+        cpu_cycles: trace_sizes.cpu_cycles(),
+        degree_bits: trace_sizes.degree_bits(),
+        unsupported_tables: trace_sizes.unsupported_tables(&p_state.circuit_sizes),
+        estimated_proving_memory: trace_sizes.estimated_proving_memory(&p_state.stark_config),
+        trace_sizes,
+    })
+}
+
+/// Estimate the peak memory (in bytes) needed to prove `ir` with this prover
+/// state.
+///
+/// See [`TraceSizes::estimated_proving_memory`] for how rough this is.
+pub fn estimate_proving_memory(p_state: &ProverState, ir: &TxnProofGenIR) -> ProofGenResult<usize> {
+    compute_trace_sizes(p_state, ir)
+        .map(|sizes| sizes.estimated_proving_memory(&p_state.stark_config))
+}
+
+/// The parts of txn IR that [`DegreeBitsEstimator`] bases its estimates on.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct IrFeatures {
+    /// The gas used by the txn.
+    pub gas_used: u64,
+
+    /// The combined size of the signed txn and of all contract code in the IR.
+    pub input_bytes: usize,
+
+    /// The number of items (values and hashed out nodes) in the state and
+    /// storage tries. This is roughly the amount of state the txn touches.
+    pub trie_items: usize,
+}
+
+impl IrFeatures {
+    pub fn from_ir(ir: &TxnProofGenIR) -> Self {
+        let inputs = &ir.gen_inputs;
+
+        let gas_used = inputs.gas_used_after.saturating_sub(inputs.gas_used_before);
+        let input_bytes = inputs.signed_txn.as_ref().map(Vec::len).unwrap_or_default()
+            + inputs.contract_code.values().map(Vec::len).sum::<usize>();
+        let trie_items = inputs.tries.state_trie.trie_items().count()
+            + inputs
+                .tries
+                .storage_tries
+                .iter()
+                .map(|(_, trie)| trie.trie_items().count())
+                .sum::<usize>();
+
+        Self {
+            gas_used: gas_used.try_into().unwrap_or(u64::MAX),
+            input_bytes,
+            trie_items,
+        }
+    }
+}
+
+/// A linear model of the number of rows that a single table needs.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TableRowModel {
+    pub base: f64,
+    pub per_gas: f64,
+    pub per_input_byte: f64,
+    pub per_trie_item: f64,
+}
+
+impl TableRowModel {
+    const fn new(base: f64, per_gas: f64, per_input_byte: f64, per_trie_item: f64) -> Self {
+        Self {
+            base,
+            per_gas,
+            per_input_byte,
+            per_trie_item,
+        }
+    }
+
+    pub fn estimate_rows(&self, features: &IrFeatures) -> f64 {
+        self.base
+            + self.per_gas * features.gas_used as f64
+            + self.per_input_byte * features.input_bytes as f64
+            + self.per_trie_item * features.trie_items as f64
+    }
+}
+
+/// Estimates the degree bits of every table directly from txn IR, without
+/// executing the txn.
+///
+/// This is much cheaper than [`simulate_txn`] (and does not need a prover
+/// state), which makes it suitable for routing txns to provers whose circuits
+/// support their sizes. It is only a heuristic though, so txns close to the
+/// edge of a circuit size may still end up on the wrong side of it.
+///
+/// The default models are rough. Provers with a known workload should fit the
+/// models to the trace sizes of txns that they have simulated (or proven).
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct DegreeBitsEstimator {
+    /// Indexed like [`TABLE_NAMES`].
+    pub tables: [TableRowModel; NUM_TABLES],
+}
+
+impl Default for DegreeBitsEstimator {
+    fn default() -> Self {
+        Self {
+            tables: [
+                // Arithmetic
+                TableRowModel::new(1024.0, 0.5, 0.0, 20.0),
+                // Byte packing
+                TableRowModel::new(512.0, 0.2, 1.0, 32.0),
+                // Cpu
+                TableRowModel::new(16384.0, 4.0, 8.0, 300.0),
+                // Keccak (24 rows per permutation of 136 bytes)
+                TableRowModel::new(1024.0, 0.05, 0.18, 72.0),
+                // Keccak sponge (a row per 136 byte block)
+                TableRowModel::new(64.0, 0.002, 0.0074, 3.0),
+                // Logic
+                TableRowModel::new(256.0, 0.1, 0.02, 4.0),
+                // Memory
+                TableRowModel::new(65536.0, 16.0, 40.0, 1500.0),
+            ],
+        }
+    }
+}
+
+impl DegreeBitsEstimator {
+    /// Estimate the degree bits of every table for `ir`, indexed like
+    /// [`TABLE_NAMES`].
+    pub fn estimate(&self, ir: &TxnProofGenIR) -> [usize; NUM_TABLES] {
+        self.estimate_from_features(&IrFeatures::from_ir(ir))
+    }
+
+    pub fn estimate_from_features(&self, features: &IrFeatures) -> [usize; NUM_TABLES] {
+        self.tables.map(|model| {
+            let rows = model.estimate_rows(features).max(1.0).ceil() as usize;
+            rows.next_power_of_two().trailing_zeros() as usize
+        })
+    }
+
+    /// The most gas that a single txn is estimated to be able to use with
+    /// circuits of `circuit_sizes`.
+    ///
+    /// This assumes a txn that uses no input bytes and touches no state, so it
+    /// is an upper bound. Real txns will run out of rows before reaching it.
+    pub fn max_gas(&self, circuit_sizes: &[Range<usize>; NUM_TABLES]) -> u64 {
+        self.tables
+            .iter()
+            .zip(circuit_sizes.iter())
+            .filter(|(model, _)| model.per_gas > 0.0)
+            .map(|(model, size)| {
+                let max_rows = (1u64 << size.end.saturating_sub(1)) as f64;
+                ((max_rows - model.base) / model.per_gas).max(0.0) as u64
+            })
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Whether `ir` is estimated to fit the circuits of `p_state`.
+    pub fn fits(&self, p_state: &ProverState, ir: &TxnProofGenIR) -> bool {
+        p_state.supports_degree_bits(&self.estimate(ir))
+    }
+}
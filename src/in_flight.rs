@@ -0,0 +1,144 @@
+//! Tracks the proofs that a prover is currently working on, so that operators
+//! can see what a busy prover is actually doing.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::proof_key::ProofKey;
+
+/// What an in-flight proof is waiting on.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProofPhase {
+    /// Accepted, but waiting for a free slot to be proven in.
+    Queued,
+    Proving,
+}
+
+/// A proof that is currently queued or being proven.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InFlightProof {
+    pub key: ProofKey,
+    pub phase: ProofPhase,
+
+    /// The time since the proof was requested.
+    pub elapsed: Duration,
+
+    /// The time since the proof entered its current phase.
+    pub in_phase: Duration,
+}
+
+#[derive(Debug)]
+struct Entry {
+    key: ProofKey,
+    phase: ProofPhase,
+    requested_at: Instant,
+    phase_started_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: u64,
+
+    /// Keyed by the order the proofs were requested in, so that listings are
+    /// oldest first.
+    entries: BTreeMap<u64, Entry>,
+}
+
+/// The registry of in-flight proofs, shared by everything that proves on
+/// behalf of a prover.
+#[derive(Clone, Debug, Default)]
+pub struct InFlightProofs {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl InFlightProofs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking the proof of `key` as queued. It is tracked until the
+    /// returned guard is dropped.
+    pub fn track(&self, key: ProofKey) -> InFlightGuard {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().expect("Lock is never poisoned");
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.entries.insert(
+            id,
+            Entry {
+                key,
+                phase: ProofPhase::Queued,
+                requested_at: now,
+                phase_started_at: now,
+            },
+        );
+
+        InFlightGuard {
+            proofs: self.clone(),
+            id,
+        }
+    }
+
+    /// The proofs that are currently queued or being proven, oldest first.
+    pub fn list(&self) -> Vec<InFlightProof> {
+        let now = Instant::now();
+        let inner = self.inner.lock().expect("Lock is never poisoned");
+
+        inner
+            .entries
+            .values()
+            .map(|entry| InFlightProof {
+                key: entry.key.clone(),
+                phase: entry.phase,
+                elapsed: now.duration_since(entry.requested_at),
+                in_phase: now.duration_since(entry.phase_started_at),
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner
+            .lock()
+            .expect("Lock is never poisoned")
+            .entries
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Keeps a proof listed as in flight until it is dropped.
+#[derive(Debug)]
+pub struct InFlightGuard {
+    proofs: InFlightProofs,
+    id: u64,
+}
+
+impl InFlightGuard {
+    pub fn set_phase(&self, phase: ProofPhase) {
+        let mut inner = self.proofs.inner.lock().expect("Lock is never poisoned");
+        if let Some(entry) = inner.entries.get_mut(&self.id) {
+            if entry.phase != phase {
+                entry.phase = phase;
+                entry.phase_started_at = Instant::now();
+            }
+        }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        // Do not panic while unwinding from a panic in the prover.
+        if let Ok(mut inner) = self.proofs.inner.lock() {
+            inner.entries.remove(&self.id);
+        }
+    }
+}
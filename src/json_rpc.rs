@@ -0,0 +1,410 @@
+//! A JSON-RPC 2.0 proving server, for deployments where the gRPC service is
+//! inconvenient.
+//!
+//! The server exposes the same operations as the gRPC service:
+//!
+//! - `prove_txn` with `{ "txn_ir" }`
+//! - `prove_agg` with `{ "lhs_child", "rhs_child", "other_data" }`
+//! - `prove_block` with `{ "prev_opt_parent_b_proof", "curr_block_agg_proof",
+//!   "other_data" }`
+//! - `verify` with `{ "proof" }`
+//! - `in_flight` without params, which lists the proofs that are currently
+//!   queued or being proven
+//!
+//! All payloads are `0x`-prefixed hex strings. Txn IR and `OtherBlockData` are
+//! bincode encoded, and proofs are in the versioned binary proof format. Child
+//! proofs and proofs to verify are passed as `{ "kind", "bytes" }`, where
+//! `kind` is one of `txn`, `agg` and `block`.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use jsonrpsee::{
+    server::{RpcModule, Server, ServerHandle},
+    types::ErrorObjectOwned,
+};
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::task;
+
+use crate::{
+    admission::{AdmissionControl, AdmissionLimits, AdmissionPermit},
+    async_proof_gen::{
+        generate_agg_proof_async, generate_block_proof_async, generate_txn_proof_async,
+    },
+    in_flight::{InFlightGuard, InFlightProof, InFlightProofs, ProofPhase},
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenErrorKind, ProofGenResult},
+    proof_key::ProofKey,
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    prover_state::ProverState,
+    verification::{verify_agg_proof, verify_block_proof, verify_txn_proof},
+};
+
+/// The JSON-RPC error code for invalid method parameters.
+const INVALID_PARAMS_CODE: i32 = -32602;
+
+/// A proof along with its kind, so that it can be deserialized.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RpcProof {
+    pub kind: RpcProofKind,
+
+    /// The proof in the versioned binary proof format, as hex.
+    pub bytes: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcProofKind {
+    Txn,
+    Agg,
+    Block,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProveTxnParams {
+    pub txn_ir: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProveAggParams {
+    pub lhs_child: RpcProof,
+    pub rhs_child: RpcProof,
+    pub other_data: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProveBlockParams {
+    pub prev_opt_parent_b_proof: Option<String>,
+    pub curr_block_agg_proof: String,
+    pub other_data: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VerifyParams {
+    pub proof: RpcProof,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VerifyResult {
+    pub valid: bool,
+
+    /// Why the proof did not pass verification (if it did not).
+    pub error: Option<String>,
+}
+
+/// The `data` of the JSON-RPC errors returned by the server.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RpcErrorData {
+    /// One of `transient`, `invalid_input` and `prover`.
+    pub kind: String,
+
+    /// Whether it makes sense to retry the request.
+    pub retryable: bool,
+
+    /// How many milliseconds to wait before retrying, if the prover is
+    /// overloaded.
+    pub retry_after_ms: Option<u64>,
+}
+
+/// The JSON-RPC error code that `err` is reported with.
+///
+/// Every variant of [`ProofGenError`] gets its own code in the range reserved
+/// for server errors.
+pub fn json_rpc_error_code(err: &ProofGenError) -> i32 {
+    match err {
+        ProofGenError::TxnProving { .. } => -32001,
+        ProofGenError::Aggregation { .. } => -32002,
+        ProofGenError::BlockProving { .. } => -32003,
+        ProofGenError::IncompatibleChildren { .. } => -32004,
+        ProofGenError::ExceedsMemoryCeiling { .. } => -32005,
+        ProofGenError::ExceedsCircuitSizes { .. } => -32006,
+        ProofGenError::TimedOut(_) => -32007,
+        ProofGenError::ProverPanic(_) => -32008,
+        ProofGenError::Verification(_) => -32009,
+        ProofGenError::Cancelled => -32010,
+        ProofGenError::MissingParentBlockProof(_) => -32011,
+        ProofGenError::InvalidInput(_) => -32012,
+        ProofGenError::Serialization(_) => -32013,
+        ProofGenError::IncompatibleProof(_) => -32014,
+        ProofGenError::TaskFailed(_) => -32015,
+        ProofGenError::Storage(_) => -32016,
+        ProofGenError::StaleCircuitCache(_) => -32017,
+        ProofGenError::Transport(_) => -32018,
+        ProofGenError::Overloaded { .. } => -32019,
+        ProofGenError::ShuttingDown => -32020,
+        ProofGenError::LeaseExpired(_) => -32021,
+        ProofGenError::InvalidSignature(_) => -32022,
+    }
+}
+
+/// Serves the proving methods with a shared prover state.
+#[derive(Clone)]
+pub struct ProverRpc {
+    p_state: Arc<ProverState>,
+    opts: ProofGenOptions,
+    admission: Arc<AdmissionControl>,
+    in_flight: InFlightProofs,
+}
+
+impl ProverRpc {
+    pub fn new(p_state: Arc<ProverState>) -> Self {
+        Self {
+            p_state,
+            opts: ProofGenOptions::default(),
+            admission: Arc::new(AdmissionControl::new(AdmissionLimits::default())),
+            in_flight: InFlightProofs::new(),
+        }
+    }
+
+    /// Limit the proofs that are generated and queued at the same time to
+    /// `limits` instead of to the default limits. Requests beyond the limits
+    /// fail with an overloaded error.
+    pub fn set_admission_limits(mut self, limits: AdmissionLimits) -> Self {
+        self.admission = Arc::new(AdmissionControl::new(limits));
+        self
+    }
+
+    /// Generate every proof with `opts` instead of with the default options.
+    pub fn set_options(mut self, opts: ProofGenOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    /// Track the proofs of the server in `in_flight` instead of in a registry
+    /// of its own, eg. to list the proofs of several servers together.
+    pub fn set_in_flight(mut self, in_flight: InFlightProofs) -> Self {
+        self.in_flight = in_flight;
+        self
+    }
+
+    /// The proofs that the server is currently working on.
+    pub fn in_flight(&self) -> &InFlightProofs {
+        &self.in_flight
+    }
+
+    /// Reject new requests and wait for the proofs in flight to be sent back
+    /// (see [`AdmissionControl::shutdown`]).
+    ///
+    /// Every clone of the server shares the same admission control, so calling
+    /// this on a clone drains the running server, which can then be stopped
+    /// with its `ServerHandle`.
+    pub async fn shutdown(&self) {
+        self.admission.shutdown().await
+    }
+
+    /// The methods of the server, for merging into an existing jsonrpsee
+    /// server.
+    pub fn into_rpc_module(self) -> RpcModule<Self> {
+        let mut module = RpcModule::new(self);
+
+        module
+            .register_async_method("prove_txn", |params, rpc| async move {
+                rpc.prove_txn(params.parse()?).await
+            })
+            .expect("Method names are unique");
+        module
+            .register_async_method("prove_agg", |params, rpc| async move {
+                rpc.prove_agg(params.parse()?).await
+            })
+            .expect("Method names are unique");
+        module
+            .register_async_method("prove_block", |params, rpc| async move {
+                rpc.prove_block(params.parse()?).await
+            })
+            .expect("Method names are unique");
+        module
+            .register_async_method("verify", |params, rpc| async move {
+                rpc.verify(params.parse()?).await
+            })
+            .expect("Method names are unique");
+        module
+            .register_method("in_flight", |_, rpc| {
+                Ok::<Vec<InFlightProof>, ErrorObjectOwned>(rpc.in_flight.list())
+            })
+            .expect("Method names are unique");
+
+        module
+    }
+
+    /// Start serving JSON-RPC over HTTP and WebSocket on `addr`.
+    pub async fn serve(self, addr: SocketAddr) -> std::io::Result<ServerHandle> {
+        let server = Server::builder().build(addr).await?;
+        Ok(server.start(self.into_rpc_module()))
+    }
+
+    /// List the proof of `key` as queued until there is a slot to prove it in.
+    async fn admit(
+        &self,
+        key: ProofKey,
+    ) -> Result<(InFlightGuard, AdmissionPermit), ErrorObjectOwned> {
+        let in_flight = self.in_flight.track(key);
+        let permit = self.admission.admit().await.map_err(to_rpc_error)?;
+        in_flight.set_phase(ProofPhase::Proving);
+
+        Ok((in_flight, permit))
+    }
+
+    async fn prove_txn(&self, params: ProveTxnParams) -> Result<String, ErrorObjectOwned> {
+        let start_info: TxnProofGenIR = decode(&params.txn_ir)?;
+        let digest = self.p_state.circuit_digests().root;
+        let _admitted = self
+            .admit(ProofKey::for_txn_ir(&start_info, digest))
+            .await?;
+
+        let proof = generate_txn_proof_async(self.p_state.clone(), start_info, self.opts.clone())
+            .await
+            .map_err(to_rpc_error)?;
+
+        encode_proof(proof.to_bytes(&digest))
+    }
+
+    async fn prove_agg(&self, params: ProveAggParams) -> Result<String, ErrorObjectOwned> {
+        let lhs_child = self.decode_aggregatable(&params.lhs_child)?;
+        let rhs_child = self.decode_aggregatable(&params.rhs_child)?;
+        let other_data: OtherBlockData = decode(&params.other_data)?;
+        let digest = self.p_state.circuit_digests().aggregation;
+
+        let txns = lhs_child
+            .underlying_txns()
+            .combine(&rhs_child.underlying_txns());
+        let _admitted = self
+            .admit(ProofKey::for_agg(lhs_child.b_height(), txns, digest))
+            .await?;
+
+        let proof = generate_agg_proof_async(
+            self.p_state.clone(),
+            lhs_child,
+            rhs_child,
+            other_data,
+            self.opts.clone(),
+        )
+        .await
+        .map_err(to_rpc_error)?;
+
+        encode_proof(proof.to_bytes(&digest))
+    }
+
+    async fn prove_block(&self, params: ProveBlockParams) -> Result<String, ErrorObjectOwned> {
+        let digests = self.p_state.circuit_digests();
+
+        let prev_opt_parent_b_proof = params
+            .prev_opt_parent_b_proof
+            .map(|hex| GeneratedBlockProof::from_bytes(&decode_hex(&hex)?, &digests.block))
+            .transpose()
+            .map_err(to_rpc_error)?;
+        let curr_block_agg_proof = GeneratedAggProof::from_bytes(
+            &decode_hex(&params.curr_block_agg_proof).map_err(to_rpc_error)?,
+            &digests.aggregation,
+        )
+        .map_err(to_rpc_error)?;
+        let other_data: OtherBlockData = decode(&params.other_data)?;
+        let _admitted = self
+            .admit(ProofKey::for_block(
+                curr_block_agg_proof.common.b_height,
+                digests.block,
+            ))
+            .await?;
+
+        let proof = generate_block_proof_async(
+            self.p_state.clone(),
+            prev_opt_parent_b_proof,
+            curr_block_agg_proof,
+            other_data,
+            self.opts.clone(),
+        )
+        .await
+        .map_err(to_rpc_error)?;
+
+        encode_proof(proof.to_bytes(&digests.block))
+    }
+
+    async fn verify(&self, params: VerifyParams) -> Result<VerifyResult, ErrorObjectOwned> {
+        let bytes = decode_hex(&params.proof.bytes).map_err(to_rpc_error)?;
+        let kind = params.proof.kind;
+        let p_state = self.p_state.clone();
+        let digests = p_state.circuit_digests();
+
+        // Verification takes long enough that it should not block the
+        // executor.
+        let result = task::spawn_blocking(move || match kind {
+            RpcProofKind::Txn => GeneratedTxnProof::from_bytes(&bytes, &digests.root)
+                .and_then(|proof| verify_txn_proof(&p_state, &proof)),
+            RpcProofKind::Agg => GeneratedAggProof::from_bytes(&bytes, &digests.aggregation)
+                .and_then(|proof| verify_agg_proof(&p_state, &proof)),
+            RpcProofKind::Block => GeneratedBlockProof::from_bytes(&bytes, &digests.block)
+                .and_then(|proof| verify_block_proof(&p_state, &proof)),
+        })
+        .await
+        .map_err(|err| to_rpc_error(err.into()))?;
+
+        match result {
+            Ok(()) => Ok(VerifyResult {
+                valid: true,
+                error: None,
+            }),
+            Err(err @ ProofGenError::Verification(_)) => Ok(VerifyResult {
+                valid: false,
+                error: Some(err.to_string()),
+            }),
+            Err(err) => Err(to_rpc_error(err)),
+        }
+    }
+
+    fn decode_aggregatable(&self, proof: &RpcProof) -> Result<AggregatableProof, ErrorObjectOwned> {
+        let bytes = decode_hex(&proof.bytes).map_err(to_rpc_error)?;
+        let digests = self.p_state.circuit_digests();
+
+        let proof = match proof.kind {
+            RpcProofKind::Txn => {
+                GeneratedTxnProof::from_bytes(&bytes, &digests.root).map(Into::into)
+            }
+            RpcProofKind::Agg => {
+                GeneratedAggProof::from_bytes(&bytes, &digests.aggregation).map(Into::into)
+            }
+            RpcProofKind::Block => {
+                return Err(invalid_params("Block proofs can not be aggregated"));
+            }
+        };
+
+        proof.map_err(to_rpc_error)
+    }
+}
+
+fn decode_hex(hex: &str) -> ProofGenResult<Vec<u8>> {
+    hex::decode(hex.trim_start_matches("0x"))
+        .map_err(|err| ProofGenError::Serialization(format!("Invalid hex: {}", err)))
+}
+
+fn decode<T: DeserializeOwned>(hex: &str) -> Result<T, ErrorObjectOwned> {
+    let bytes = decode_hex(hex).map_err(|err| invalid_params(&err.to_string()))?;
+    bincode::deserialize(&bytes).map_err(|err| invalid_params(&err.to_string()))
+}
+
+fn encode_proof(bytes: ProofGenResult<Vec<u8>>) -> Result<String, ErrorObjectOwned> {
+    bytes
+        .map(|bytes| format!("0x{}", hex::encode(bytes)))
+        .map_err(to_rpc_error)
+}
+
+fn invalid_params(msg: &str) -> ErrorObjectOwned {
+    ErrorObjectOwned::owned(INVALID_PARAMS_CODE, msg, None::<()>)
+}
+
+fn to_rpc_error(err: ProofGenError) -> ErrorObjectOwned {
+    let kind = match err.kind() {
+        ProofGenErrorKind::Transient => "transient",
+        ProofGenErrorKind::InvalidInput => "invalid_input",
+        ProofGenErrorKind::Prover => "prover",
+    };
+    let retry_after_ms = match &err {
+        ProofGenError::Overloaded { retry_after } => Some(retry_after.as_millis() as u64),
+        _ => None,
+    };
+    let data = RpcErrorData {
+        kind: kind.to_string(),
+        retryable: err.is_retryable(),
+        retry_after_ms,
+    };
+
+    ErrorObjectOwned::owned(json_rpc_error_code(&err), err.to_string(), Some(data))
+}
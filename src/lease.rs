@@ -0,0 +1,320 @@
+//! Leasing of proof jobs to distributed workers.
+//!
+//! A [`LeaseCoordinator`] hands out the ready jobs of a [`ProofDag`] to workers
+//! on other machines. Every job is leased to one worker at a time, and the
+//! worker has to renew its lease with heartbeats while it is proving. When a
+//! worker dies, its lease expires and the job is leased to the next worker
+//! that asks for one, so jobs are neither lost nor proven twice while workers
+//! are healthy.
+//!
+//! Results are handled exactly once: if two workers race on the same job (eg.
+//! because a slow worker's lease expired), only the first result is recorded
+//! in the DAG and stored, so the jobs depending on it are only released once.
+//!
+//! The coordinator does not talk to the workers itself: claims, heartbeats
+//! and results are passed in by whatever transport the deployment uses (eg.
+//! the [`RemoteOperation`](crate::remote::RemoteOperation)s of the `remote`
+//! feature).
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_store::ProofStore,
+    scheduler::{JobId, JobOutput, JobStatus, ProofDag, ReadyJob},
+    types::CircuitDigests,
+};
+
+/// The default time that a worker has to renew its lease in.
+pub const DEFAULT_LEASE_DURATION: Duration = Duration::from_secs(60);
+
+/// Identifies a lease handed out by a [`LeaseCoordinator`].
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct LeaseId(pub u64);
+
+impl Display for LeaseId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A job leased to a worker.
+#[derive(Clone, Debug)]
+pub struct Lease {
+    pub id: LeaseId,
+    pub job_id: JobId,
+    pub job: ReadyJob,
+
+    /// The lease expires unless it is renewed with
+    /// [`LeaseCoordinator::heartbeat`] before then.
+    pub expires_at: Instant,
+}
+
+/// What happened to a result handed in with [`LeaseCoordinator::complete`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Completion {
+    /// The result was recorded (and stored, if the coordinator has a store).
+    Recorded,
+
+    /// Another worker already handed in the result of the job, so this one
+    /// was dropped.
+    Duplicate,
+}
+
+/// An active lease, as listed by [`LeaseCoordinator::leases`].
+#[derive(Clone, Debug)]
+pub struct LeaseInfo {
+    pub id: LeaseId,
+    pub job_id: JobId,
+    pub worker: String,
+    pub expires_at: Instant,
+}
+
+#[derive(Debug)]
+struct ActiveLease {
+    job_id: JobId,
+    worker: String,
+    expires_at: Instant,
+}
+
+/// Leases the jobs of a [`ProofDag`] to workers.
+#[derive(Debug)]
+pub struct LeaseCoordinator {
+    dag: ProofDag,
+    lease_duration: Duration,
+    next_id: u64,
+    active: HashMap<LeaseId, ActiveLease>,
+
+    /// The jobs of leases that expired or were revoked. A worker that was only
+    /// slow rather than dead can still hand in its result.
+    expired: HashMap<LeaseId, JobId>,
+
+    store: Option<(Arc<dyn ProofStore>, CircuitDigests)>,
+}
+
+impl LeaseCoordinator {
+    /// Lease the jobs of `dag` for [`DEFAULT_LEASE_DURATION`] at a time.
+    pub fn new(dag: ProofDag) -> Self {
+        Self {
+            dag,
+            lease_duration: DEFAULT_LEASE_DURATION,
+            next_id: 0,
+            active: HashMap::new(),
+            expired: HashMap::new(),
+            store: None,
+        }
+    }
+
+    /// Store every recorded result in `store` (with
+    /// [`ProofStore::put_if_absent`], so that coordinators sharing a store also
+    /// store every proof once).
+    ///
+    /// `digests` must be the circuit digests of the workers.
+    pub fn set_proof_store(mut self, store: Arc<dyn ProofStore>, digests: CircuitDigests) -> Self {
+        self.store = Some((store, digests));
+        self
+    }
+
+    /// Lease jobs for `lease_duration` at a time instead of for
+    /// [`DEFAULT_LEASE_DURATION`].
+    ///
+    /// Workers should send heartbeats a few times per lease duration, so that
+    /// a single lost heartbeat does not expire their lease.
+    pub fn set_lease_duration(mut self, lease_duration: Duration) -> Self {
+        self.lease_duration = lease_duration;
+        self
+    }
+
+    pub fn dag(&self) -> &ProofDag {
+        &self.dag
+    }
+
+    /// The DAG being leased, eg. to add more jobs to it.
+    pub fn dag_mut(&mut self) -> &mut ProofDag {
+        &mut self.dag
+    }
+
+    /// Stop leasing and return the DAG. Jobs that are still leased are pending
+    /// again.
+    pub fn into_dag(mut self) -> ProofDag {
+        for (_, lease) in self.active.drain() {
+            // Every leased job is running.
+            let _ = self.dag.requeue(lease.job_id);
+        }
+
+        self.dag
+    }
+
+    /// Lease the ready job with the highest priority to `worker`, if there is
+    /// one. Expired leases are reclaimed first, so their jobs can be leased
+    /// again.
+    pub fn claim(&mut self, worker: &str) -> ProofGenResult<Option<Lease>> {
+        self.reclaim_expired()?;
+
+        let Some(job_id) = self.dag.ready_jobs().first().copied() else {
+            return Ok(None);
+        };
+        let job = self.dag.take_job(job_id)?;
+
+        let id = LeaseId(self.next_id);
+        self.next_id += 1;
+        let expires_at = Instant::now() + self.lease_duration;
+        self.active.insert(
+            id,
+            ActiveLease {
+                job_id,
+                worker: worker.to_string(),
+                expires_at,
+            },
+        );
+
+        Ok(Some(Lease {
+            id,
+            job_id,
+            job,
+            expires_at,
+        }))
+    }
+
+    /// Renew lease `id`, returning when it expires now.
+    ///
+    /// Fails with `ProofGenError::LeaseExpired` if the lease has already
+    /// expired (or the job was done by another worker), in which case the
+    /// worker should stop proving the job.
+    pub fn heartbeat(&mut self, id: LeaseId) -> ProofGenResult<Instant> {
+        let now = Instant::now();
+        match self.active.get_mut(&id) {
+            Some(lease) if lease.expires_at > now => {
+                lease.expires_at = now + self.lease_duration;
+                Ok(lease.expires_at)
+            }
+            _ => Err(ProofGenError::LeaseExpired(id.0)),
+        }
+    }
+
+    /// Record the result of the job of lease `id`.
+    ///
+    /// Results for expired leases are still accepted if nobody has handed in
+    /// the result of the job yet, in which case the lease of the worker that
+    /// took the job over is revoked. If the job is already done, the result is
+    /// dropped as a [`Completion::Duplicate`]. Fails with
+    /// `ProofGenError::LeaseExpired` for leases that were never handed out.
+    pub fn complete(&mut self, id: LeaseId, output: JobOutput) -> ProofGenResult<Completion> {
+        let job_id = match self.active.remove(&id) {
+            Some(lease) => lease.job_id,
+            None => {
+                let job_id = self
+                    .expired
+                    .remove(&id)
+                    .ok_or(ProofGenError::LeaseExpired(id.0))?;
+                if self.dag.status(job_id) == Some(JobStatus::Done) {
+                    return Ok(Completion::Duplicate);
+                }
+
+                self.take_over_job(job_id)?;
+                job_id
+            }
+        };
+
+        self.dag.complete(job_id, output)?;
+
+        // Store the proof after recording it, so that a failing store does not
+        // cause the job to be proven again.
+        if let (Some((store, digests)), Some(output)) = (&self.store, self.dag.output(job_id)) {
+            output.put_if_absent(store.as_ref(), digests)?;
+        }
+
+        Ok(Completion::Recorded)
+    }
+
+    /// Record that the job of lease `id` failed. The job stays failed until it
+    /// is retried with [`ProofDag::retry`].
+    pub fn fail(&mut self, id: LeaseId) -> ProofGenResult<()> {
+        let lease = self
+            .active
+            .remove(&id)
+            .ok_or(ProofGenError::LeaseExpired(id.0))?;
+
+        self.dag.fail(lease.job_id)
+    }
+
+    /// Give lease `id` back without a result (eg. because the worker is
+    /// shutting down), so that its job can be leased to another worker right
+    /// away.
+    pub fn release(&mut self, id: LeaseId) -> ProofGenResult<()> {
+        let lease = self
+            .active
+            .remove(&id)
+            .ok_or(ProofGenError::LeaseExpired(id.0))?;
+
+        self.dag.requeue(lease.job_id)
+    }
+
+    /// Make the jobs of every expired lease pending again. Returns the jobs.
+    pub fn reclaim_expired(&mut self) -> ProofGenResult<Vec<JobId>> {
+        let now = Instant::now();
+        let expired: Vec<_> = self
+            .active
+            .iter()
+            .filter(|(_, lease)| lease.expires_at <= now)
+            .map(|(id, _)| *id)
+            .collect();
+
+        let mut job_ids = Vec::with_capacity(expired.len());
+        for id in expired {
+            let lease = self.active.remove(&id).expect("Lease is active");
+            self.dag.requeue(lease.job_id)?;
+            self.expired.insert(id, lease.job_id);
+            job_ids.push(lease.job_id);
+        }
+
+        Ok(job_ids)
+    }
+
+    /// The leases that are currently active.
+    pub fn leases(&self) -> Vec<LeaseInfo> {
+        let mut leases: Vec<_> = self
+            .active
+            .iter()
+            .map(|(id, lease)| LeaseInfo {
+                id: *id,
+                job_id: lease.job_id,
+                worker: lease.worker.clone(),
+                expires_at: lease.expires_at,
+            })
+            .collect();
+        leases.sort_by_key(|lease| lease.id);
+
+        leases
+    }
+
+    /// Makes the job of an expired lease running again, revoking the lease of
+    /// the worker that took it over (if any).
+    fn take_over_job(&mut self, job_id: JobId) -> ProofGenResult<()> {
+        let took_over = self
+            .active
+            .iter()
+            .find(|(_, lease)| lease.job_id == job_id)
+            .map(|(id, _)| *id);
+
+        match took_over {
+            Some(id) => {
+                // Its result is a duplicate now.
+                self.active.remove(&id);
+                self.expired.insert(id, job_id);
+            }
+            None => {
+                self.dag.take_job(job_id)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,4 +1,64 @@
+pub mod abi;
+#[cfg(feature = "async")]
+pub mod admission;
+pub mod aggregation;
+#[cfg(feature = "async")]
+pub mod async_proof_gen;
+pub mod audit;
+pub mod backend;
+pub mod chain;
+pub mod chain_config;
+mod circuit_cache;
+pub mod circuit_sets;
+pub mod compression;
+pub mod dummy;
+pub mod estimation;
+pub mod in_flight;
+#[cfg(feature = "json-rpc")]
+pub mod json_rpc;
+pub mod lease;
+pub mod manifest;
+pub mod memory;
+pub mod metadata;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "nats")]
+pub mod nats_consumer;
+#[cfg(feature = "object-store")]
+pub mod object_proof_store;
+pub mod options;
+pub mod pipeline;
+pub mod profile;
+pub mod progress;
+pub mod proof_cache;
 pub mod proof_gen;
+pub mod proof_key;
+pub mod proof_store;
 pub mod proof_types;
 pub mod prover_state;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "sqlite")]
+pub mod request_queue;
+pub mod scheduler;
+pub mod selection;
+pub mod serialization;
+#[cfg(feature = "service")]
+pub mod service;
+#[cfg(feature = "signing")]
+pub mod signing;
+#[cfg(feature = "sled")]
+pub mod sled_store;
+#[cfg(feature = "otlp")]
+pub mod telemetry;
+pub mod timing_report;
+pub mod txn_cache;
 pub mod types;
+pub mod validation;
+pub mod verification;
+pub mod verifier_state;
+#[cfg(feature = "async")]
+pub mod worker;
+pub mod wrapper;
@@ -0,0 +1,258 @@
+//! Manifests of the proofs that a block proof was built from.
+//!
+//! A [`BlockProofManifest`] lists every txn and agg proof that fed a block
+//! proof, along with the hash and size of each of them, so that the full
+//! provenance of a block proof can be archived next to it and the archived
+//! proofs can later be checked against it. See
+//! [`prove_block_from_ir_with_manifest`](crate::pipeline::prove_block_from_ir_with_manifest).
+
+use std::{fs, path::Path, sync::Mutex};
+
+use ethereum_types::H256;
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::BlockProverBackend,
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_key::ProofKey,
+    proof_store::{ProofStore, StorableProof},
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    serialization::proof_hash,
+    types::{BlockHeight, CircuitDigest, CircuitDigests, TxnIdx},
+};
+
+/// The version of the manifest format, for readers of archived manifests.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// A proof listed in a [`BlockProofManifest`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ManifestEntry {
+    pub key: ProofKey,
+
+    /// The hash of the proof in the versioned binary proof format (see
+    /// [`proof_hash`]).
+    pub proof_hash: H256,
+
+    /// The size of the proof in the versioned binary proof format when the
+    /// manifest was written, in bytes. This includes the prover metadata, so
+    /// the same proof serialized again may differ slightly in size.
+    pub size_bytes: usize,
+}
+
+impl ManifestEntry {
+    pub fn for_proof<P: StorableProof>(
+        proof: &P,
+        circuit_digest: &CircuitDigest,
+    ) -> ProofGenResult<Self> {
+        let bytes = proof.to_bytes(circuit_digest)?;
+
+        Ok(Self {
+            key: proof.proof_key(*circuit_digest),
+            proof_hash: proof_hash(&bytes)?,
+            size_bytes: bytes.len(),
+        })
+    }
+
+    /// Check that `bytes` are the serialized proof that this entry lists.
+    pub fn check(&self, bytes: &[u8]) -> ProofGenResult<()> {
+        match proof_hash(bytes)? == self.proof_hash {
+            true => Ok(()),
+            false => Err(ProofGenError::IncompatibleProof(format!(
+                "The {} does not match its manifest entry",
+                self.key
+            ))),
+        }
+    }
+}
+
+/// The provenance of a block proof.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BlockProofManifest {
+    pub version: u32,
+    pub b_height: BlockHeight,
+    pub circuit_digests: CircuitDigests,
+    pub block_proof: ManifestEntry,
+
+    /// The block proof of the parent block that the block proof builds on, if
+    /// any.
+    pub parent_block_proof: Option<ManifestEntry>,
+
+    /// Every txn proof (including dummy txn proofs), in txn order.
+    pub txn_proofs: Vec<ManifestEntry>,
+
+    /// Every agg proof, ordered by their first txn and then by their last
+    /// txn.
+    pub agg_proofs: Vec<ManifestEntry>,
+}
+
+impl BlockProofManifest {
+    /// The entries of every proof listed in the manifest.
+    pub fn entries(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.txn_proofs
+            .iter()
+            .chain(&self.agg_proofs)
+            .chain(self.parent_block_proof.iter())
+            .chain(std::iter::once(&self.block_proof))
+    }
+
+    /// Check that every proof listed in the manifest is in `store` and matches
+    /// its entry.
+    pub fn check_store(&self, store: &dyn ProofStore) -> ProofGenResult<()> {
+        for entry in self.entries() {
+            let bytes = store.get(&entry.key)?.ok_or_else(|| {
+                ProofGenError::InvalidInput(format!("The {} is not in the store", entry.key))
+            })?;
+            entry.check(&bytes)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> ProofGenResult<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|err| ProofGenError::Serialization(err.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> ProofGenResult<Self> {
+        serde_json::from_str(json).map_err(|err| ProofGenError::Serialization(err.to_string()))
+    }
+
+    /// Write the manifest to `path` as JSON.
+    pub fn write(&self, path: impl AsRef<Path>) -> ProofGenResult<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_json()?)
+            .map_err(|err| ProofGenError::Storage(format!("Manifest {}: {}", path.display(), err)))
+    }
+
+    /// Read a manifest written with [`BlockProofManifest::write`].
+    pub fn read(path: impl AsRef<Path>) -> ProofGenResult<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).map_err(|err| {
+            ProofGenError::Storage(format!("Manifest {}: {}", path.display(), err))
+        })?;
+
+        Self::from_json(&json)
+    }
+}
+
+/// A backend that records a manifest entry for every txn and agg proof that
+/// `backend` generates.
+pub(crate) struct ManifestRecorder<'a, B: BlockProverBackend + ?Sized> {
+    backend: &'a B,
+    txn_proofs: Mutex<Vec<ManifestEntry>>,
+    agg_proofs: Mutex<Vec<ManifestEntry>>,
+}
+
+impl<'a, B: BlockProverBackend + ?Sized> ManifestRecorder<'a, B> {
+    pub(crate) fn new(backend: &'a B) -> Self {
+        Self {
+            backend,
+            txn_proofs: Mutex::default(),
+            agg_proofs: Mutex::default(),
+        }
+    }
+
+    /// Build the manifest of `block_proof` from the recorded proofs.
+    pub(crate) fn into_manifest(
+        self,
+        block_proof: &GeneratedBlockProof,
+        parent_block_proof: Option<&GeneratedBlockProof>,
+    ) -> ProofGenResult<BlockProofManifest> {
+        let circuit_digests = self.backend.circuit_digests();
+
+        let mut txn_proofs = self.txn_proofs.into_inner().unwrap();
+        txn_proofs.sort_by_key(txn_range_key);
+        let mut agg_proofs = self.agg_proofs.into_inner().unwrap();
+        agg_proofs.sort_by_key(txn_range_key);
+
+        Ok(BlockProofManifest {
+            version: MANIFEST_VERSION,
+            b_height: block_proof.b_height,
+            circuit_digests,
+            block_proof: ManifestEntry::for_proof(block_proof, &circuit_digests.block)?,
+            parent_block_proof: parent_block_proof
+                .map(|parent| ManifestEntry::for_proof(parent, &circuit_digests.block))
+                .transpose()?,
+            txn_proofs,
+            agg_proofs,
+        })
+    }
+}
+
+impl<B: BlockProverBackend + ?Sized> BlockProverBackend for ManifestRecorder<'_, B> {
+    fn generate_txn_proof(
+        &self,
+        start_info: TxnProofGenIR,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedTxnProof> {
+        let proof = self.backend.generate_txn_proof(start_info, opts)?;
+        let entry = ManifestEntry::for_proof(&proof, &self.circuit_digests().root)?;
+        self.txn_proofs.lock().unwrap().push(entry);
+
+        Ok(proof)
+    }
+
+    fn generate_agg_proof(
+        &self,
+        lhs_child: &AggregatableProof,
+        rhs_child: &AggregatableProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedAggProof> {
+        let proof = self
+            .backend
+            .generate_agg_proof(lhs_child, rhs_child, other_data, opts)?;
+        let entry = ManifestEntry::for_proof(&proof, &self.circuit_digests().aggregation)?;
+        self.agg_proofs.lock().unwrap().push(entry);
+
+        Ok(proof)
+    }
+
+    fn generate_block_proof(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: &GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        self.backend.generate_block_proof(
+            prev_opt_parent_b_proof,
+            curr_block_agg_proof,
+            other_data,
+            opts,
+        )
+    }
+
+    fn generate_block_proof_from_owned(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        self.backend.generate_block_proof_from_owned(
+            prev_opt_parent_b_proof,
+            curr_block_agg_proof,
+            other_data,
+            opts,
+        )
+    }
+
+    fn circuit_digests(&self) -> CircuitDigests {
+        self.backend.circuit_digests()
+    }
+}
+
+/// Orders entries by their first txn and then by their last txn. Dummy txn
+/// proofs cover an empty range, so they sort right after the txn before them.
+fn txn_range_key(entry: &ManifestEntry) -> (TxnIdx, TxnIdx) {
+    let txns = &entry
+        .key
+        .txns
+        .as_ref()
+        .expect("Txn and agg proofs cover txns")
+        .txn_idxs;
+    (txns.start, txns.end)
+}
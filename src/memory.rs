@@ -0,0 +1,48 @@
+//! Measures the peak memory usage (ie. the high-water mark of the resident set
+//! size) of proof generation.
+//!
+//! Linux only keeps the high-water mark per process, so measuring a proof
+//! resets it first (through `/proc/self/clear_refs`) and reads it once the
+//! proof is done. Proofs that are generated concurrently in the same process
+//! reset each other's high-water mark, so the measurements are only
+//! meaningful for provers that generate one proof at a time. Other platforms
+//! do not support measuring.
+
+use std::fs;
+
+/// The peak resident set size of the process since it started (or since the
+/// last [`reset_peak_rss`]), in bytes. `None` if it can not be read.
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+
+    Some(kib * 1024)
+}
+
+/// Reset the peak resident set size of the process to its current resident
+/// set size. Returns `false` if it can not be reset.
+pub fn reset_peak_rss() -> bool {
+    fs::write("/proc/self/clear_refs", "5").is_ok()
+}
+
+/// Run `f` and measure the peak resident set size of the process while it
+/// runs, if `enabled`.
+pub(crate) fn measure_peak_rss<T>(enabled: bool, f: impl FnOnce() -> T) -> (T, Option<u64>) {
+    // Without a reset, the high-water mark could be that of an earlier proof.
+    let measuring = enabled && reset_peak_rss();
+    let result = f();
+
+    let peak = match measuring {
+        true => peak_rss_bytes(),
+        false => None,
+    };
+
+    (result, peak)
+}
@@ -0,0 +1,72 @@
+//! Metadata about the prover that is embedded in every serialized proof, so
+//! that debugging a bad proof months later does not depend on external logs.
+
+use std::{
+    fs,
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{circuit_digest_hex, CircuitDigest};
+
+/// The plonky2 revision that the crate (and the circuits that it saves) is
+/// built against. Keep in sync with `Cargo.toml`.
+pub(crate) const PLONKY2_EVM_REV: &str = "32d009671a1af86312807ba2dc90e9bb4f4a94da";
+
+/// Describes the prover that serialized a proof (see
+/// [`read_prover_metadata`](crate::serialization::read_prover_metadata)).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ProverMetadata {
+    /// The version of this crate.
+    pub crate_version: String,
+
+    /// The git revision of plonky2_evm.
+    pub plonky2_evm_rev: String,
+
+    /// The digest of the circuit that the proof is a proof of, as hex.
+    pub circuit_digest: String,
+
+    /// The host name of the machine, if it could be determined.
+    pub hostname: Option<String>,
+
+    /// When the proof was serialized, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+
+    /// The peak memory usage of generating the proof, in bytes, if it was
+    /// measured (see `ProofGenOptions::set_track_peak_memory`).
+    pub peak_rss_bytes: Option<u64>,
+}
+
+impl ProverMetadata {
+    /// The metadata of this process, for a proof of the circuit with the digest
+    /// `circuit_digest`. The peak memory usage is unknown.
+    pub fn current(circuit_digest: &CircuitDigest) -> Self {
+        Self {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            plonky2_evm_rev: PLONKY2_EVM_REV.to_string(),
+            circuit_digest: circuit_digest_hex(circuit_digest),
+            hostname: hostname().clone(),
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_millis() as u64)
+                .unwrap_or_default(),
+            peak_rss_bytes: None,
+        }
+    }
+}
+
+/// The host name doesn't change while the process runs, so it is only looked up
+/// once.
+fn hostname() -> &'static Option<String> {
+    static HOSTNAME: OnceLock<Option<String>> = OnceLock::new();
+
+    HOSTNAME.get_or_init(|| {
+        std::env::var("HOSTNAME")
+            .ok()
+            .or_else(|| fs::read_to_string("/etc/hostname").ok())
+            .map(|hostname| hostname.trim().to_string())
+            .filter(|hostname| !hostname.is_empty())
+    })
+}
@@ -0,0 +1,334 @@
+//! Prometheus metrics of proof generation.
+//!
+//! [`MeteredBackend`] wraps a [`BlockProverBackend`] and records every proof
+//! that it generates in a set of [`ProverMetrics`], so that a prover fleet can
+//! be monitored and alerted on without instrumenting every call site. Queue
+//! depths are not known to the backend, so they are reported separately with
+//! [`ProverMetrics::observe_in_flight`] and [`ProverMetrics::set_queue_depth`].
+//!
+//! ```ignore
+//! let registry = Registry::new();
+//! let metrics = ProverMetrics::new(&registry)?;
+//! let backend = MeteredBackend::new(p_state, metrics.clone());
+//!
+//! // In the handler of the scrape endpoint:
+//! metrics.observe_in_flight(&in_flight);
+//! let body = encode_text(&registry)?;
+//! ```
+
+use std::time::Instant;
+
+use prometheus::{
+    exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+
+use crate::{
+    backend::BlockProverBackend,
+    in_flight::{InFlightProofs, ProofPhase},
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenErrorKind, ProofGenResult},
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    serialization::ProofKind,
+    types::CircuitDigests,
+};
+
+/// Prefixes the names of all metrics.
+const NAMESPACE: &str = "plonky_block_proof_gen";
+
+/// The metrics of proof generation. Cloning is cheap, and clones record into
+/// the same metrics.
+#[derive(Clone, Debug)]
+pub struct ProverMetrics {
+    /// Labelled by proof kind.
+    proofs_generated: IntCounterVec,
+
+    /// Labelled by proof kind and `ProofGenErrorKind`.
+    proof_failures: IntCounterVec,
+
+    /// Labelled by proof kind. Includes failed proof generations.
+    proving_duration: HistogramVec,
+
+    /// Labelled by proof kind.
+    proof_size: HistogramVec,
+
+    /// Labelled by proof kind. Only has the proofs whose peak memory usage was
+    /// measured.
+    peak_memory: HistogramVec,
+
+    /// Labelled by phase.
+    proofs_in_flight: IntGaugeVec,
+
+    /// Labelled by the name of the queue.
+    queue_depth: IntGaugeVec,
+}
+
+impl ProverMetrics {
+    /// Create the metrics and register them in `registry`.
+    ///
+    /// Fails if metrics with the same names are already registered, eg.
+    /// because `new` was called twice with the same registry. Clone the
+    /// metrics instead.
+    pub fn new(registry: &Registry) -> ProofGenResult<Self> {
+        let proofs_generated = IntCounterVec::new(
+            Opts::new("proofs_generated_total", "Proofs generated successfully")
+                .namespace(NAMESPACE),
+            &["kind"],
+        )
+        .map_err(metrics_err)?;
+        let proof_failures = IntCounterVec::new(
+            Opts::new("proof_failures_total", "Failed proof generations").namespace(NAMESPACE),
+            &["kind", "error_kind"],
+        )
+        .map_err(metrics_err)?;
+        // Root proofs take tens of seconds to minutes, agg and block proofs a
+        // few seconds.
+        let proving_duration = HistogramVec::new(
+            HistogramOpts::new("proving_duration_seconds", "Time spent generating a proof")
+                .namespace(NAMESPACE)
+                .buckets(exponential_buckets(0.5, 2.0, 12).map_err(metrics_err)?),
+            &["kind"],
+        )
+        .map_err(metrics_err)?;
+        let proof_size = HistogramVec::new(
+            HistogramOpts::new("proof_size_bytes", "Size of the generated proofs")
+                .namespace(NAMESPACE)
+                .buckets(exponential_buckets(16.0 * 1024.0, 2.0, 10).map_err(metrics_err)?),
+            &["kind"],
+        )
+        .map_err(metrics_err)?;
+        let peak_memory = HistogramVec::new(
+            HistogramOpts::new(
+                "proof_peak_rss_bytes",
+                "Peak resident set size while generating a proof",
+            )
+            .namespace(NAMESPACE)
+            .buckets(exponential_buckets(1024.0 * 1024.0 * 1024.0, 2.0, 10).map_err(metrics_err)?),
+            &["kind"],
+        )
+        .map_err(metrics_err)?;
+        let proofs_in_flight = IntGaugeVec::new(
+            Opts::new("proofs_in_flight", "Proofs that are queued or being proven")
+                .namespace(NAMESPACE),
+            &["phase"],
+        )
+        .map_err(metrics_err)?;
+        let queue_depth = IntGaugeVec::new(
+            Opts::new("queue_depth", "Number of requests waiting in a queue").namespace(NAMESPACE),
+            &["queue"],
+        )
+        .map_err(metrics_err)?;
+
+        registry
+            .register(Box::new(proofs_generated.clone()))
+            .and_then(|()| registry.register(Box::new(proof_failures.clone())))
+            .and_then(|()| registry.register(Box::new(proving_duration.clone())))
+            .and_then(|()| registry.register(Box::new(proof_size.clone())))
+            .and_then(|()| registry.register(Box::new(peak_memory.clone())))
+            .and_then(|()| registry.register(Box::new(proofs_in_flight.clone())))
+            .and_then(|()| registry.register(Box::new(queue_depth.clone())))
+            .map_err(metrics_err)?;
+
+        Ok(Self {
+            proofs_generated,
+            proof_failures,
+            proving_duration,
+            proof_size,
+            peak_memory,
+            proofs_in_flight,
+            queue_depth,
+        })
+    }
+
+    /// Record a successful proof generation.
+    pub fn record_proof(
+        &self,
+        kind: ProofKind,
+        duration_secs: f64,
+        size_bytes: usize,
+        peak_rss_bytes: Option<u64>,
+    ) {
+        let kind = kind.to_string();
+        self.proofs_generated.with_label_values(&[&kind]).inc();
+        self.proving_duration
+            .with_label_values(&[&kind])
+            .observe(duration_secs);
+        self.proof_size
+            .with_label_values(&[&kind])
+            .observe(size_bytes as f64);
+        if let Some(peak_rss_bytes) = peak_rss_bytes {
+            self.peak_memory
+                .with_label_values(&[&kind])
+                .observe(peak_rss_bytes as f64);
+        }
+    }
+
+    /// Record a failed proof generation.
+    pub fn record_failure(&self, kind: ProofKind, duration_secs: f64, err: &ProofGenError) {
+        let kind = kind.to_string();
+        self.proof_failures
+            .with_label_values(&[&kind, error_kind_label(err.kind())])
+            .inc();
+        self.proving_duration
+            .with_label_values(&[&kind])
+            .observe(duration_secs);
+    }
+
+    /// Update the in-flight gauges from the proofs tracked by `in_flight`.
+    pub fn observe_in_flight(&self, in_flight: &InFlightProofs) {
+        let proofs = in_flight.list();
+        for (phase, label) in [
+            (ProofPhase::Queued, "queued"),
+            (ProofPhase::Proving, "proving"),
+        ] {
+            let count = proofs.iter().filter(|proof| proof.phase == phase).count();
+            self.proofs_in_flight
+                .with_label_values(&[label])
+                .set(count as i64);
+        }
+    }
+
+    /// Set the depth of the queue called `queue` (eg. the length of a
+    /// `SqliteRequestQueue` or the number of pending jobs of a `ProofDag`).
+    pub fn set_queue_depth(&self, queue: &str, depth: usize) {
+        self.queue_depth
+            .with_label_values(&[queue])
+            .set(depth as i64);
+    }
+}
+
+/// Encode every metric in `registry` in the Prometheus text format, for
+/// serving from a scrape endpoint.
+pub fn encode_text(registry: &Registry) -> ProofGenResult<String> {
+    let mut buf = Vec::new();
+    TextEncoder::new()
+        .encode(&registry.gather(), &mut buf)
+        .map_err(metrics_err)?;
+
+    String::from_utf8(buf).map_err(|err| ProofGenError::Serialization(err.to_string()))
+}
+
+/// A [`BlockProverBackend`] that records every proof generated by `backend`
+/// in a set of [`ProverMetrics`].
+pub struct MeteredBackend<B> {
+    backend: B,
+    metrics: ProverMetrics,
+}
+
+impl<B: BlockProverBackend> MeteredBackend<B> {
+    pub fn new(backend: B, metrics: ProverMetrics) -> Self {
+        Self { backend, metrics }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn metrics(&self) -> &ProverMetrics {
+        &self.metrics
+    }
+
+    /// Generate a proof of `kind` with `generate` and record it, along with
+    /// the size and the peak memory usage from `stats`.
+    fn metered<P>(
+        &self,
+        kind: ProofKind,
+        stats: impl FnOnce(&P) -> (usize, Option<u64>),
+        generate: impl FnOnce() -> ProofGenResult<P>,
+    ) -> ProofGenResult<P> {
+        let started = Instant::now();
+        let result = generate();
+        let duration_secs = started.elapsed().as_secs_f64();
+
+        match &result {
+            Ok(proof) => {
+                let (size_bytes, peak_rss_bytes) = stats(proof);
+                self.metrics
+                    .record_proof(kind, duration_secs, size_bytes, peak_rss_bytes)
+            }
+            Err(err) => self.metrics.record_failure(kind, duration_secs, err),
+        }
+
+        result
+    }
+}
+
+impl<B: BlockProverBackend> BlockProverBackend for MeteredBackend<B> {
+    fn generate_txn_proof(
+        &self,
+        start_info: TxnProofGenIR,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedTxnProof> {
+        let stats = |proof: &GeneratedTxnProof| (proof.size_bytes(), proof.peak_rss_bytes);
+        self.metered(ProofKind::Txn, stats, || {
+            self.backend.generate_txn_proof(start_info, opts)
+        })
+    }
+
+    fn generate_agg_proof(
+        &self,
+        lhs_child: &AggregatableProof,
+        rhs_child: &AggregatableProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedAggProof> {
+        let stats = |proof: &GeneratedAggProof| (proof.size_bytes(), proof.peak_rss_bytes);
+        self.metered(ProofKind::Agg, stats, || {
+            self.backend
+                .generate_agg_proof(lhs_child, rhs_child, other_data, opts)
+        })
+    }
+
+    fn generate_block_proof(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: &GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        let stats = |proof: &GeneratedBlockProof| (proof.size_bytes(), proof.peak_rss_bytes);
+        self.metered(ProofKind::Block, stats, || {
+            self.backend.generate_block_proof(
+                prev_opt_parent_b_proof,
+                curr_block_agg_proof,
+                other_data,
+                opts,
+            )
+        })
+    }
+
+    fn generate_block_proof_from_owned(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        let stats = |proof: &GeneratedBlockProof| (proof.size_bytes(), proof.peak_rss_bytes);
+        self.metered(ProofKind::Block, stats, || {
+            self.backend.generate_block_proof_from_owned(
+                prev_opt_parent_b_proof,
+                curr_block_agg_proof,
+                other_data,
+                opts,
+            )
+        })
+    }
+
+    fn circuit_digests(&self) -> CircuitDigests {
+        self.backend.circuit_digests()
+    }
+}
+
+fn error_kind_label(kind: ProofGenErrorKind) -> &'static str {
+    match kind {
+        ProofGenErrorKind::Transient => "transient",
+        ProofGenErrorKind::InvalidInput => "invalid_input",
+        ProofGenErrorKind::Prover => "prover",
+    }
+}
+
+fn metrics_err(err: prometheus::Error) -> ProofGenError {
+    ProofGenError::InvalidInput(format!("Metrics: {}", err))
+}
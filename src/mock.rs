@@ -0,0 +1,185 @@
+//! A mock prover for integration tests of downstream services.
+//!
+//! [`MockProverState`] generates proofs instantly without proving anything. The
+//! proof types carry the same public values (roots, gas and block data) that
+//! real proofs of the same inputs would, but their underlying plonky2 proofs
+//! are all the same proof of a trivial circuit and will never verify against
+//! a real [`ProverState`](crate::prover_state::ProverState).
+
+use eth_trie_utils::partial_trie::PartialTrie;
+use plonky2::{
+    field::{goldilocks_field::GoldilocksField, types::Field},
+    iop::witness::{PartialWitness, WitnessWrite},
+    plonk::{
+        circuit_builder::CircuitBuilder, circuit_data::CircuitConfig,
+        config::PoseidonGoldilocksConfig,
+    },
+};
+use plonky2_evm::proof::TrieRoots;
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+
+use crate::{
+    backend::BlockProverBackend,
+    options::ProofGenOptions,
+    proof_gen::{
+        block_public_values, check_children_compatible, merge_lhs_and_rhs_deltas, ProofGenError,
+        ProofGenResult,
+    },
+    proof_types::{
+        AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof, ProofCommon,
+    },
+    prover_state::CheckpointConfig,
+    types::{CircuitDigest, CircuitDigests, PlonkyProofIntern},
+};
+
+/// A stand-in for [`ProverState`](crate::prover_state::ProverState) that
+/// generates unproven proofs instantly.
+///
+/// It implements [`BlockProverBackend`], so it can be passed to all of the
+/// orchestration helpers in place of a real prover state. Inputs are checked
+/// the same way as by the real prover (eg. child proofs must be compatible and
+/// parent block proofs are required off checkpoints), so pipelines that work
+/// against the mock should also work against the real prover.
+pub struct MockProverState {
+    /// The heights at which a block proof may be generated without a parent
+    /// block proof.
+    pub checkpoints: CheckpointConfig,
+
+    intern: PlonkyProofIntern,
+    digest: CircuitDigest,
+}
+
+impl Default for MockProverState {
+    fn default() -> Self {
+        Self::new(CheckpointConfig::default())
+    }
+}
+
+impl MockProverState {
+    pub fn new(checkpoints: CheckpointConfig) -> Self {
+        let (intern, digest) = trivial_proof();
+
+        Self {
+            checkpoints,
+            intern,
+            digest,
+        }
+    }
+}
+
+impl BlockProverBackend for MockProverState {
+    fn generate_txn_proof(
+        &self,
+        start_info: TxnProofGenIR,
+        _opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedTxnProof> {
+        let tries = &start_info.gen_inputs.tries;
+        let roots_before = TrieRoots {
+            state_root: tries.state_trie.hash(),
+            transactions_root: tries.transactions_trie.hash(),
+            receipts_root: tries.receipts_trie.hash(),
+        };
+
+        let common = ProofCommon {
+            b_height: start_info.b_height().into(),
+            deltas: start_info.deltas(),
+            roots_before,
+            roots_after: start_info.gen_inputs.trie_roots_after.clone(),
+            b_hash: start_info.gen_inputs.block_hashes.cur_hash,
+            b_timestamp: start_info.gen_inputs.block_metadata.block_timestamp,
+        };
+
+        Ok(GeneratedTxnProof {
+            txn_idx: start_info.txn_idx.into(),
+            common,
+            intern: self.intern.clone(),
+            trace_sizes: None,
+            peak_rss_bytes: None,
+        })
+    }
+
+    fn generate_agg_proof(
+        &self,
+        lhs_child: &AggregatableProof,
+        rhs_child: &AggregatableProof,
+        _other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedAggProof> {
+        check_children_compatible(lhs_child.into(), rhs_child.into(), opts)?;
+
+        let lhs = lhs_child.common();
+        let rhs = rhs_child.common();
+        let common = ProofCommon {
+            b_height: lhs.b_height,
+            deltas: merge_lhs_and_rhs_deltas(&lhs.deltas, &rhs.deltas),
+            roots_before: lhs.roots_before.clone(),
+            roots_after: rhs.roots_after.clone(),
+            b_hash: lhs.b_hash,
+            b_timestamp: lhs.b_timestamp,
+        };
+
+        Ok(GeneratedAggProof {
+            underlying_txns: lhs_child
+                .underlying_txns()
+                .combine(&rhs_child.underlying_txns()),
+            common,
+            intern: self.intern.clone(),
+            peak_rss_bytes: None,
+        })
+    }
+
+    fn generate_block_proof(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: &GeneratedAggProof,
+        other_data: OtherBlockData,
+        _opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        let b_height = curr_block_agg_proof.common.b_height;
+        if prev_opt_parent_b_proof.is_none() && !self.checkpoints.is_checkpoint(b_height) {
+            return Err(ProofGenError::MissingParentBlockProof(b_height));
+        }
+
+        Ok(GeneratedBlockProof {
+            b_height,
+            intern: self.intern.clone(),
+            withdrawals: other_data.b_data.withdrawals.clone(),
+            public_values: block_public_values(
+                curr_block_agg_proof.common.clone(),
+                &curr_block_agg_proof.underlying_txns,
+                other_data,
+            ),
+            // Nothing was proven.
+            security_bits: 0,
+            peak_rss_bytes: None,
+        })
+    }
+
+    /// All mock proofs are proofs of the same trivial circuit.
+    fn circuit_digests(&self) -> CircuitDigests {
+        CircuitDigests {
+            root: self.digest,
+            aggregation: self.digest,
+            block: self.digest,
+        }
+    }
+}
+
+/// A proof of a circuit that only has a single (zero) public input, along with
+/// the digest of the circuit. Proving it takes milliseconds.
+fn trivial_proof() -> (PlonkyProofIntern, CircuitDigest) {
+    let mut builder =
+        CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+    let target = builder.add_virtual_target();
+    builder.register_public_input(target);
+    let circuit = builder.build::<PoseidonGoldilocksConfig>();
+
+    let mut witness = PartialWitness::new();
+    witness.set_target(target, GoldilocksField::ZERO);
+
+    let proof = circuit
+        .prove(witness)
+        .expect("Proving the trivial circuit can not fail");
+
+    (proof, circuit.verifier_only.circuit_digest)
+}
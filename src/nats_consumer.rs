@@ -0,0 +1,242 @@
+//! Consumes proof requests from a NATS subject and publishes the proofs to a
+//! result subject.
+//!
+//! Consumers subscribe to the request subject as part of a queue group, so
+//! every request is delivered to exactly one of the consumers in the group and
+//! a fleet scales by simply starting more of them.
+//!
+//! Proofs easily exceed the maximum message size of a NATS server (1 MiB by
+//! default), so results are published in chunks. Requests are not chunked
+//! (the queue group would deliver their chunks to different consumers), so
+//! they must fit into a single message.
+
+use std::{
+    future::Future,
+    pin::pin,
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use async_nats::{Client, Message};
+use futures::{
+    future::{self, Either},
+    StreamExt,
+};
+use keccak_hash::keccak;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::task;
+
+use crate::{
+    backend::BlockProverBackend,
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenErrorKind, ProofGenResult},
+    remote::{split_payload, RemoteOperation},
+    scheduler::JobOutput,
+};
+
+/// Room left in every message for the fields of a [`PayloadChunk`] besides
+/// its bytes.
+///
+/// [`PayloadChunk`]: crate::remote::PayloadChunk
+const CHUNK_OVERHEAD: usize = 1024;
+
+/// Distinguishes the results published by this process.
+static RESULT_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A proof request, as published (bincode encoded) to the request subject.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProofRequestMessage {
+    /// Chosen by the requester to match up results with requests.
+    pub request_id: String,
+    pub operation: RemoteOperation,
+}
+
+/// The result of a proof request.
+///
+/// The bincode encoded result is split with
+/// [`split_payload`](crate::remote::split_payload) into chunks that fit into a
+/// message, which are published (bincode encoded) to the result subject.
+/// Subscribers reassemble the result with a
+/// [`PayloadAssembler`](crate::remote::PayloadAssembler).
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProofResultMessage {
+    pub request_id: String,
+    pub result: Result<JobOutput, ProofErrorMessage>,
+}
+
+/// A [`ProofGenError`] that happened while handling a request.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProofErrorMessage {
+    pub msg: String,
+
+    /// Whether it makes sense to publish the request again.
+    pub retryable: bool,
+
+    /// Whether the request itself was rejected, ie. publishing it again will
+    /// fail in the same way.
+    pub invalid_input: bool,
+}
+
+impl From<&ProofGenError> for ProofErrorMessage {
+    fn from(err: &ProofGenError) -> Self {
+        Self {
+            msg: err.to_string(),
+            retryable: err.is_retryable(),
+            invalid_input: err.kind() == ProofGenErrorKind::InvalidInput,
+        }
+    }
+}
+
+/// Proves the requests published to a NATS subject.
+pub struct NatsProofConsumer<B: BlockProverBackend + 'static> {
+    client: Client,
+    backend: Arc<B>,
+    opts: ProofGenOptions,
+    request_subject: String,
+    result_subject: String,
+    queue_group: String,
+}
+
+impl<B: BlockProverBackend + 'static> NatsProofConsumer<B> {
+    /// Consume the requests on `request_subject` as a member of `queue_group`
+    /// and publish the results to `result_subject`.
+    ///
+    /// Requests that were published with a reply subject get their result
+    /// published to the reply subject instead.
+    pub fn new(
+        client: Client,
+        backend: Arc<B>,
+        request_subject: impl Into<String>,
+        result_subject: impl Into<String>,
+        queue_group: impl Into<String>,
+    ) -> Self {
+        Self {
+            client,
+            backend,
+            opts: ProofGenOptions::default(),
+            request_subject: request_subject.into(),
+            result_subject: result_subject.into(),
+            queue_group: queue_group.into(),
+        }
+    }
+
+    /// Generate every proof with `opts` instead of with the default options.
+    pub fn set_options(mut self, opts: ProofGenOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    /// Handle requests until the subscription ends.
+    ///
+    /// Requests are proven one at a time, since a single proof already keeps
+    /// every core busy. Failing to handle a request does not stop the consumer,
+    /// but failing to subscribe or to publish a result does.
+    pub async fn run(&self) -> ProofGenResult<()> {
+        self.run_until(future::pending()).await
+    }
+
+    /// Like [`NatsProofConsumer::run`], but shuts down once `shutdown`
+    /// completes.
+    ///
+    /// On shutdown, the consumer unsubscribes from the request subject so that
+    /// the other members of the queue group get the new requests, finishes the
+    /// proof that it is working on, publishes its result and flushes the
+    /// client before returning. Requests that were delivered to the consumer
+    /// but not started yet are dropped, and need to be published again by the
+    /// requester.
+    pub async fn run_until(&self, shutdown: impl Future<Output = ()>) -> ProofGenResult<()> {
+        let mut requests = self
+            .client
+            .queue_subscribe(self.request_subject.clone(), self.queue_group.clone())
+            .await
+            .map_err(transport_err)?;
+        info!(
+            "Consuming proof requests on {} (queue group {})",
+            self.request_subject, self.queue_group
+        );
+
+        let mut shutdown = pin!(shutdown);
+        loop {
+            let msg = match future::select(requests.next(), shutdown.as_mut()).await {
+                Either::Left((Some(msg), _)) => msg,
+                Either::Left((None, _)) => break,
+                Either::Right(((), _)) => {
+                    info!("Shutting down, no longer consuming proof requests");
+                    requests.unsubscribe().await.map_err(transport_err)?;
+                    break;
+                }
+            };
+
+            self.handle(msg).await?;
+        }
+
+        self.client.flush().await.map_err(transport_err)
+    }
+
+    async fn handle(&self, msg: Message) -> ProofGenResult<()> {
+        let request: ProofRequestMessage = match bincode::deserialize(&msg.payload) {
+            Ok(request) => request,
+            Err(err) => {
+                warn!("Dropping undecodable proof request: {}", err);
+                return Ok(());
+            }
+        };
+
+        let result = self.prove(request.operation).await;
+        if let Err(err) = &result {
+            warn!("Proof request {} failed: {}", request.request_id, err);
+        }
+
+        let payload_id = result_payload_id(&request.request_id);
+        let result = ProofResultMessage {
+            request_id: request.request_id,
+            result: result.map_err(|err| ProofErrorMessage::from(&err)),
+        };
+        let payload = bincode::serialize(&result)
+            .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+
+        let subject = match msg.reply {
+            Some(reply) => reply.to_string(),
+            None => self.result_subject.clone(),
+        };
+        let max_chunk_size = self
+            .client
+            .server_info()
+            .max_payload
+            .saturating_sub(CHUNK_OVERHEAD);
+        for chunk in split_payload(payload_id, &payload, max_chunk_size) {
+            let chunk = bincode::serialize(&chunk)
+                .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+            self.client
+                .publish(subject.clone(), chunk.into())
+                .await
+                .map_err(transport_err)?;
+        }
+
+        Ok(())
+    }
+
+    async fn prove(&self, operation: RemoteOperation) -> ProofGenResult<JobOutput> {
+        let backend = self.backend.clone();
+        let opts = self.opts.clone();
+
+        task::spawn_blocking(move || operation.execute(&*backend, &opts)).await?
+    }
+}
+
+/// An id for the chunks of the result of `request_id`, which is unique across
+/// the consumers for all practical purposes.
+fn result_payload_id(request_id: &str) -> u64 {
+    let counter = RESULT_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let hash = keccak(format!("{}/{}/{}", request_id, process::id(), counter));
+
+    hash.to_low_u64_be()
+}
+
+fn transport_err(err: impl ToString) -> ProofGenError {
+    ProofGenError::Transport(format!("NATS: {}", err.to_string()))
+}
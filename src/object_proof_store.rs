@@ -0,0 +1,218 @@
+//! An async proof store backed by an S3-compatible object store (S3, GCS,
+//! MinIO, ...), so that workers on different machines can share the proofs of
+//! a block without a shared filesystem.
+
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+use object_store::{path::Path, ObjectStore};
+use plonky2::field::{
+    goldilocks_field::GoldilocksField,
+    types::{Field, Field64, PrimeField64},
+};
+
+use crate::{
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_key::ProofKey,
+    proof_store::{check_proof_kind, ProofStoreEntry, StorableProof},
+    serialization::ProofKind,
+    types::{circuit_digest_hex, BlockHeight, CircuitDigest, ProofUnderlyingTxns, TxnIdx},
+};
+
+/// Stores proofs as objects in an [`ObjectStore`].
+///
+/// The mirror image of [`ProofStore`](crate::proof_store::ProofStore), but
+/// async. Every proof is stored at
+/// `<prefix>/<b_height>/<txns>/<circuit_digest>/<kind>`, where `txns` is
+/// either `block` or the (exclusive) txn range `<start>-<end>`. Encoding the
+/// whole key in the object path lets the proofs of a block be listed without
+/// downloading any of them.
+#[derive(Clone, Debug)]
+pub struct ObjectProofStore {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+}
+
+impl ObjectProofStore {
+    /// Store proofs in `store` under `prefix`. `store` can be built with any of
+    /// the `object_store` builders (eg. `AmazonS3Builder` for S3 and MinIO).
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: impl Into<Path>) -> Self {
+        Self {
+            store,
+            prefix: prefix.into(),
+        }
+    }
+
+    /// Store the serialized proof `bytes` under `key`, replacing any proof
+    /// that is already stored under it.
+    pub async fn put(&self, key: &ProofKey, bytes: Vec<u8>) -> ProofGenResult<()> {
+        self.store
+            .put(&self.location(key), bytes.into())
+            .await
+            .map_err(storage_err)?;
+
+        Ok(())
+    }
+
+    /// Load the serialized bytes of the proof stored under `key`, if there is
+    /// one.
+    pub async fn get(&self, key: &ProofKey) -> ProofGenResult<Option<Vec<u8>>> {
+        match self.store.get(&self.location(key)).await {
+            Ok(res) => Ok(Some(res.bytes().await.map_err(storage_err)?.to_vec())),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(err) => Err(storage_err(err)),
+        }
+    }
+
+    /// Remove the proof stored under `key`. Returns whether there was one.
+    pub async fn delete(&self, key: &ProofKey) -> ProofGenResult<bool> {
+        let location = self.location(key);
+
+        // Most object stores do not report whether a deleted object existed.
+        match self.store.head(&location).await {
+            Ok(_) => (),
+            Err(object_store::Error::NotFound { .. }) => return Ok(false),
+            Err(err) => return Err(storage_err(err)),
+        }
+
+        match self.store.delete(&location).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(true),
+            Err(err) => Err(storage_err(err)),
+        }
+    }
+
+    /// All entries in the store for the block at `b_height`.
+    pub async fn list_by_block(
+        &self,
+        b_height: BlockHeight,
+    ) -> ProofGenResult<Vec<ProofStoreEntry>> {
+        let block_prefix = self.prefix.child(b_height.to_string());
+        let objects: Vec<_> = self
+            .store
+            .list(Some(&block_prefix))
+            .try_collect()
+            .await
+            .map_err(storage_err)?;
+
+        objects
+            .into_iter()
+            .map(|meta| {
+                self.parse_location(&meta.location)
+                    .map(|key| ProofStoreEntry {
+                        key,
+                        size_bytes: meta.size,
+                    })
+                    .ok_or_else(|| invalid_location_err(&meta.location))
+            })
+            .collect()
+    }
+
+    /// Store `proof`, replacing any proof with the same key. Returns the key
+    /// that the proof was stored under.
+    ///
+    /// `circuit_digest` must be the digest of the circuit that the proof is a
+    /// proof of.
+    pub async fn put_proof<P: StorableProof>(
+        &self,
+        proof: &P,
+        circuit_digest: &CircuitDigest,
+    ) -> ProofGenResult<ProofKey> {
+        let key = proof.proof_key(*circuit_digest);
+        self.put(&key, proof.to_bytes(circuit_digest)?).await?;
+
+        Ok(key)
+    }
+
+    /// Load the proof stored under `key`, if there is one.
+    pub async fn get_proof<P: StorableProof>(&self, key: &ProofKey) -> ProofGenResult<Option<P>> {
+        check_proof_kind::<P>(key)?;
+
+        self.get(key)
+            .await?
+            .map(|bytes| P::from_bytes(&bytes, &key.circuit_digest))
+            .transpose()
+    }
+
+    fn location(&self, key: &ProofKey) -> Path {
+        let txns = match &key.txns {
+            Some(txns) => format!("{}-{}", txns.txn_idxs.start, txns.txn_idxs.end),
+            None => "block".to_string(),
+        };
+
+        self.prefix
+            .child(key.b_height.to_string())
+            .child(txns)
+            .child(circuit_digest_hex(&key.circuit_digest))
+            .child(kind_to_str(key.kind))
+    }
+
+    fn parse_location(&self, location: &Path) -> Option<ProofKey> {
+        let parts: Vec<_> = location.prefix_match(&self.prefix)?.collect();
+        let [b_height, txns, digest, kind] = parts.as_slice() else {
+            return None;
+        };
+
+        let txns = match txns.as_ref() {
+            "block" => None,
+            txns => {
+                let (start, end) = txns.split_once('-')?;
+                Some(ProofUnderlyingTxns::from(
+                    TxnIdx(start.parse().ok()?)..TxnIdx(end.parse().ok()?),
+                ))
+            }
+        };
+
+        Some(ProofKey {
+            b_height: BlockHeight(b_height.as_ref().parse().ok()?),
+            txns,
+            kind: kind_from_str(kind.as_ref())?,
+            circuit_digest: digest_from_hex(digest.as_ref())?,
+        })
+    }
+}
+
+fn kind_to_str(kind: ProofKind) -> &'static str {
+    match kind {
+        ProofKind::Txn => "txn",
+        ProofKind::Agg => "agg",
+        ProofKind::Block => "block",
+    }
+}
+
+fn kind_from_str(kind: &str) -> Option<ProofKind> {
+    match kind {
+        "txn" => Some(ProofKind::Txn),
+        "agg" => Some(ProofKind::Agg),
+        "block" => Some(ProofKind::Block),
+        _ => None,
+    }
+}
+
+fn digest_from_hex(hex: &str) -> Option<CircuitDigest> {
+    let mut elements = [GoldilocksField::ZERO; 4];
+    if hex.len() != 16 * elements.len() {
+        return None;
+    }
+
+    for (i, elem) in elements.iter_mut().enumerate() {
+        let val = u64::from_str_radix(hex.get(16 * i..16 * (i + 1))?, 16).ok()?;
+        if val >= GoldilocksField::ORDER {
+            return None;
+        }
+
+        *elem = GoldilocksField::from_canonical_u64(val);
+    }
+
+    Some(CircuitDigest { elements })
+}
+
+fn invalid_location_err(location: &Path) -> ProofGenError {
+    ProofGenError::Storage(format!(
+        "Unexpected object in the proof store: {}",
+        location
+    ))
+}
+
+fn storage_err(err: object_store::Error) -> ProofGenError {
+    ProofGenError::Storage(err.to_string())
+}
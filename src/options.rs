@@ -0,0 +1,185 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::{
+    progress::{ProofGenPhase, ProofProgress},
+    proof_gen::{ProofGenError, ProofGenResult},
+};
+
+/// Per-call options for the `generate_*_proof` functions.
+///
+/// The default options never abort or time out, report no progress, do not
+/// validate txn IR, only allow aggregating contiguous txn ranges and prove on
+/// rayon's global thread pool and do not measure peak memory.
+#[derive(Clone, Default)]
+pub struct ProofGenOptions {
+    abort_signal: Option<Arc<AtomicBool>>,
+    progress: Option<Arc<dyn ProofProgress>>,
+    max_proving_duration: Option<Duration>,
+    thread_pool: Option<Arc<ThreadPool>>,
+    pub(crate) validate_ir: bool,
+    pub(crate) check_circuit_sizes: bool,
+    pub(crate) allow_non_contiguous_txns: bool,
+    pub(crate) track_peak_memory: bool,
+}
+
+impl ProofGenOptions {
+    /// Setting `abort_signal` to `true` aborts proof generation with
+    /// `ProofGenError::Cancelled` if it has not started proving yet. Proofs
+    /// that are already being proven are finished and returned.
+    pub fn set_abort_signal(mut self, abort_signal: Arc<AtomicBool>) -> Self {
+        self.abort_signal = Some(abort_signal);
+        self
+    }
+
+    /// Report each proving phase to `progress` as it is entered.
+    pub fn set_progress(mut self, progress: Arc<dyn ProofProgress>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Abort proof generation with `ProofGenError::TimedOut` if a single
+    /// `generate_*_proof` call has spent longer than `duration` on its checks
+    /// (eg. the witness generation of
+    /// [`ProofGenOptions::set_check_circuit_sizes`]) before it starts proving.
+    ///
+    /// This is a pre-start check only. Plonky2 can not be interrupted, so once
+    /// proving has started the proof is finished and returned however long it
+    /// takes.
+    pub fn set_max_proving_duration(mut self, duration: Duration) -> Self {
+        self.max_proving_duration = Some(duration);
+        self
+    }
+
+    /// Prove on `pool` instead of on rayon's global thread pool.
+    ///
+    /// Plonky2 parallelizes proving with rayon, so two proofs generated
+    /// concurrently on the global pool compete for all of its threads. Giving
+    /// each of them their own pool splits the cores between them instead.
+    pub fn set_thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.thread_pool = Some(pool);
+        self
+    }
+
+    /// Prove on a new thread pool with `num_threads` threads. See
+    /// [`ProofGenOptions::set_thread_pool`].
+    ///
+    /// Fails with `ProofGenError::TaskFailed` if the threads of the pool can
+    /// not be spawned.
+    pub fn set_max_threads(self, num_threads: usize) -> ProofGenResult<Self> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .map_err(|err| {
+                ProofGenError::TaskFailed(format!(
+                    "Failed to create the proving thread pool: {}",
+                    err
+                ))
+            })?;
+
+        Ok(self.set_thread_pool(Arc::new(pool)))
+    }
+
+    /// Run [`validate_ir`](crate::validation::validate_ir) on txn IR before
+    /// proving it.
+    pub fn set_validate_ir(mut self, validate_ir: bool) -> Self {
+        self.validate_ir = validate_ir;
+        self
+    }
+
+    /// Run witness generation for txns before proving them and reject the ones
+    /// whose traces do not fit the circuit sizes of the prover state with
+    /// `ProofGenError::ExceedsCircuitSizes`.
+    ///
+    /// Plonky2 only notices that a trace does not fit after STARK proving it,
+    /// so this trades the (comparatively cheap) extra witness generation for
+    /// not wasting minutes on txns that can not be proven. Note that txns this
+    /// large would need to be split into segments, which is not supported.
+    pub fn set_check_circuit_sizes(mut self, check: bool) -> Self {
+        self.check_circuit_sizes = check;
+        self
+    }
+
+    /// Allow aggregating children whose txn ranges are not directly adjacent.
+    ///
+    /// By default, the txns of the lhs child must immediately precede the txns
+    /// of the rhs child. Only disable this check if you build aggregation trees
+    /// with unusual txn ranges on purpose.
+    pub fn set_allow_non_contiguous_txns(mut self, allow: bool) -> Self {
+        self.allow_non_contiguous_txns = allow;
+        self
+    }
+
+    /// Measure the peak memory usage of proving and record it in the
+    /// `peak_rss_bytes` of the generated proofs.
+    ///
+    /// Only supported on Linux, and only meaningful if the process generates
+    /// one proof at a time (see [`memory`](crate::memory)).
+    pub fn set_track_peak_memory(mut self, track: bool) -> Self {
+        self.track_peak_memory = track;
+        self
+    }
+
+    /// Runs `op` on the thread pool of the options (if there is one).
+    pub(crate) fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        // Keeps the work on the pool inside the span of the caller.
+        #[cfg(feature = "tracing")]
+        let op = {
+            let span = tracing::Span::current();
+            move || span.in_scope(op)
+        };
+
+        match &self.thread_pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
+    /// Checks whether the caller has requested that proof generation be
+    /// aborted.
+    ///
+    /// Plonky2 can not be interrupted while it is proving, so this is only
+    /// checked before proving starts.
+    pub(crate) fn check_abort_signal(&self) -> ProofGenResult<()> {
+        match &self.abort_signal {
+            Some(signal) if signal.load(Ordering::Relaxed) => Err(ProofGenError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks whether a call that started at `started` has used up the max
+    /// proving duration before proving.
+    fn check_timeout(&self, started: Instant) -> ProofGenResult<()> {
+        match self.max_proving_duration {
+            Some(max) if started.elapsed() > max => Err(ProofGenError::TimedOut(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks for an abort (or a timeout of a call that started at `started`)
+    /// and then reports that the proving `phase` has been entered.
+    ///
+    /// Only called before proving, as aborting afterwards would throw away a
+    /// finished proof.
+    pub(crate) fn enter_phase(&self, phase: ProofGenPhase, started: Instant) -> ProofGenResult<()> {
+        self.check_abort_signal()?;
+        self.check_timeout(started)?;
+        self.report_phase(phase);
+
+        Ok(())
+    }
+
+    /// Reports that `phase` has been entered, without checking for an abort.
+    pub(crate) fn report_phase(&self, phase: ProofGenPhase) {
+        if let Some(progress) = &self.progress {
+            progress.on_phase(phase);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use ethereum_types::H256;
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    aggregation::aggregate_proofs,
+    backend::BlockProverBackend,
+    dummy::{generate_dummy_txn_proof_after, generate_empty_block_agg_proof},
+    manifest::{BlockProofManifest, ManifestRecorder},
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_key::ProofKey,
+    proof_store::{ProofStore, ProofStoreExt},
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    types::{BlockHeight, CircuitDigests},
+    validation::validate_withdrawals,
+};
+
+/// Generate a block proof directly from the proof IR of every txn in the block.
+///
+/// This proves all of the txns in parallel, aggregates the txn proofs into a
+/// single agg proof covering the entire block (see [`aggregate_proofs`]) and
+/// finally proves the block.
+///
+/// `txns` must be in txn order and be non-empty. A block with a single txn gets
+/// padded with a dummy txn during aggregation. Like with
+/// [`generate_block_proof`](crate::proof_gen::generate_block_proof),
+/// `prev_opt_parent_b_proof` is able to be `None` on checkpoint heights.
+pub fn prove_block_from_ir<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    txns: Vec<TxnProofGenIR>,
+    other_data: OtherBlockData,
+    prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedBlockProof> {
+    if txns.is_empty() {
+        return Err(ProofGenError::InvalidInput(
+            "Proving a block requires at least one txn (use `prove_empty_block` for empty blocks)"
+                .to_string(),
+        ));
+    }
+
+    if opts.validate_ir {
+        validate_withdrawals(&txns, &other_data)?;
+    }
+
+    let txn_proofs = txns
+        .into_par_iter()
+        .map(|txn| {
+            p_state
+                .generate_txn_proof(txn, opts)
+                .map(AggregatableProof::from)
+        })
+        .collect::<ProofGenResult<Vec<_>>>()?;
+
+    let agg_proof = aggregate_proofs(p_state, txn_proofs, &other_data, opts)?;
+
+    p_state.generate_block_proof_from_owned(prev_opt_parent_b_proof, agg_proof, other_data, opts)
+}
+
+/// Same as [`prove_block_from_ir`], but also returns the manifest of every txn
+/// and agg proof that the block proof was built from (see
+/// [`BlockProofManifest`]), for archiving the provenance of the block proof.
+pub fn prove_block_from_ir_with_manifest<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    txns: Vec<TxnProofGenIR>,
+    other_data: OtherBlockData,
+    prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<(GeneratedBlockProof, BlockProofManifest)> {
+    let recorder = ManifestRecorder::new(p_state);
+    let b_proof = prove_block_from_ir(&recorder, txns, other_data, prev_opt_parent_b_proof, opts)?;
+    let manifest = recorder.into_manifest(&b_proof, prev_opt_parent_b_proof)?;
+
+    Ok((b_proof, manifest))
+}
+
+/// Same as [`prove_block_from_ir`], but persists the txn, agg and block proofs
+/// in `store` as they get generated.
+///
+/// Every txn proof and every agg proof of the aggregation tree is persisted as
+/// soon as it finishes. Proofs that are already in the store are loaded instead
+/// of being proven again, and subtrees whose agg proof is in the store are
+/// skipped entirely, so calling this again after a crash (or a restart) resumes
+/// the partially proven block. To bound the memory that a store kept around for
+/// retries uses, pass a [`ProofLruCache`](crate::proof_cache::ProofLruCache).
+///
+/// Proofs are keyed by block height, so stored proofs of another block at the
+/// same height (eg. of a fork that got reorged out) are proven again (and
+/// overwritten) rather than reused.
+pub fn prove_block_from_ir_with_store<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    txns: Vec<TxnProofGenIR>,
+    other_data: OtherBlockData,
+    prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+    store: &dyn ProofStore,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedBlockProof> {
+    let b_height = match txns.first() {
+        Some(txn) => BlockHeight::from(txn.b_height()),
+        None => {
+            return Err(ProofGenError::InvalidInput(
+                "Proving a block requires at least one txn (use `prove_empty_block` for empty \
+                 blocks)"
+                    .to_string(),
+            ))
+        }
+    };
+
+    if opts.validate_ir {
+        validate_withdrawals(&txns, &other_data)?;
+    }
+
+    let digests = p_state.circuit_digests();
+    let block_key = ProofKey::for_block(b_height, digests.block);
+    let b_hash = other_data.b_data.b_hashes.cur_hash;
+    let stored_b_proof = store
+        .get_proof::<GeneratedBlockProof>(&block_key)?
+        .filter(|b_proof| b_proof.public_values.block_hashes.cur_hash == b_hash);
+    if let Some(b_proof) = stored_b_proof {
+        return Ok(b_proof);
+    }
+
+    let resumable = ResumableAggregation {
+        p_state,
+        store,
+        digests,
+        other_data: &other_data,
+        opts,
+    };
+    let agg_key = resumable.agg_key(&txns);
+    let agg_proof = match resumable.stored_agg_proof(&agg_key)? {
+        Some(agg_proof) => agg_proof,
+        None => {
+            let agg_proof = match resumable.aggregate(&txns)? {
+                AggregatableProof::Agg(agg_proof) => agg_proof,
+                // A lone txn gets aggregated with a dummy txn.
+                AggregatableProof::Txn(txn_proof) => {
+                    let dummy =
+                        generate_dummy_txn_proof_after(p_state, &txn_proof, &other_data, opts)?;
+                    p_state.generate_agg_proof(
+                        &txn_proof.into(),
+                        &dummy.into(),
+                        other_data.clone(),
+                        opts,
+                    )?
+                }
+            };
+
+            store.put_proof(&agg_proof, &digests.aggregation)?;
+            agg_proof
+        }
+    };
+
+    let b_proof = p_state.generate_block_proof_from_owned(
+        prev_opt_parent_b_proof,
+        agg_proof,
+        other_data,
+        opts,
+    )?;
+    store.put_proof(&b_proof, &digests.block)?;
+
+    Ok(b_proof)
+}
+
+/// Aggregates the proofs of txns with a balanced aggregation tree, persisting
+/// every proof of the tree as it finishes and reusing the ones that are
+/// already in the store.
+struct ResumableAggregation<'a, B: BlockProverBackend + ?Sized> {
+    p_state: &'a B,
+    store: &'a dyn ProofStore,
+    digests: CircuitDigests,
+    other_data: &'a OtherBlockData,
+    opts: &'a ProofGenOptions,
+}
+
+impl<B: BlockProverBackend + ?Sized> ResumableAggregation<'_, B> {
+    /// The key of the agg proof of `txns`, which must be non-empty.
+    fn agg_key(&self, txns: &[TxnProofGenIR]) -> ProofKey {
+        let txn_range = |ir: &TxnProofGenIR| {
+            ProofKey::for_txn_ir(ir, self.digests.root)
+                .txns
+                .expect("Txn proofs cover txns")
+        };
+        let first = txn_range(&txns[0]);
+        let last = txn_range(&txns[txns.len() - 1]);
+
+        ProofKey::for_agg(
+            BlockHeight::from(txns[0].b_height()),
+            first.combine(&last),
+            self.digests.aggregation,
+        )
+    }
+
+    /// The agg proof stored under `key`, unless it is a proof of another block
+    /// at the same height.
+    fn stored_agg_proof(&self, key: &ProofKey) -> ProofGenResult<Option<GeneratedAggProof>> {
+        let b_hash = self.other_data.b_data.b_hashes.cur_hash;
+
+        Ok(self
+            .store
+            .get_proof::<GeneratedAggProof>(key)?
+            .filter(|agg_proof| agg_proof.common.b_hash == b_hash))
+    }
+
+    /// Returns the txn proof if `txns` only contains a single txn, and an agg
+    /// proof otherwise. Assumes that `txns` is non-empty.
+    fn aggregate(&self, txns: &[TxnProofGenIR]) -> ProofGenResult<AggregatableProof> {
+        if let [txn] = txns {
+            let key = ProofKey::for_txn_ir(txn, self.digests.root);
+            let stored_txn_proof =
+                self.store
+                    .get_proof::<GeneratedTxnProof>(&key)?
+                    .filter(|txn_proof| {
+                        txn_proof.common.b_hash == txn.gen_inputs.block_hashes.cur_hash
+                            && txn_proof.common.roots_after.state_root
+                                == txn.gen_inputs.trie_roots_after.state_root
+                    });
+            if let Some(txn_proof) = stored_txn_proof {
+                return Ok(txn_proof.into());
+            }
+
+            let txn_proof = self.p_state.generate_txn_proof(txn.clone(), self.opts)?;
+            self.store.put_proof(&txn_proof, &self.digests.root)?;
+
+            return Ok(txn_proof.into());
+        }
+
+        if let Some(agg_proof) = self.stored_agg_proof(&self.agg_key(txns))? {
+            return Ok(agg_proof.into());
+        }
+
+        let (lhs_txns, rhs_txns) = txns.split_at(txns.len() / 2);
+        let (lhs, rhs) = rayon::join(|| self.aggregate(lhs_txns), || self.aggregate(rhs_txns));
+
+        let agg_proof =
+            self.p_state
+                .generate_agg_proof(&lhs?, &rhs?, self.other_data.clone(), self.opts)?;
+        self.store
+            .put_proof(&agg_proof, &self.digests.aggregation)?;
+
+        Ok(agg_proof.into())
+    }
+}
+
+/// Generate a block proof for a block that contains no txns.
+///
+/// `state_root` is the state trie root at the start of the block (ie. the state
+/// root of the parent block). Empty blocks with withdrawals are not supported
+/// (see [`generate_empty_block_agg_proof`]).
+pub fn prove_empty_block<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    state_root: H256,
+    other_data: OtherBlockData,
+    prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedBlockProof> {
+    let agg_proof = generate_empty_block_agg_proof(p_state, state_root, &other_data, opts)?;
+
+    p_state.generate_block_proof_from_owned(prev_opt_parent_b_proof, agg_proof, other_data, opts)
+}
@@ -0,0 +1,83 @@
+use plonky2::fri::{reduction_strategies::FriReductionStrategy, FriConfig};
+use plonky2_evm::config::StarkConfig;
+use serde::{Deserialize, Serialize};
+
+/// Preconfigured STARK configs, so that operators can pick a trade-off between
+/// proving time, proof size and security without tuning FRI parameters.
+///
+/// Note that plonky2 always builds the recursive circuits with its standard
+/// recursion config, so profiles only affect the STARK proofs of the tables.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ProverProfile {
+    /// The fastest proving with the smallest blowup factor FRI allows, at the
+    /// cost of larger proofs. Meant for development.
+    Fast,
+
+    /// The config that plonky2 uses for production (`standard_fast_config`).
+    #[default]
+    Standard,
+
+    /// A larger blowup factor and more proof of work for a higher (conjectured)
+    /// security level, at the cost of proving time.
+    HighSecurity,
+}
+
+impl ProverProfile {
+    pub fn stark_config(self) -> StarkConfig {
+        match self {
+            ProverProfile::Fast => StarkConfig {
+                security_bits: 100,
+                num_challenges: 2,
+                fri_config: FriConfig {
+                    rate_bits: 1,
+                    cap_height: 4,
+                    proof_of_work_bits: 16,
+                    reduction_strategy: FriReductionStrategy::ConstantArityBits(4, 5),
+                    num_query_rounds: 84,
+                },
+            },
+            ProverProfile::Standard => StarkConfig::standard_fast_config(),
+            ProverProfile::HighSecurity => StarkConfig {
+                security_bits: 128,
+                num_challenges: 2,
+                fri_config: FriConfig {
+                    rate_bits: 3,
+                    cap_height: 4,
+                    proof_of_work_bits: 20,
+                    reduction_strategy: FriReductionStrategy::ConstantArityBits(4, 5),
+                    num_query_rounds: 84,
+                },
+            },
+        }
+    }
+}
+
+/// A STARK config with a (conjectured) security level of at least
+/// `security_bits`.
+///
+/// Every FRI query adds `rate_bits` bits of security on top of the proof of
+/// work bits, which is the same estimate that plonky2 uses for its own configs.
+/// Levels above 100 bits use a larger blowup factor to keep the number of
+/// queries (and therefore the proof size) down.
+pub fn stark_config_for_security_bits(security_bits: usize) -> StarkConfig {
+    let (rate_bits, proof_of_work_bits) = match security_bits {
+        0..=100 => (2, 16),
+        _ => (3, 20),
+    };
+    let num_query_rounds = security_bits
+        .saturating_sub(proof_of_work_bits as usize)
+        .div_ceil(rate_bits)
+        .max(1);
+
+    StarkConfig {
+        security_bits,
+        num_challenges: 2,
+        fri_config: FriConfig {
+            rate_bits,
+            cap_height: 4,
+            proof_of_work_bits,
+            reduction_strategy: FriReductionStrategy::ConstantArityBits(4, 5),
+            num_query_rounds,
+        },
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use plonky2_evm::all_stark::NUM_TABLES;
+
+/// The phases of proof generation that are reported to a [`ProofProgress`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ProofGenPhase {
+    /// Witness generation, STARK proving of every table and the recursive root
+    /// proof of a txn.
+    ///
+    /// Plonky2 performs all three of these inside a single call, so they can
+    /// not be reported separately.
+    RootProving,
+
+    /// Proving an aggregation of two child proofs.
+    AggregationProving,
+
+    /// Proving a block from an agg proof (and possibly its parent block proof).
+    BlockProving,
+
+    /// The proof has been generated.
+    Finished,
+}
+
+/// Receives updates as proof generation moves through its phases.
+///
+/// Any `Fn(ProofGenPhase)` closure that is `Send + Sync` can be used as a
+/// progress callback.
+pub trait ProofProgress: Send + Sync {
+    /// Called when proof generation enters `phase`.
+    fn on_phase(&self, phase: ProofGenPhase);
+}
+
+impl<F> ProofProgress for F
+where
+    F: Fn(ProofGenPhase) + Send + Sync,
+{
+    fn on_phase(&self, phase: ProofGenPhase) {
+        self(phase)
+    }
+}
+
+/// Events reported to a [`CircuitBuildProgress`] while building the circuits
+/// of a prover state.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CircuitBuildEvent {
+    /// Building has started. `table_circuits` holds the number of recursive
+    /// circuits that will be built for every table, indexed like
+    /// [`TABLE_NAMES`](crate::types::TABLE_NAMES).
+    Started { table_circuits: [usize; NUM_TABLES] },
+
+    /// Building is still in progress. Reported every heartbeat interval.
+    ///
+    /// Plonky2 builds all circuits inside a single call without reporting its
+    /// own progress, so this is the only sign of life until building finishes.
+    StillBuilding { elapsed: Duration },
+
+    /// All circuits have been built.
+    Finished { elapsed: Duration },
+}
+
+/// Receives updates while the circuits of a prover state are being built.
+///
+/// Any `Fn(CircuitBuildEvent)` closure that is `Send + Sync` can be used as a
+/// progress callback.
+pub trait CircuitBuildProgress: Send + Sync {
+    fn on_event(&self, event: CircuitBuildEvent);
+}
+
+impl<F> CircuitBuildProgress for F
+where
+    F: Fn(CircuitBuildEvent) + Send + Sync,
+{
+    fn on_event(&self, event: CircuitBuildEvent) {
+        self(event)
+    }
+}
@@ -0,0 +1,216 @@
+//! A bounded in-memory cache of serialized proofs.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use crate::{
+    proof_gen::ProofGenResult,
+    proof_key::ProofKey,
+    proof_store::{ProofStore, ProofStoreEntry},
+    types::BlockHeight,
+};
+
+/// A [`ProofStore`] that keeps the most recently used proofs in memory, up to a
+/// total of `budget_bytes` of serialized proofs.
+///
+/// On its own, the cache simply forgets the least recently used proofs once it
+/// is full. With a backing store (see [`ProofLruCache::set_backing_store`]),
+/// every proof is also written through to the backing store, and proofs that
+/// are not in memory (anymore) are loaded from it.
+///
+/// Passing the cache to
+/// [`prove_block_from_ir_with_store`](crate::pipeline::prove_block_from_ir_with_store)
+/// keeps the intermediate proofs of a long-running pipeline around for retries
+/// without holding all of them in memory until the block proof completes.
+pub struct ProofLruCache {
+    budget_bytes: usize,
+    backing: Option<Box<dyn ProofStore>>,
+    inner: Mutex<LruInner>,
+}
+
+#[derive(Default)]
+struct LruInner {
+    entries: HashMap<ProofKey, LruEntry>,
+
+    /// The keys of the entries by when they were last used.
+    by_last_use: BTreeMap<u64, ProofKey>,
+
+    size_bytes: usize,
+    clock: u64,
+}
+
+struct LruEntry {
+    bytes: Vec<u8>,
+    last_use: u64,
+}
+
+impl ProofLruCache {
+    pub fn new(budget_bytes: usize) -> Self {
+        Self {
+            budget_bytes,
+            backing: None,
+            inner: Mutex::new(LruInner::default()),
+        }
+    }
+
+    /// Write every proof through to `store`, and load proofs that are not in
+    /// memory from it.
+    pub fn set_backing_store(mut self, store: Box<dyn ProofStore>) -> Self {
+        self.backing = Some(store);
+        self
+    }
+
+    /// The total size of the serialized proofs that are currently in memory.
+    pub fn size_bytes(&self) -> usize {
+        self.inner.lock().unwrap().size_bytes
+    }
+
+    /// The number of proofs that are currently in memory.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl LruInner {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, key: &ProofKey) -> Option<Vec<u8>> {
+        let now = self.tick();
+        let entry = self.entries.get_mut(key)?;
+
+        self.by_last_use.remove(&entry.last_use);
+        self.by_last_use.insert(now, key.clone());
+        entry.last_use = now;
+
+        Some(entry.bytes.clone())
+    }
+
+    fn insert(&mut self, key: ProofKey, bytes: Vec<u8>, budget_bytes: usize) {
+        self.remove(&key);
+
+        // A proof that does not fit at all would only evict everything else.
+        if bytes.len() > budget_bytes {
+            return;
+        }
+
+        while self.size_bytes + bytes.len() > budget_bytes {
+            let Some((_, lru_key)) = self.by_last_use.pop_first() else {
+                break;
+            };
+            if let Some(evicted) = self.entries.remove(&lru_key) {
+                self.size_bytes -= evicted.bytes.len();
+            }
+        }
+
+        let now = self.tick();
+        self.size_bytes += bytes.len();
+        self.by_last_use.insert(now, key.clone());
+        self.entries.insert(
+            key,
+            LruEntry {
+                bytes,
+                last_use: now,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &ProofKey) -> bool {
+        match self.entries.remove(key) {
+            Some(entry) => {
+                self.by_last_use.remove(&entry.last_use);
+                self.size_bytes -= entry.bytes.len();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl ProofStore for ProofLruCache {
+    fn put(&self, key: &ProofKey, bytes: &[u8]) -> ProofGenResult<()> {
+        if let Some(backing) = &self.backing {
+            backing.put(key, bytes)?;
+        }
+
+        self.inner
+            .lock()
+            .unwrap()
+            .insert(key.clone(), bytes.to_vec(), self.budget_bytes);
+
+        Ok(())
+    }
+
+    fn get(&self, key: &ProofKey) -> ProofGenResult<Option<Vec<u8>>> {
+        if let Some(bytes) = self.inner.lock().unwrap().get(key) {
+            return Ok(Some(bytes));
+        }
+
+        let Some(backing) = &self.backing else {
+            return Ok(None);
+        };
+
+        let bytes = backing.get(key)?;
+        if let Some(bytes) = &bytes {
+            self.inner
+                .lock()
+                .unwrap()
+                .insert(key.clone(), bytes.clone(), self.budget_bytes);
+        }
+
+        Ok(bytes)
+    }
+
+    fn put_if_absent(&self, key: &ProofKey, bytes: &[u8]) -> ProofGenResult<bool> {
+        // The backing store (if any) decides, since it may have proofs that
+        // are not in memory.
+        let mut inner = self.inner.lock().unwrap();
+        let stored = match &self.backing {
+            Some(backing) => backing.put_if_absent(key, bytes)?,
+            None => inner.get(key).is_none(),
+        };
+
+        if stored {
+            inner.insert(key.clone(), bytes.to_vec(), self.budget_bytes);
+        }
+
+        Ok(stored)
+    }
+
+    fn delete(&self, key: &ProofKey) -> ProofGenResult<bool> {
+        let in_memory = self.inner.lock().unwrap().remove(key);
+
+        match &self.backing {
+            Some(backing) => backing.delete(key),
+            None => Ok(in_memory),
+        }
+    }
+
+    fn list_by_block(&self, b_height: BlockHeight) -> ProofGenResult<Vec<ProofStoreEntry>> {
+        // The backing store has every proof that was ever put into the cache.
+        if let Some(backing) = &self.backing {
+            return backing.list_by_block(b_height);
+        }
+
+        Ok(self
+            .inner
+            .lock()
+            .unwrap()
+            .entries
+            .iter()
+            .filter(|(key, _)| key.b_height == b_height)
+            .map(|(key, entry)| ProofStoreEntry {
+                key: key.clone(),
+                size_bytes: entry.bytes.len(),
+            })
+            .collect())
+    }
+}
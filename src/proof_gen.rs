@@ -1,116 +1,560 @@
-use plonky2::util::timing::TimingTree;
-use plonky2_evm::{
-    all_stark::AllStark,
-    config::StarkConfig,
-    proof::{ExtraBlockData, PublicValues},
+use std::{
+    any::Any,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
 };
+
+use ethereum_types::H256;
+use plonky2::util::timing::TimingTree;
+use plonky2_evm::proof::{ExtraBlockData, PublicValues};
 use proof_protocol_decoder::{
     proof_gen_types::ProofBeforeAndAfterDeltas,
     types::{OtherBlockData, TxnProofGenIR},
 };
+use rayon::{
+    prelude::{IntoParallelIterator, ParallelIterator},
+    ThreadPool,
+};
+use thiserror::Error;
 
 use crate::{
+    estimation::{compute_trace_sizes, TraceSizes},
+    memory::measure_peak_rss,
+    options::ProofGenOptions,
+    progress::ProofGenPhase,
     proof_types::{
-        create_extra_block_data, AggregatableProof, GeneratedAggProof, GeneratedBlockProof,
-        GeneratedTxnProof, ProofCommon,
+        create_extra_block_data, AggregatableProof, AggregatableProofRef, GeneratedAggProof,
+        GeneratedBlockProof, GeneratedTxnProof, ProofCommon,
     },
     prover_state::ProverState,
-    types::{PlonkyProofIntern, ProofUnderlyingTxns},
+    types::{BlockHeight, PlonkyProofIntern, ProofUnderlyingTxns, TxnIdx},
+    validation::validate_ir,
 };
 
 pub type ProofGenResult<T> = Result<T, ProofGenError>;
 
-// Plonky2 is still using `anyhow` for proof gen, and since this is a library,
-// it's probably best if we at least convert it to a `String`.
-#[derive(Debug)]
-pub struct ProofGenError(pub String);
+// Plonky2 is still using `anyhow` for proof gen, so the underlying plonky2
+// errors are kept as (fully formatted) strings.
+#[derive(Debug, Error)]
+pub enum ProofGenError {
+    /// Plonky2 failed to prove a txn. Witness generation, STARK proving and
+    /// the recursive root proof all happen inside a single plonky2 call, so
+    /// they are reported together.
+    #[error("Failed to prove txn {txn_idx} of block {b_height}: {msg}")]
+    TxnProving {
+        b_height: BlockHeight,
+        txn_idx: TxnIdx,
+        msg: String,
+    },
+
+    /// Plonky2 failed to aggregate two child proofs.
+    #[error("Failed to aggregate txns {txns} of block {b_height}: {msg}")]
+    Aggregation {
+        b_height: BlockHeight,
+        txns: ProofUnderlyingTxns,
+        msg: String,
+    },
+
+    /// Plonky2 failed to generate (or wrap) a block proof.
+    #[error("Failed to prove block {b_height}: {msg}")]
+    BlockProving { b_height: BlockHeight, msg: String },
+
+    /// Two child proofs can not be aggregated because the lhs child does not
+    /// directly precede the rhs child.
+    #[error(
+        "Can not aggregate txns {lhs_txns} with txns {rhs_txns}: {}",
+        .differences.join("; ")
+    )]
+    IncompatibleChildren {
+        lhs_txns: ProofUnderlyingTxns,
+        rhs_txns: ProofUnderlyingTxns,
+        differences: Vec<String>,
+    },
+
+    /// Proving the txn is estimated to need more memory than the memory ceiling
+    /// of the prover state allows.
+    #[error(
+        "Proving txn {txn_idx} of block {b_height} is estimated to need {estimated} bytes of \
+         memory (ceiling is {ceiling} bytes)"
+    )]
+    ExceedsMemoryCeiling {
+        b_height: BlockHeight,
+        txn_idx: TxnIdx,
+        estimated: usize,
+        ceiling: usize,
+    },
+
+    /// The traces of the txn are too large (or too small) for the circuits of
+    /// the prover state. Txns that are too large would need to be split into
+    /// segments, which is not supported.
+    #[error(
+        "Txn {txn_idx} of block {b_height} does not fit the circuit sizes of the {} table(s)",
+        .tables.join(", ")
+    )]
+    ExceedsCircuitSizes {
+        b_height: BlockHeight,
+        txn_idx: TxnIdx,
+        tables: Vec<String>,
+    },
+
+    /// The checks before proving took longer than the max proving duration.
+    #[error("Proof generation exceeded the max proving duration of {0:?}")]
+    TimedOut(Duration),
+
+    /// Plonky2 panicked while proving. Holds the panic message.
+    #[error("Prover panicked: {0}")]
+    ProverPanic(String),
+
+    /// A proof did not pass verification.
+    #[error("Proof verification failed: {0}")]
+    Verification(String),
+
+    /// Proof generation was aborted through its abort signal.
+    #[error("Proof generation was cancelled")]
+    Cancelled,
+
+    /// A block proof was requested without a parent block proof at a height
+    /// that is not a checkpoint.
+    #[error("Block {0} is not a checkpoint height and requires a parent block proof")]
+    MissingParentBlockProof(BlockHeight),
+
+    /// The inputs passed in by the caller can not be proven.
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    /// Failed to serialize or deserialize proving data.
+    #[error("Serialization failed: {0}")]
+    Serialization(String),
+
+    /// A serialized proof is in an incompatible format or was generated for a
+    /// different circuit.
+    #[error("Incompatible proof: {0}")]
+    IncompatibleProof(String),
+
+    /// A proving task running on another thread did not complete.
+    #[error("Proving task failed to complete: {0}")]
+    TaskFailed(String),
+
+    /// Reading from or writing to a proof store failed.
+    #[error("Proof storage failed: {0}")]
+    Storage(String),
+
+    /// Sending or receiving proving messages (eg. over a message queue)
+    /// failed.
+    #[error("Proof transport failed: {0}")]
+    Transport(String),
+
+    /// The prover is already handling as many requests as it accepts. The
+    /// request should be retried after `retry_after`.
+    #[error("Prover is overloaded, retry after {retry_after:?}")]
+    Overloaded { retry_after: Duration },
+
+    /// The prover is shutting down and does not accept any more requests.
+    #[error("Prover is shutting down")]
+    ShuttingDown,
+
+    /// A worker referred to a job lease that has expired or was never handed
+    /// out. The job may have been assigned to another worker.
+    #[error("Lease {0} is not active")]
+    LeaseExpired(u64),
+
+    /// A signed proof envelope did not carry a valid signature from a trusted
+    /// signer.
+    #[error("Invalid proof signature: {0}")]
+    InvalidSignature(String),
+
+    /// Saved circuits can not be loaded because they are corrupted or were
+    /// saved by a different version or with different circuit parameters. The
+    /// circuits need to be rebuilt.
+    #[error("Circuit cache is stale, rebuild required: {0}")]
+    StaleCircuitCache(String),
+}
+
+/// A coarse classification of [`ProofGenError`]s for retry policies.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ProofGenErrorKind {
+    /// The failure was not caused by the inputs, so retrying them may succeed.
+    Transient,
+
+    /// The inputs were rejected before proving. Retrying them will fail in the
+    /// same way.
+    InvalidInput,
+
+    /// Plonky2 failed to prove (or verify) the inputs. Proving is
+    /// deterministic, so retrying will fail in the same way.
+    Prover,
+}
+
+impl ProofGenError {
+    pub fn kind(&self) -> ProofGenErrorKind {
+        match self {
+            ProofGenError::Cancelled
+            | ProofGenError::TimedOut(_)
+            | ProofGenError::TaskFailed(_)
+            | ProofGenError::Storage(_)
+            | ProofGenError::Transport(_)
+            | ProofGenError::Overloaded { .. }
+            | ProofGenError::ShuttingDown => ProofGenErrorKind::Transient,
+            ProofGenError::IncompatibleChildren { .. }
+            | ProofGenError::ExceedsMemoryCeiling { .. }
+            | ProofGenError::ExceedsCircuitSizes { .. }
+            | ProofGenError::MissingParentBlockProof(_)
+            | ProofGenError::InvalidInput(_)
+            | ProofGenError::Serialization(_)
+            | ProofGenError::IncompatibleProof(_)
+            | ProofGenError::LeaseExpired(_)
+            | ProofGenError::InvalidSignature(_)
+            | ProofGenError::StaleCircuitCache(_) => ProofGenErrorKind::InvalidInput,
+            ProofGenError::TxnProving { .. }
+            | ProofGenError::Aggregation { .. }
+            | ProofGenError::BlockProving { .. }
+            | ProofGenError::ProverPanic(_)
+            | ProofGenError::Verification(_) => ProofGenErrorKind::Prover,
+        }
+    }
 
-impl std::fmt::Display for ProofGenError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:#?}", self.0)
+    /// Whether it makes sense to retry the inputs that caused this error.
+    pub fn is_retryable(&self) -> bool {
+        self.kind() == ProofGenErrorKind::Transient
     }
 }
 
-impl std::error::Error for ProofGenError {}
+// Plonky2 panics on some witness generation edge cases. These shouldn't take
+// down the whole process (note that this only works with `panic = "unwind"`).
+pub(crate) fn catch_prover_panic<T>(prove: impl FnOnce() -> T) -> ProofGenResult<T> {
+    panic::catch_unwind(AssertUnwindSafe(prove))
+        .map_err(|payload| ProofGenError::ProverPanic(panic_payload_msg(&*payload)))
+}
 
-impl From<String> for ProofGenError {
-    fn from(v: String) -> Self {
-        Self(v)
+fn panic_payload_msg(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "Unknown panic payload".to_string()
     }
 }
 
 /// Generate a txn proof from proof IR data.
+///
+/// The proof is generated with the `AllStark` and `StarkConfig` stored in the
+/// prover state.
 pub fn generate_txn_proof(
     p_state: &ProverState,
     start_info: TxnProofGenIR,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedTxnProof> {
+    generate_txn_proof_with_timing(p_state, start_info, opts, &mut TimingTree::default())
+}
+
+/// Same as [`generate_txn_proof`], but records plonky2's timing data into
+/// `timing` so that the caller can inspect (or print) where proving time went.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "generate_txn_proof",
+        skip_all,
+        err,
+        fields(kind = "txn", b_height = %start_info.b_height(), txn_idx = %start_info.txn_idx)
+    )
+)]
+pub fn generate_txn_proof_with_timing(
+    p_state: &ProverState,
+    start_info: TxnProofGenIR,
+    opts: &ProofGenOptions,
+    timing: &mut TimingTree,
 ) -> ProofGenResult<GeneratedTxnProof> {
-    let b_height = start_info.b_height();
-    let txn_idx = start_info.txn_idx;
+    let started = Instant::now();
+
+    if opts.validate_ir {
+        validate_ir(&start_info)?;
+    }
+
+    if let Some(chain_config) = &p_state.chain_config {
+        chain_config.validate_block_metadata(&start_info.gen_inputs.block_metadata)?;
+    }
+
+    // Checking the memory ceiling or the circuit sizes requires the trace sizes,
+    // so we might as well keep them around in the proof.
+    let trace_sizes = match p_state.memory_ceiling.is_some() || opts.check_circuit_sizes {
+        true => {
+            let sizes = compute_trace_sizes(p_state, &start_info)?;
+            check_trace_sizes(p_state, &start_info, &sizes, opts)?;
+
+            Some(sizes)
+        }
+        false => None,
+    };
+
+    opts.enter_phase(ProofGenPhase::RootProving, started)?;
+
+    let b_height = BlockHeight::from(start_info.b_height());
+    let txn_idx = TxnIdx::from(start_info.txn_idx);
     let deltas = start_info.deltas();
 
-    let (txn_proof_intern, p_vals) = p_state
-        .state
-        .prove_root(
-            &AllStark::default(),
-            &StarkConfig::standard_fast_config(),
-            start_info.gen_inputs,
-            &mut TimingTree::default(),
-        )
-        .map_err(|err| err.to_string())?;
+    let (proven, peak_rss_bytes) = measure_peak_rss(opts.track_peak_memory, || {
+        catch_prover_panic(|| {
+            opts.install(|| {
+                p_state.state.prove_root(
+                    &p_state.all_stark,
+                    &p_state.stark_config,
+                    start_info.gen_inputs,
+                    timing,
+                )
+            })
+        })
+    });
+    let (txn_proof_intern, p_vals) = proven?.map_err(|err| ProofGenError::TxnProving {
+        b_height,
+        txn_idx,
+        msg: format!("{:#}", err),
+    })?;
+
+    opts.report_phase(ProofGenPhase::Finished);
 
     let common = ProofCommon {
         b_height,
         deltas,
         roots_before: p_vals.trie_roots_before,
         roots_after: p_vals.trie_roots_after,
+        b_hash: p_vals.block_hashes.cur_hash,
+        b_timestamp: p_vals.block_metadata.block_timestamp,
     };
 
     Ok(GeneratedTxnProof {
         txn_idx,
         common,
         intern: txn_proof_intern,
+        trace_sizes,
+        peak_rss_bytes,
     })
 }
 
+fn check_trace_sizes(
+    p_state: &ProverState,
+    ir: &TxnProofGenIR,
+    sizes: &TraceSizes,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<()> {
+    if let Some(ceiling) = p_state.memory_ceiling {
+        let estimated = sizes.estimated_proving_memory(&p_state.stark_config);
+        if estimated > ceiling {
+            return Err(ProofGenError::ExceedsMemoryCeiling {
+                b_height: ir.b_height().into(),
+                txn_idx: ir.txn_idx.into(),
+                estimated,
+                ceiling,
+            });
+        }
+    }
+
+    if opts.check_circuit_sizes {
+        let tables = sizes.unsupported_tables(&p_state.circuit_sizes);
+        if !tables.is_empty() {
+            return Err(ProofGenError::ExceedsCircuitSizes {
+                b_height: ir.b_height().into(),
+                txn_idx: ir.txn_idx.into(),
+                tables: tables.into_iter().map(String::from).collect(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Generate txn proofs for many txns in parallel.
+///
+/// The txns are proven on `pool` if one is given, or on rayon's global pool
+/// otherwise. Results are returned in the same order as `txns`, and a failure
+/// to prove one txn does not affect the others.
+pub fn generate_txn_proofs(
+    p_state: &ProverState,
+    txns: Vec<TxnProofGenIR>,
+    opts: &ProofGenOptions,
+    pool: Option<&ThreadPool>,
+) -> Vec<ProofGenResult<GeneratedTxnProof>> {
+    let prove_all = || {
+        txns.into_par_iter()
+            .map(|txn| generate_txn_proof(p_state, txn, opts))
+            .collect::<Vec<_>>()
+    };
+
+    match pool {
+        Some(pool) => pool.install(prove_all),
+        None => prove_all(),
+    }
+}
+
 /// Generate a agg proof from two child proofs.
 ///
-/// Note that the child proofs may be either txn or agg proofs.
+/// Note that the child proofs may be either txn or agg proofs. The children are
+/// checked to be compatible (ie. the lhs child ends in the state that the rhs
+/// child starts in and its txns immediately precede the txns of the rhs child)
+/// before anything gets proven.
 pub fn generate_agg_proof(
     p_state: &ProverState,
     lhs_child: &AggregatableProof,
     rhs_child: &AggregatableProof,
     other_data: OtherBlockData,
+    opts: &ProofGenOptions,
 ) -> ProofGenResult<GeneratedAggProof> {
-    let expanded_agg_proofs = expand_aggregatable_proofs(lhs_child, rhs_child, other_data);
+    generate_agg_proof_from_refs(
+        p_state,
+        lhs_child.into(),
+        rhs_child.into(),
+        other_data,
+        opts,
+    )
+}
 
-    let (agg_proof_intern, p_vals) = p_state
-        .state
-        .prove_aggregation(
-            expanded_agg_proofs.lhs.is_agg,
-            expanded_agg_proofs.lhs.intern,
-            expanded_agg_proofs.p_vals_lhs,
-            expanded_agg_proofs.rhs.is_agg,
-            expanded_agg_proofs.rhs.intern,
-            expanded_agg_proofs.p_vals_rhs,
+/// Same as [`generate_agg_proof`], but takes borrowed children, so txn and agg
+/// proofs do not have to be moved (or cloned) into an [`AggregatableProof`]
+/// first.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "generate_agg_proof",
+        skip_all,
+        err,
+        fields(
+            kind = "agg",
+            b_height = %lhs_child.b_height(),
+            txns = %lhs_child.underlying_txns().combine(&rhs_child.underlying_txns())
         )
-        .map_err(|err| err.to_string())?;
+    )
+)]
+pub fn generate_agg_proof_from_refs(
+    p_state: &ProverState,
+    lhs_child: AggregatableProofRef,
+    rhs_child: AggregatableProofRef,
+    other_data: OtherBlockData,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedAggProof> {
+    let started = Instant::now();
+    check_children_compatible(lhs_child, rhs_child, opts)?;
+
+    if let Some(chain_config) = &p_state.chain_config {
+        chain_config.validate_block_metadata(&other_data.b_data.b_meta)?;
+    }
+
+    opts.enter_phase(ProofGenPhase::AggregationProving, started)?;
+
+    let b_height = lhs_child.b_height();
+    let underlying_txns = lhs_child
+        .underlying_txns()
+        .combine(&rhs_child.underlying_txns());
+    let expanded_agg_proofs = expand_aggregatable_proofs(lhs_child, rhs_child, other_data);
+
+    let (proven, peak_rss_bytes) = measure_peak_rss(opts.track_peak_memory, || {
+        catch_prover_panic(|| {
+            opts.install(|| {
+                p_state.state.prove_aggregation(
+                    expanded_agg_proofs.lhs.is_agg,
+                    expanded_agg_proofs.lhs.intern,
+                    expanded_agg_proofs.p_vals_lhs,
+                    expanded_agg_proofs.rhs.is_agg,
+                    expanded_agg_proofs.rhs.intern,
+                    expanded_agg_proofs.p_vals_rhs,
+                )
+            })
+        })
+    });
+    let (agg_proof_intern, p_vals) = proven?.map_err(|err| ProofGenError::Aggregation {
+        b_height,
+        txns: underlying_txns.clone(),
+        msg: format!("{:#}", err),
+    })?;
+
+    opts.report_phase(ProofGenPhase::Finished);
 
     let common = ProofCommon {
-        b_height: lhs_child.b_height(),
+        b_height,
         deltas: expanded_agg_proofs.combined_deltas,
         roots_before: p_vals.trie_roots_before,
         roots_after: p_vals.trie_roots_after,
+        b_hash: p_vals.block_hashes.cur_hash,
+        b_timestamp: p_vals.block_metadata.block_timestamp,
     };
 
     Ok(GeneratedAggProof {
         common,
-        underlying_txns: lhs_child
-            .underlying_txns()
-            .combine(&rhs_child.underlying_txns()),
+        underlying_txns,
         intern: agg_proof_intern,
+        peak_rss_bytes,
     })
 }
 
+pub(crate) fn check_children_compatible(
+    lhs_child: AggregatableProofRef,
+    rhs_child: AggregatableProofRef,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<()> {
+    let lhs = lhs_child.common();
+    let rhs = rhs_child.common();
+    let lhs_txns = lhs_child.underlying_txns();
+    let rhs_txns = rhs_child.underlying_txns();
+    let mut differences = Vec::new();
+
+    if !opts.allow_non_contiguous_txns && lhs_txns.txn_idxs.end != rhs_txns.txn_idxs.start {
+        differences.push(format!(
+            "lhs txns end at {} but rhs txns start at {}",
+            lhs_txns.txn_idxs.end, rhs_txns.txn_idxs.start
+        ));
+    }
+
+    if lhs.b_height != rhs.b_height {
+        differences.push(format!(
+            "lhs block height {} != rhs block height {}",
+            lhs.b_height, rhs.b_height
+        ));
+    }
+
+    let roots = [
+        (
+            "state",
+            lhs.roots_after.state_root,
+            rhs.roots_before.state_root,
+        ),
+        (
+            "txn",
+            lhs.roots_after.transactions_root,
+            rhs.roots_before.transactions_root,
+        ),
+        (
+            "receipt",
+            lhs.roots_after.receipts_root,
+            rhs.roots_before.receipts_root,
+        ),
+    ];
+
+    for (trie, lhs_root_after, rhs_root_before) in roots {
+        if lhs_root_after != rhs_root_before {
+            differences.push(format!(
+                "lhs {} root after {:x} != rhs {} root before {:x}",
+                trie, lhs_root_after, trie, rhs_root_before
+            ));
+        }
+    }
+
+    if lhs.deltas.gas_used_after != rhs.deltas.gas_used_before {
+        differences.push(format!(
+            "lhs gas used after {} != rhs gas used before {}",
+            lhs.deltas.gas_used_after, rhs.deltas.gas_used_before
+        ));
+    }
+
+    match differences.is_empty() {
+        true => Ok(()),
+        false => Err(ProofGenError::IncompatibleChildren {
+            lhs_txns,
+            rhs_txns,
+            differences,
+        }),
+    }
+}
+
 struct ExpandedAggregatableProofs<'a> {
     p_vals_lhs: PublicValues,
     p_vals_rhs: PublicValues,
@@ -127,8 +571,8 @@ struct ExpandedAggregatableProof<'a> {
 // TODO: Remove or simplify, as most of this work is occurring inside plonky2
 // now.
 fn expand_aggregatable_proofs<'a>(
-    lhs_child: &'a AggregatableProof,
-    rhs_child: &'a AggregatableProof,
+    lhs_child: AggregatableProofRef<'a>,
+    rhs_child: AggregatableProofRef<'a>,
     other_data: OtherBlockData,
 ) -> ExpandedAggregatableProofs<'a> {
     let (expanded_lhs, lhs_common) = expand_aggregatable_proof(lhs_child);
@@ -180,7 +624,7 @@ fn create_extra_block_data_for_child(
     )
 }
 
-fn merge_lhs_and_rhs_deltas(
+pub(crate) fn merge_lhs_and_rhs_deltas(
     lhs: &ProofBeforeAndAfterDeltas,
     rhs: &ProofBeforeAndAfterDeltas,
 ) -> ProofBeforeAndAfterDeltas {
@@ -190,10 +634,10 @@ fn merge_lhs_and_rhs_deltas(
     }
 }
 
-fn expand_aggregatable_proof(p: &AggregatableProof) -> (ExpandedAggregatableProof, &ProofCommon) {
+fn expand_aggregatable_proof(p: AggregatableProofRef) -> (ExpandedAggregatableProof, &ProofCommon) {
     let (intern, is_agg, common) = match p {
-        AggregatableProof::Txn(txn_intern) => (&txn_intern.intern, false, &txn_intern.common),
-        AggregatableProof::Agg(agg_intern) => (&agg_intern.intern, true, &agg_intern.common),
+        AggregatableProofRef::Txn(txn_intern) => (&txn_intern.intern, false, &txn_intern.common),
+        AggregatableProofRef::Agg(agg_intern) => (&agg_intern.intern, true, &agg_intern.common),
     };
 
     let expanded = ExpandedAggregatableProof { intern, is_agg };
@@ -204,38 +648,196 @@ fn expand_aggregatable_proof(p: &AggregatableProof) -> (ExpandedAggregatableProo
 /// Generate a block proof.
 ///
 /// Note that `prev_opt_parent_b_proof` is able to be `None` on checkpoint
+/// heights (as configured in the prover state), and is required on all other
 /// heights.
 pub fn generate_block_proof(
     p_state: &ProverState,
     prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
     curr_block_agg_proof: &GeneratedAggProof,
     other_data: OtherBlockData,
+    opts: &ProofGenOptions,
 ) -> ProofGenResult<GeneratedBlockProof> {
+    check_has_parent(p_state, prev_opt_parent_b_proof, curr_block_agg_proof)?;
+
+    prove_block(
+        p_state,
+        prev_opt_parent_b_proof,
+        &curr_block_agg_proof.intern,
+        curr_block_agg_proof.common.clone(),
+        &curr_block_agg_proof.underlying_txns,
+        other_data,
+        opts,
+    )
+}
+
+/// Same as [`generate_block_proof`], but takes the agg proof by value, so that
+/// its trie roots and deltas are moved into the public values of the block
+/// rather than cloned.
+pub fn generate_block_proof_from_owned(
+    p_state: &ProverState,
+    prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+    curr_block_agg_proof: GeneratedAggProof,
+    other_data: OtherBlockData,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedBlockProof> {
+    check_has_parent(p_state, prev_opt_parent_b_proof, &curr_block_agg_proof)?;
+
+    let GeneratedAggProof {
+        underlying_txns,
+        common,
+        intern,
+        ..
+    } = curr_block_agg_proof;
+
+    prove_block(
+        p_state,
+        prev_opt_parent_b_proof,
+        &intern,
+        common,
+        &underlying_txns,
+        other_data,
+        opts,
+    )
+}
+
+fn check_has_parent(
+    p_state: &ProverState,
+    prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+    curr_block_agg_proof: &GeneratedAggProof,
+) -> ProofGenResult<()> {
     let b_height = curr_block_agg_proof.common.b_height;
+    match prev_opt_parent_b_proof.is_none() && !p_state.checkpoints.is_checkpoint(b_height) {
+        true => Err(ProofGenError::MissingParentBlockProof(b_height)),
+        false => Ok(()),
+    }
+}
+
+/// Generate a block proof for the first proven block of a chain, which has no
+/// parent block proof.
+///
+/// Instead of a parent proof, the block is anchored to `initial_state_root`,
+/// the state root that the block starts from (eg. the genesis state root, or
+/// the state root of a trusted block that proving starts after). Plonky2 links
+/// a block without a parent to the genesis state trie root of its public
+/// values, so this overrides the one in `other_data`. Unlike
+/// [`generate_block_proof`], this does not require the block to be at a
+/// checkpoint height.
+///
+/// Plonky2 also links the block to a dummy parent at the height before it, so
+/// block 0 can not be proven and is rejected with
+/// `ProofGenError::InvalidInput`.
+pub fn generate_genesis_block_proof(
+    p_state: &ProverState,
+    curr_block_agg_proof: &GeneratedAggProof,
+    mut other_data: OtherBlockData,
+    initial_state_root: H256,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedBlockProof> {
+    let state_root_before = curr_block_agg_proof.common.roots_before.state_root;
+    if state_root_before != initial_state_root {
+        return Err(ProofGenError::InvalidInput(format!(
+            "Block {} starts from state root {:x} instead of the initial state root {:x}",
+            curr_block_agg_proof.common.b_height, state_root_before, initial_state_root
+        )));
+    }
+
+    if other_data.b_data.b_meta.block_number.is_zero() {
+        return Err(ProofGenError::InvalidInput(
+            "Block 0 can not be proven without a parent block proof".to_string(),
+        ));
+    }
+
+    other_data.genesis_state_trie_root = initial_state_root;
+
+    prove_block(
+        p_state,
+        None,
+        &curr_block_agg_proof.intern,
+        curr_block_agg_proof.common.clone(),
+        &curr_block_agg_proof.underlying_txns,
+        other_data,
+        opts,
+    )
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "generate_block_proof",
+        skip_all,
+        err,
+        fields(
+            kind = "block",
+            b_height = %common.b_height,
+            txns = %underlying_txns,
+            has_parent = prev_opt_parent_b_proof.is_some()
+        )
+    )
+)]
+fn prove_block(
+    p_state: &ProverState,
+    prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+    agg_intern: &PlonkyProofIntern,
+    common: ProofCommon,
+    underlying_txns: &ProofUnderlyingTxns,
+    mut other_data: OtherBlockData,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<GeneratedBlockProof> {
+    let started = Instant::now();
+
+    if let Some(chain_config) = &p_state.chain_config {
+        chain_config.validate_block_metadata(&other_data.b_data.b_meta)?;
+    }
+
+    opts.enter_phase(ProofGenPhase::BlockProving, started)?;
+
+    let b_height = common.b_height;
     let parent_intern = prev_opt_parent_b_proof.map(|p| &p.intern);
+    // The public values do not include the withdrawals.
+    let withdrawals = mem::take(&mut other_data.b_data.withdrawals);
+    let p_vals = block_public_values(common, underlying_txns, other_data);
+
+    let (proven, peak_rss_bytes) = measure_peak_rss(opts.track_peak_memory, || {
+        catch_prover_panic(|| {
+            opts.install(|| p_state.state.prove_block(parent_intern, agg_intern, p_vals))
+        })
+    });
+    let (b_proof_intern, public_values) = proven?.map_err(|err| ProofGenError::BlockProving {
+        b_height,
+        msg: format!("{:#}", err),
+    })?;
+
+    opts.report_phase(ProofGenPhase::Finished);
 
+    Ok(GeneratedBlockProof {
+        b_height,
+        intern: b_proof_intern,
+        public_values,
+        security_bits: p_state.security_bits(),
+        withdrawals,
+        peak_rss_bytes,
+    })
+}
+
+/// The public values of the block proof for the agg proof with `common` and
+/// `underlying_txns`.
+pub(crate) fn block_public_values(
+    common: ProofCommon,
+    underlying_txns: &ProofUnderlyingTxns,
+    other_data: OtherBlockData,
+) -> PublicValues {
     let extra_block_data = create_extra_block_data(
-        curr_block_agg_proof.common.deltas.clone(),
+        common.deltas,
         other_data.genesis_state_trie_root,
-        curr_block_agg_proof.underlying_txns.txn_idxs.start,
-        curr_block_agg_proof.underlying_txns.txn_idxs.end,
+        underlying_txns.txn_idxs.start,
+        underlying_txns.txn_idxs.end,
     );
 
-    let p_vals = PublicValues {
-        trie_roots_before: curr_block_agg_proof.common.roots_before.clone(),
-        trie_roots_after: curr_block_agg_proof.common.roots_after.clone(),
+    PublicValues {
+        trie_roots_before: common.roots_before,
+        trie_roots_after: common.roots_after,
         block_metadata: other_data.b_data.b_meta,
         block_hashes: other_data.b_data.b_hashes,
         extra_block_data,
-    };
-
-    let (b_proof_intern, _) = p_state
-        .state
-        .prove_block(parent_intern, &curr_block_agg_proof.intern, p_vals)
-        .map_err(|err| err.to_string())?;
-
-    Ok(GeneratedBlockProof {
-        b_height,
-        intern: b_proof_intern,
-    })
+    }
 }
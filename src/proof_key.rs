@@ -0,0 +1,108 @@
+use std::fmt::{self, Display, Formatter};
+
+use eth_trie_utils::partial_trie::PartialTrie;
+use proof_protocol_decoder::types::TxnProofGenIR;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    proof_types::{GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    serialization::ProofKind,
+    types::{circuit_digest_hex, BlockHeight, CircuitDigest, ProofUnderlyingTxns, TxnIdx},
+};
+
+/// Uniquely identifies a generated proof.
+///
+/// Proof stores, caches and logs should all identify proofs by their key, so
+/// that every component refers to the same proof in the same way. Note that a
+/// lone txn proof and the agg proof of it with a dummy txn cover the same txns,
+/// so the kind is part of the key.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct ProofKey {
+    pub b_height: BlockHeight,
+
+    /// The txns that the proof covers. Always `None` for block proofs.
+    pub txns: Option<ProofUnderlyingTxns>,
+
+    pub kind: ProofKind,
+
+    /// The digest of the circuit that the proof is a proof of.
+    pub circuit_digest: CircuitDigest,
+}
+
+impl ProofKey {
+    /// The key of the txn proof for `ir` (which may be a dummy txn).
+    pub fn for_txn_ir(ir: &TxnProofGenIR, circuit_digest: CircuitDigest) -> Self {
+        let txn_idx = TxnIdx::from(ir.txn_idx);
+        let txns_before = ir.gen_inputs.tries.transactions_trie.hash();
+        let txns = match txns_before == ir.gen_inputs.trie_roots_after.transactions_root {
+            // A dummy txn does not execute any txn.
+            true => (txn_idx..txn_idx).into(),
+            false => (txn_idx..=txn_idx).into(),
+        };
+
+        Self {
+            b_height: ir.b_height().into(),
+            txns: Some(txns),
+            kind: ProofKind::Txn,
+            circuit_digest,
+        }
+    }
+
+    pub fn for_txn_proof(proof: &GeneratedTxnProof, circuit_digest: CircuitDigest) -> Self {
+        Self {
+            b_height: proof.common.b_height,
+            txns: Some(proof.underlying_txns()),
+            kind: ProofKind::Txn,
+            circuit_digest,
+        }
+    }
+
+    pub fn for_agg(
+        b_height: BlockHeight,
+        txns: ProofUnderlyingTxns,
+        circuit_digest: CircuitDigest,
+    ) -> Self {
+        Self {
+            b_height,
+            txns: Some(txns),
+            kind: ProofKind::Agg,
+            circuit_digest,
+        }
+    }
+
+    pub fn for_agg_proof(proof: &GeneratedAggProof, circuit_digest: CircuitDigest) -> Self {
+        Self::for_agg(
+            proof.common.b_height,
+            proof.underlying_txns.clone(),
+            circuit_digest,
+        )
+    }
+
+    pub fn for_block(b_height: BlockHeight, circuit_digest: CircuitDigest) -> Self {
+        Self {
+            b_height,
+            txns: None,
+            kind: ProofKind::Block,
+            circuit_digest,
+        }
+    }
+
+    pub fn for_block_proof(proof: &GeneratedBlockProof, circuit_digest: CircuitDigest) -> Self {
+        Self::for_block(proof.b_height, circuit_digest)
+    }
+}
+
+impl Display for ProofKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.txns {
+            Some(txns) => write!(
+                f,
+                "{} proof of txns {} of block {}",
+                self.kind, txns, self.b_height
+            )?,
+            None => write!(f, "{} proof of block {}", self.kind, self.b_height)?,
+        }
+
+        write!(f, " (circuit {})", circuit_digest_hex(&self.circuit_digest))
+    }
+}
@@ -0,0 +1,405 @@
+//! Persistence of generated proofs.
+//!
+//! [`ProofStore`] is the interface that the orchestration helpers use to
+//! persist intermediate proofs, so that a long block-proving pipeline can pick
+//! up where it left off. [`ProofFileStore`] is a store backed by a plain
+//! directory, and more backends are available behind feature flags.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use keccak_hash::keccak;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_key::ProofKey,
+    proof_types::{GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    serialization::ProofKind,
+    types::{BlockHeight, CircuitDigest},
+};
+
+const INDEX_FILE_NAME: &str = "index.bin";
+const PROOF_FILE_EXT: &str = "proof";
+const TMP_FILE_EXT: &str = "tmp";
+
+/// Numbers the temporary files written by this process, so that concurrent
+/// writes of the same file never share a temporary file.
+static TMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Temporary files that have not been written to for this long are left over
+/// from an interrupted write, rather than being written by another process
+/// sharing the directory.
+const STALE_TMP_FILE_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// The name of the file that the proof for `key` is stored in.
+fn file_name(key: &ProofKey) -> String {
+    // Serializing the key with bincode is deterministic, so the digest is
+    // stable across runs.
+    let key_bytes = bincode::serialize(key).expect("Serializing a proof key can not fail");
+    format!("{:x}.{}", keccak(key_bytes), PROOF_FILE_EXT)
+}
+
+/// A proof type that can be persisted in a [`ProofStore`].
+pub trait StorableProof: Sized {
+    const KIND: ProofKind;
+
+    /// The key of the proof, given the digest of the circuit that it is a
+    /// proof of.
+    fn proof_key(&self, circuit_digest: CircuitDigest) -> ProofKey;
+
+    fn to_bytes(&self, circuit_digest: &CircuitDigest) -> ProofGenResult<Vec<u8>>;
+
+    fn from_bytes(bytes: &[u8], circuit_digest: &CircuitDigest) -> ProofGenResult<Self>;
+}
+
+impl StorableProof for GeneratedTxnProof {
+    const KIND: ProofKind = ProofKind::Txn;
+
+    fn proof_key(&self, circuit_digest: CircuitDigest) -> ProofKey {
+        ProofKey::for_txn_proof(self, circuit_digest)
+    }
+
+    fn to_bytes(&self, circuit_digest: &CircuitDigest) -> ProofGenResult<Vec<u8>> {
+        GeneratedTxnProof::to_bytes(self, circuit_digest)
+    }
+
+    fn from_bytes(bytes: &[u8], circuit_digest: &CircuitDigest) -> ProofGenResult<Self> {
+        GeneratedTxnProof::from_bytes(bytes, circuit_digest)
+    }
+}
+
+impl StorableProof for GeneratedAggProof {
+    const KIND: ProofKind = ProofKind::Agg;
+
+    fn proof_key(&self, circuit_digest: CircuitDigest) -> ProofKey {
+        ProofKey::for_agg_proof(self, circuit_digest)
+    }
+
+    fn to_bytes(&self, circuit_digest: &CircuitDigest) -> ProofGenResult<Vec<u8>> {
+        GeneratedAggProof::to_bytes(self, circuit_digest)
+    }
+
+    fn from_bytes(bytes: &[u8], circuit_digest: &CircuitDigest) -> ProofGenResult<Self> {
+        GeneratedAggProof::from_bytes(bytes, circuit_digest)
+    }
+}
+
+impl StorableProof for GeneratedBlockProof {
+    const KIND: ProofKind = ProofKind::Block;
+
+    fn proof_key(&self, circuit_digest: CircuitDigest) -> ProofKey {
+        ProofKey::for_block_proof(self, circuit_digest)
+    }
+
+    fn to_bytes(&self, circuit_digest: &CircuitDigest) -> ProofGenResult<Vec<u8>> {
+        GeneratedBlockProof::to_bytes(self, circuit_digest)
+    }
+
+    fn from_bytes(bytes: &[u8], circuit_digest: &CircuitDigest) -> ProofGenResult<Self> {
+        GeneratedBlockProof::from_bytes(bytes, circuit_digest)
+    }
+}
+
+/// An entry of a [`ProofStore`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProofStoreEntry {
+    pub key: ProofKey,
+
+    /// The size of the serialized proof in bytes.
+    pub size_bytes: usize,
+}
+
+/// A persistence layer for serialized proofs.
+///
+/// Proofs are stored in the versioned binary proof format. Use
+/// [`ProofStoreExt`] to store and load the proof types themselves.
+pub trait ProofStore: Send + Sync {
+    /// Store the serialized proof `bytes` under `key`, replacing any proof that
+    /// is already stored under it.
+    fn put(&self, key: &ProofKey, bytes: &[u8]) -> ProofGenResult<()>;
+
+    /// Load the serialized bytes of the proof stored under `key`, if there is
+    /// one.
+    fn get(&self, key: &ProofKey) -> ProofGenResult<Option<Vec<u8>>>;
+
+    /// Store the serialized proof `bytes` under `key`, unless a proof is
+    /// already stored under it. Returns whether `bytes` were stored.
+    ///
+    /// When two workers race to produce the same proof, this makes sure that
+    /// only the first result is stored. The default implementation checks and
+    /// stores in two steps, so stores that can be shared between processes
+    /// should override it with an atomic version.
+    fn put_if_absent(&self, key: &ProofKey, bytes: &[u8]) -> ProofGenResult<bool> {
+        match self.get(key)? {
+            Some(_) => Ok(false),
+            None => {
+                self.put(key, bytes)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Remove the proof stored under `key`. Returns whether there was one.
+    fn delete(&self, key: &ProofKey) -> ProofGenResult<bool>;
+
+    /// All entries in the store for the block at `b_height`.
+    fn list_by_block(&self, b_height: BlockHeight) -> ProofGenResult<Vec<ProofStoreEntry>>;
+}
+
+/// Typed access to the proofs in a [`ProofStore`].
+pub trait ProofStoreExt: ProofStore {
+    /// Store `proof`, replacing any proof with the same key. Returns the key
+    /// that the proof was stored under.
+    ///
+    /// `circuit_digest` must be the digest of the circuit that the proof is a
+    /// proof of.
+    fn put_proof<P: StorableProof>(
+        &self,
+        proof: &P,
+        circuit_digest: &CircuitDigest,
+    ) -> ProofGenResult<ProofKey> {
+        let key = proof.proof_key(*circuit_digest);
+        self.put(&key, &proof.to_bytes(circuit_digest)?)?;
+
+        Ok(key)
+    }
+
+    /// Store `proof` unless a proof with the same key is already stored (see
+    /// [`ProofStore::put_if_absent`]). Returns the key of the proof and whether
+    /// it was stored.
+    fn put_proof_if_absent<P: StorableProof>(
+        &self,
+        proof: &P,
+        circuit_digest: &CircuitDigest,
+    ) -> ProofGenResult<(ProofKey, bool)> {
+        let key = proof.proof_key(*circuit_digest);
+        let stored = self.put_if_absent(&key, &proof.to_bytes(circuit_digest)?)?;
+
+        Ok((key, stored))
+    }
+
+    /// Load the proof stored under `key`, if there is one.
+    fn get_proof<P: StorableProof>(&self, key: &ProofKey) -> ProofGenResult<Option<P>> {
+        check_proof_kind::<P>(key)?;
+
+        self.get(key)?
+            .map(|bytes| P::from_bytes(&bytes, &key.circuit_digest))
+            .transpose()
+    }
+}
+
+impl<S: ProofStore + ?Sized> ProofStoreExt for S {}
+
+/// Checks that a proof of type `P` can be stored under `key`.
+pub(crate) fn check_proof_kind<P: StorableProof>(key: &ProofKey) -> ProofGenResult<()> {
+    match key.kind == P::KIND {
+        true => Ok(()),
+        false => Err(ProofGenError::IncompatibleProof(format!(
+            "Expected the key of a {} proof but got the key of the {}",
+            P::KIND,
+            key
+        ))),
+    }
+}
+
+/// A content-addressed [`ProofStore`] that persists proofs to a directory.
+///
+/// Every proof is written to its own file, named after a digest of its key. An
+/// index file next to the proofs records which keys are in the store. Both
+/// proofs and the index are written atomically (to a temporary file that is
+/// then renamed over the target), so a crash in the middle of a write never
+/// leaves a truncated proof behind.
+#[derive(Debug)]
+pub struct ProofFileStore {
+    dir: PathBuf,
+    index: Mutex<HashMap<ProofKey, ProofStoreEntry>>,
+}
+
+impl ProofFileStore {
+    /// Open the store in `dir`, creating the directory if it does not exist
+    /// yet.
+    ///
+    /// Index entries whose proof file has gone missing are dropped, and
+    /// temporary files left behind by an interrupted write are removed once
+    /// they are an hour old (younger ones may belong to a write of another
+    /// process using the same directory).
+    pub fn open(dir: impl AsRef<Path>) -> ProofGenResult<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|err| storage_err(&dir, err))?;
+
+        for entry in fs::read_dir(&dir).map_err(|err| storage_err(&dir, err))? {
+            let entry = entry.map_err(|err| storage_err(&dir, err))?;
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == TMP_FILE_EXT) && is_stale(&entry) {
+                match fs::remove_file(&path) {
+                    // Another process cleaned it up first.
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                    res => res.map_err(|err| storage_err(&path, err))?,
+                }
+            }
+        }
+
+        let index_path = dir.join(INDEX_FILE_NAME);
+        let index = match fs::read(&index_path) {
+            Ok(bytes) => {
+                let entries: Vec<ProofStoreEntry> = bincode::deserialize(&bytes)
+                    .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+
+                entries
+                    .into_iter()
+                    .filter(|entry| dir.join(file_name(&entry.key)).is_file())
+                    .map(|entry| (entry.key.clone(), entry))
+                    .collect()
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(storage_err(&index_path, err)),
+        };
+
+        Ok(Self {
+            dir,
+            index: Mutex::new(index),
+        })
+    }
+
+    pub fn contains(&self, key: &ProofKey) -> bool {
+        self.index.lock().unwrap().contains_key(key)
+    }
+
+    fn write_index(&self, index: &HashMap<ProofKey, ProofStoreEntry>) -> ProofGenResult<()> {
+        let entries: Vec<&ProofStoreEntry> = index.values().collect();
+        let bytes = bincode::serialize(&entries)
+            .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+
+        self.write_atomically(INDEX_FILE_NAME, &bytes)
+    }
+
+    fn write_atomically(&self, file_name: &str, bytes: &[u8]) -> ProofGenResult<()> {
+        let path = self.dir.join(file_name);
+        // Unique across processes (by the pid) and within this process (by
+        // the counter), and still ending in `TMP_FILE_EXT`.
+        let tmp_path = self.dir.join(format!(
+            "{}.{}.{}.{}",
+            file_name,
+            process::id(),
+            TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed),
+            TMP_FILE_EXT
+        ));
+
+        let write = || -> io::Result<()> {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(bytes)?;
+            file.sync_all()?;
+            fs::rename(&tmp_path, &path)
+        };
+
+        write().map_err(|err| {
+            // Best effort, `open` removes stale leftovers anyways.
+            let _ = fs::remove_file(&tmp_path);
+            storage_err(&path, err)
+        })
+    }
+}
+
+/// Whether the temporary file `entry` is old enough to be left over from an
+/// interrupted write. Files whose age is unknown are kept.
+fn is_stale(entry: &fs::DirEntry) -> bool {
+    entry
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age >= STALE_TMP_FILE_AGE)
+}
+
+impl ProofStore for ProofFileStore {
+    fn put(&self, key: &ProofKey, bytes: &[u8]) -> ProofGenResult<()> {
+        self.write_atomically(&file_name(key), bytes)?;
+
+        let mut index = self.index.lock().unwrap();
+        index.insert(
+            key.clone(),
+            ProofStoreEntry {
+                key: key.clone(),
+                size_bytes: bytes.len(),
+            },
+        );
+
+        self.write_index(&index)
+    }
+
+    fn get(&self, key: &ProofKey) -> ProofGenResult<Option<Vec<u8>>> {
+        if !self.contains(key) {
+            return Ok(None);
+        }
+
+        let path = self.dir.join(file_name(key));
+        let bytes = fs::read(&path).map_err(|err| storage_err(&path, err))?;
+
+        Ok(Some(bytes))
+    }
+
+    fn put_if_absent(&self, key: &ProofKey, bytes: &[u8]) -> ProofGenResult<bool> {
+        // Holding the index lock while writing makes the check and the write
+        // atomic within this process.
+        let mut index = self.index.lock().unwrap();
+        if index.contains_key(key) {
+            return Ok(false);
+        }
+
+        self.write_atomically(&file_name(key), bytes)?;
+        index.insert(
+            key.clone(),
+            ProofStoreEntry {
+                key: key.clone(),
+                size_bytes: bytes.len(),
+            },
+        );
+        self.write_index(&index)?;
+
+        Ok(true)
+    }
+
+    fn delete(&self, key: &ProofKey) -> ProofGenResult<bool> {
+        let mut index = self.index.lock().unwrap();
+        if index.remove(key).is_none() {
+            return Ok(false);
+        }
+
+        // The index is updated first, so a crash in between only leaves an
+        // unreferenced proof file behind.
+        self.write_index(&index)?;
+
+        let path = self.dir.join(file_name(key));
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(true),
+            Err(err) => Err(storage_err(&path, err)),
+        }
+    }
+
+    fn list_by_block(&self, b_height: BlockHeight) -> ProofGenResult<Vec<ProofStoreEntry>> {
+        Ok(self
+            .index
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|entry| entry.key.b_height == b_height)
+            .cloned()
+            .collect())
+    }
+}
+
+fn storage_err(path: &Path, err: io::Error) -> ProofGenError {
+    ProofGenError::Storage(format!("{}: {}", path.display(), err))
+}
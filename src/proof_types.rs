@@ -1,18 +1,112 @@
-use ethereum_types::H256;
-use plonky2_evm::proof::{ExtraBlockData, TrieRoots};
+use std::ops::Range;
+
+use ethereum_types::{Address, H256, U256};
+use plonky2_evm::proof::{ExtraBlockData, PublicValues, TrieRoots};
 use proof_protocol_decoder::proof_gen_types::ProofBeforeAndAfterDeltas;
 use serde::{Deserialize, Serialize};
 
-use crate::types::{BlockHeight, PlonkyProofIntern, ProofUnderlyingTxns, TxnIdx};
+use crate::{
+    estimation::TraceSizes,
+    proof_gen::{ProofGenError, ProofGenResult},
+    types::{BlockHeight, KeccakPlonkyProofIntern, PlonkyProofIntern, ProofUnderlyingTxns, TxnIdx},
+};
 
+/// The public values that txn and agg proofs have in common.
+///
+/// Txn and agg proofs do not keep the `PublicValues` that plonky2 returns, so
+/// this is the only decoded copy of them (the rest of the block metadata lives
+/// in `OtherBlockData` once per block). The block hash and timestamp are kept
+/// so that proofs can be labeled without looking up their block. It is a few
+/// hundred bytes, while the underlying plonky2 proof is hundreds of kilobytes,
+/// so an aggregation tree's memory is dominated by the proofs themselves.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ProofCommon {
     pub b_height: BlockHeight,
     pub deltas: ProofBeforeAndAfterDeltas,
     pub roots_before: TrieRoots,
     pub roots_after: TrieRoots,
+    pub b_hash: H256,
+    pub b_timestamp: U256,
+}
+
+// Neither `ProofBeforeAndAfterDeltas` nor `TrieRoots` implement `PartialEq`, so
+// we compare their fields.
+impl PartialEq for ProofCommon {
+    fn eq(&self, other: &Self) -> bool {
+        let roots_eq = |lhs: &TrieRoots, rhs: &TrieRoots| {
+            lhs.state_root == rhs.state_root
+                && lhs.transactions_root == rhs.transactions_root
+                && lhs.receipts_root == rhs.receipts_root
+        };
+
+        self.b_height == other.b_height
+            && self.b_hash == other.b_hash
+            && self.b_timestamp == other.b_timestamp
+            && self.deltas.gas_used_before == other.deltas.gas_used_before
+            && self.deltas.gas_used_after == other.deltas.gas_used_after
+            && roots_eq(&self.roots_before, &other.roots_before)
+            && roots_eq(&self.roots_after, &other.roots_after)
+    }
+}
+
+impl ProofCommon {
+    pub fn block_number(&self) -> BlockHeight {
+        self.b_height
+    }
+
+    pub fn state_root_before(&self) -> H256 {
+        self.roots_before.state_root
+    }
+
+    pub fn state_root_after(&self) -> H256 {
+        self.roots_after.state_root
+    }
+
+    /// The gas used by the txns of the proof. Fails if the gas used decreases
+    /// over the txns (which no valid proof does).
+    pub fn gas_used(&self) -> ProofGenResult<U256> {
+        checked_gas_used(
+            self.deltas.gas_used_before,
+            self.deltas.gas_used_after,
+            self.b_height,
+        )
+    }
+}
+
+/// Forwards the accessors of [`ProofCommon`] for a proof type that holds one.
+macro_rules! impl_common_accessors {
+    ($ty:ty) => {
+        impl $ty {
+            pub fn block_number(&self) -> BlockHeight {
+                self.common().block_number()
+            }
+
+            pub fn state_root_before(&self) -> H256 {
+                self.common().state_root_before()
+            }
+
+            pub fn state_root_after(&self) -> H256 {
+                self.common().state_root_after()
+            }
+
+            /// The gas used by the txns of the proof. See
+            /// [`ProofCommon::gas_used`].
+            pub fn gas_used(&self) -> ProofGenResult<U256> {
+                self.common().gas_used()
+            }
+
+            /// The txns of the block that the proof covers.
+            pub fn txn_range(&self) -> Range<TxnIdx> {
+                self.underlying_txns().txn_idxs
+            }
+        }
+    };
 }
 
+impl_common_accessors!(GeneratedTxnProof);
+impl_common_accessors!(GeneratedAggProof);
+impl_common_accessors!(AggregatableProof);
+
 pub fn create_extra_block_data(
     deltas: ProofBeforeAndAfterDeltas,
     genesis_root: H256,
@@ -33,9 +127,42 @@ pub struct GeneratedTxnProof {
     pub txn_idx: TxnIdx,
     pub common: ProofCommon,
     pub intern: PlonkyProofIntern,
+
+    /// The trace size of every STARK table. Only known if witness generation
+    /// was run separately before proving (eg. to check a memory ceiling).
+    pub trace_sizes: Option<TraceSizes>,
+
+    /// The peak memory usage of proving, in bytes. Only known if it was
+    /// measured (see `ProofGenOptions::set_track_peak_memory`).
+    ///
+    /// Not part of the serde representation of the proof, so that measuring
+    /// does not change the proof. The versioned binary proof format keeps it
+    /// in the prover metadata instead.
+    #[serde(skip)]
+    pub peak_rss_bytes: Option<u64>,
+}
+
+// The peak memory usage is not part of the proof, so the same proof measured
+// twice is still the same proof.
+impl PartialEq for GeneratedTxnProof {
+    fn eq(&self, other: &Self) -> bool {
+        self.txn_idx == other.txn_idx
+            && self.common == other.common
+            && self.intern == other.intern
+            && self.trace_sizes == other.trace_sizes
+    }
 }
 
 impl GeneratedTxnProof {
+    /// The size of the serialized proof in bytes.
+    pub fn size_bytes(&self) -> usize {
+        self.intern.to_bytes().len()
+    }
+
+    pub fn common(&self) -> &ProofCommon {
+        &self.common
+    }
+
     pub fn underlying_txns(&self) -> ProofUnderlyingTxns {
         if self.common.roots_before.transactions_root == self.common.roots_after.transactions_root {
             // This is a dummy proof no transaction was executed.
@@ -51,18 +178,164 @@ pub struct GeneratedAggProof {
     pub underlying_txns: ProofUnderlyingTxns,
     pub common: ProofCommon,
     pub intern: PlonkyProofIntern,
+
+    /// The peak memory usage of proving, in bytes. Only known if it was
+    /// measured (see `ProofGenOptions::set_track_peak_memory`).
+    ///
+    /// Not part of the serde representation of the proof, so that measuring
+    /// does not change the proof. The versioned binary proof format keeps it
+    /// in the prover metadata instead.
+    #[serde(skip)]
+    pub peak_rss_bytes: Option<u64>,
+}
+
+// Like for txn proofs, the peak memory usage is not part of the proof.
+impl PartialEq for GeneratedAggProof {
+    fn eq(&self, other: &Self) -> bool {
+        self.underlying_txns == other.underlying_txns
+            && self.common == other.common
+            && self.intern == other.intern
+    }
+}
+
+impl GeneratedAggProof {
+    /// The size of the serialized proof in bytes.
+    pub fn size_bytes(&self) -> usize {
+        self.intern.to_bytes().len()
+    }
+
+    pub fn common(&self) -> &ProofCommon {
+        &self.common
+    }
+
+    pub fn underlying_txns(&self) -> ProofUnderlyingTxns {
+        self.underlying_txns.clone()
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct GeneratedBlockProof {
     pub b_height: BlockHeight,
     pub intern: PlonkyProofIntern,
+
+    /// The public values of the block proof, including those of the parent
+    /// block proof it was aggregated with (if any).
+    pub public_values: PublicValues,
+
+    /// The (conjectured) security level of the proof in bits.
+    pub security_bits: usize,
+
+    /// The withdrawals (address and amount) processed at the end of the block.
+    ///
+    /// Plonky2 applies the withdrawals to the state trie in the last txn of
+    /// the block, so their effect is proven through the state root after the
+    /// block. The withdrawals themselves are not part of the public values
+    /// though, and there is no withdrawals root to check them against.
+    pub withdrawals: Vec<(Address, U256)>,
+
+    /// The peak memory usage of proving, in bytes. Only known if it was
+    /// measured (see `ProofGenOptions::set_track_peak_memory`).
+    ///
+    /// Not part of the serde representation of the proof, so that measuring
+    /// does not change the proof. The versioned binary proof format keeps it
+    /// in the prover metadata instead.
+    #[serde(skip)]
+    pub peak_rss_bytes: Option<u64>,
+}
+
+// `PublicValues` does not implement `PartialEq`. The public values are also
+// public inputs of the proof though, so comparing the proofs compares them too.
+impl PartialEq for GeneratedBlockProof {
+    fn eq(&self, other: &Self) -> bool {
+        self.b_height == other.b_height
+            && self.intern == other.intern
+            && self.security_bits == other.security_bits
+            && self.withdrawals == other.withdrawals
+    }
+}
+
+impl GeneratedBlockProof {
+    /// The size of the serialized proof in bytes.
+    pub fn size_bytes(&self) -> usize {
+        self.intern.to_bytes().len()
+    }
+
+    pub fn block_number(&self) -> BlockHeight {
+        self.b_height
+    }
+
+    pub fn state_root_before(&self) -> H256 {
+        self.public_values.trie_roots_before.state_root
+    }
+
+    pub fn state_root_after(&self) -> H256 {
+        self.public_values.trie_roots_after.state_root
+    }
+
+    /// The gas used by the txns of the block. Fails if the gas used decreases
+    /// over the block (which no valid proof does).
+    pub fn gas_used(&self) -> ProofGenResult<U256> {
+        let extra = &self.public_values.extra_block_data;
+        checked_gas_used(extra.gas_used_before, extra.gas_used_after, self.b_height)
+    }
+
+    /// The txns of the block that the proof covers.
+    ///
+    /// Fails with `ProofGenError::InvalidInput` if the txn numbers in the
+    /// public values do not fit a `usize` (which no real block does).
+    pub fn txn_range(&self) -> ProofGenResult<Range<TxnIdx>> {
+        let extra = &self.public_values.extra_block_data;
+        let to_txn_idx = |txn_number: U256| {
+            txn_number.try_into().map(TxnIdx).map_err(|_| {
+                ProofGenError::InvalidInput(format!(
+                    "Txn number {} of block {} does not fit a usize",
+                    txn_number, self.b_height
+                ))
+            })
+        };
+
+        Ok(to_txn_idx(extra.txn_number_before)?..to_txn_idx(extra.txn_number_after)?)
+    }
+
+    /// The total amount withdrawn in the block.
+    pub fn withdrawn_amount(&self) -> U256 {
+        self.withdrawals
+            .iter()
+            .fold(U256::zero(), |total, (_, amount)| {
+                total.saturating_add(*amount)
+            })
+    }
+}
+
+/// A proof for the range of blocks `start_b_height..=end_b_height`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GeneratedChainProof {
+    pub start_b_height: BlockHeight,
+    pub end_b_height: BlockHeight,
+    pub intern: PlonkyProofIntern,
+}
+
+/// A block proof that has been shrunk by a
+/// [`BlockProofWrapper`](crate::wrapper::BlockProofWrapper).
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GeneratedWrappedBlockProof {
+    pub b_height: BlockHeight,
+    pub intern: PlonkyProofIntern,
+}
+
+/// A block proof that has been shrunk by a
+/// [`KeccakBlockProofWrapper`](crate::wrapper::KeccakBlockProofWrapper), so
+/// that its outer proof uses Keccak as its hasher.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GeneratedKeccakWrappedBlockProof {
+    pub b_height: BlockHeight,
+    pub intern: KeccakPlonkyProofIntern,
 }
 
 /// Sometimes we don't care about the underlying proof type and instead only if
 /// we can combine it into an agg proof. For these cases, we want to abstract
 /// away whether or not the proof was a txn or agg proof.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum AggregatableProof {
     Txn(GeneratedTxnProof),
     Agg(GeneratedAggProof),
@@ -77,9 +350,95 @@ impl AggregatableProof {
     }
 
     pub fn b_height(&self) -> BlockHeight {
+        self.common().b_height
+    }
+
+    pub fn common(&self) -> &ProofCommon {
         match self {
-            AggregatableProof::Txn(info) => info.common.b_height,
-            AggregatableProof::Agg(info) => info.common.b_height,
+            AggregatableProof::Txn(info) => &info.common,
+            AggregatableProof::Agg(info) => &info.common,
+        }
+    }
+}
+
+/// A borrowed [`AggregatableProof`].
+#[derive(Clone, Copy, Debug)]
+pub enum AggregatableProofRef<'a> {
+    Txn(&'a GeneratedTxnProof),
+    Agg(&'a GeneratedAggProof),
+}
+
+impl<'a> AggregatableProofRef<'a> {
+    pub fn underlying_txns(&self) -> ProofUnderlyingTxns {
+        match self {
+            AggregatableProofRef::Txn(info) => info.underlying_txns(),
+            AggregatableProofRef::Agg(info) => info.underlying_txns.clone(),
+        }
+    }
+
+    pub fn b_height(&self) -> BlockHeight {
+        self.common().b_height
+    }
+
+    pub fn common(&self) -> &'a ProofCommon {
+        match self {
+            AggregatableProofRef::Txn(info) => &info.common,
+            AggregatableProofRef::Agg(info) => &info.common,
+        }
+    }
+
+    /// Clone the borrowed proof into an owned [`AggregatableProof`].
+    pub fn to_owned_proof(&self) -> AggregatableProof {
+        match self {
+            AggregatableProofRef::Txn(info) => AggregatableProof::Txn((*info).clone()),
+            AggregatableProofRef::Agg(info) => AggregatableProof::Agg((*info).clone()),
+        }
+    }
+}
+
+impl_common_accessors!(AggregatableProofRef<'_>);
+
+impl<'a> From<&'a AggregatableProof> for AggregatableProofRef<'a> {
+    fn from(v: &'a AggregatableProof) -> Self {
+        match v {
+            AggregatableProof::Txn(info) => Self::Txn(info),
+            AggregatableProof::Agg(info) => Self::Agg(info),
+        }
+    }
+}
+
+impl<'a> From<&'a GeneratedTxnProof> for AggregatableProofRef<'a> {
+    fn from(v: &'a GeneratedTxnProof) -> Self {
+        Self::Txn(v)
+    }
+}
+
+impl<'a> From<&'a GeneratedAggProof> for AggregatableProofRef<'a> {
+    fn from(v: &'a GeneratedAggProof) -> Self {
+        Self::Agg(v)
+    }
+}
+
+/// Fails with the original proof if it is not a txn proof.
+impl TryFrom<AggregatableProof> for GeneratedTxnProof {
+    type Error = AggregatableProof;
+
+    fn try_from(v: AggregatableProof) -> Result<Self, Self::Error> {
+        match v {
+            AggregatableProof::Txn(info) => Ok(info),
+            v => Err(v),
+        }
+    }
+}
+
+/// Fails with the original proof if it is not an agg proof.
+impl TryFrom<AggregatableProof> for GeneratedAggProof {
+    type Error = AggregatableProof;
+
+    fn try_from(v: AggregatableProof) -> Result<Self, Self::Error> {
+        match v {
+            AggregatableProof::Agg(info) => Ok(info),
+            v => Err(v),
         }
     }
 }
@@ -95,3 +454,16 @@ impl From<GeneratedAggProof> for AggregatableProof {
         Self::Agg(v)
     }
 }
+
+fn checked_gas_used(
+    gas_used_before: U256,
+    gas_used_after: U256,
+    b_height: BlockHeight,
+) -> ProofGenResult<U256> {
+    gas_used_after.checked_sub(gas_used_before).ok_or_else(|| {
+        ProofGenError::InvalidInput(format!(
+            "Gas used of block {} decreases from {} to {}",
+            b_height, gas_used_before, gas_used_after
+        ))
+    })
+}
@@ -1,19 +1,291 @@
-use std::ops::Range;
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    ops::Range,
+    path::Path,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Mutex, OnceLock, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use log::info;
 use paste::paste;
-use plonky2_evm::{all_stark::AllStark, config::StarkConfig};
+use plonky2::fri::{reduction_strategies::FriReductionStrategy, FriConfig};
+use plonky2_evm::{
+    all_stark::{Table, NUM_TABLES},
+    config::StarkConfig,
+};
+use serde::{Deserialize, Serialize};
 
-use crate::types::AllRecursiveCircuits;
+use crate::{
+    chain_config::ChainConfig,
+    circuit_cache::{circuit_params_fingerprint, circuits_from_bytes, circuits_to_bytes},
+    estimation::DegreeBitsEstimator,
+    profile::{stark_config_for_security_bits, ProverProfile},
+    progress::{CircuitBuildEvent, CircuitBuildProgress},
+    proof_gen::{catch_prover_panic, ProofGenError, ProofGenResult},
+    types::{AllRecursiveCircuits, AllStark, BlockHeight, CircuitDigest, CircuitDigests, HardFork},
+};
 
 /// Plonky2 proving state. Note that this is generally going to be massive in
 /// terms of memory and has a long spin-up time,
 pub struct ProverState {
     pub state: AllRecursiveCircuits,
+
+    /// The range of degree bits that the circuits support for every STARK
+    /// table, indexed like [`TABLE_NAMES`](crate::types::TABLE_NAMES).
+    pub circuit_sizes: [Range<usize>; NUM_TABLES],
+
+    /// The STARK definitions that the circuits were built from. Built once
+    /// here and reused for every root proof.
+    pub all_stark: AllStark,
+
+    /// The STARK config that the circuits were built with. Any root proof
+    /// generated against `state` must use this same config.
+    pub stark_config: StarkConfig,
+
+    /// The heights at which a block proof may be generated without a parent
+    /// block proof.
+    pub checkpoints: CheckpointConfig,
+
+    /// If set, txns that are estimated to need more memory (in bytes) than
+    /// this to prove get rejected before proving them.
+    pub memory_ceiling: Option<usize>,
+
+    /// Whether proofs that plonky2 is able to blind are generated with
+    /// zero-knowledge. See [`ProverStateBuilder::set_zero_knowledge`].
+    pub zero_knowledge: bool,
+
+    /// If set, the block metadata of every txn and block gets checked against
+    /// this config before proving.
+    pub chain_config: Option<ChainConfig>,
+}
+
+/// What a prover state is able to prove. See [`ProverState::capabilities`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ProverCapabilities {
+    /// The range of degree bits supported for every table, indexed like
+    /// [`TABLE_NAMES`](crate::types::TABLE_NAMES).
+    pub circuit_sizes: [Range<usize>; NUM_TABLES],
+
+    /// The largest degree bits supported for every table (ie. the last degree
+    /// of `circuit_sizes`).
+    pub max_degree_bits: [usize; NUM_TABLES],
+
+    /// A rough upper bound on the gas of a single txn. See
+    /// [`DegreeBitsEstimator::max_gas`].
+    pub approx_max_gas_per_txn: u64,
+
+    pub supported_hard_forks: Vec<HardFork>,
+
+    pub circuit_digests: CircuitDigests,
+
+    pub security_bits: usize,
+}
+
+impl ProverState {
+    /// Describe what this prover state is able to prove, eg. for an
+    /// orchestrator that routes txns to a fleet of provers with different
+    /// circuits.
+    pub fn capabilities(&self) -> ProverCapabilities {
+        ProverCapabilities {
+            circuit_sizes: self.circuit_sizes.clone(),
+            max_degree_bits: self
+                .circuit_sizes
+                .clone()
+                .map(|size| size.end.saturating_sub(1)),
+            approx_max_gas_per_txn: DegreeBitsEstimator::default().max_gas(&self.circuit_sizes),
+            supported_hard_forks: HardFork::SUPPORTED.to_vec(),
+            circuit_digests: self.circuit_digests(),
+            security_bits: self.security_bits(),
+        }
+    }
+
+    /// The digest of the root (txn) circuit.
+    pub fn root_circuit_digest(&self) -> CircuitDigest {
+        self.state.root.circuit.verifier_only.circuit_digest
+    }
+
+    /// The digest of the aggregation circuit.
+    pub fn aggregation_circuit_digest(&self) -> CircuitDigest {
+        self.state.aggregation.circuit.verifier_only.circuit_digest
+    }
+
+    /// The digest of the block circuit.
+    pub fn block_circuit_digest(&self) -> CircuitDigest {
+        self.state.block.circuit.verifier_only.circuit_digest
+    }
+
+    /// The digests of the root, aggregation and block circuits.
+    pub fn circuit_digests(&self) -> CircuitDigests {
+        CircuitDigests {
+            root: self.root_circuit_digest(),
+            aggregation: self.aggregation_circuit_digest(),
+            block: self.block_circuit_digest(),
+        }
+    }
+
+    /// The (conjectured) security level of the generated proofs. This is the
+    /// lowest level of the STARK config and the configs of the recursive
+    /// circuits.
+    pub fn security_bits(&self) -> usize {
+        [
+            &self.state.root.circuit.common,
+            &self.state.aggregation.circuit.common,
+            &self.state.block.circuit.common,
+        ]
+        .into_iter()
+        .map(|common| common.config.security_bits)
+        .fold(self.stark_config.security_bits, usize::min)
+    }
+
+    /// The degree bits of the root (txn) circuit.
+    pub fn root_degree_bits(&self) -> usize {
+        self.state.root.circuit.common.degree_bits()
+    }
+
+    /// The degree bits of the aggregation circuit.
+    pub fn aggregation_degree_bits(&self) -> usize {
+        self.state.aggregation.circuit.common.degree_bits()
+    }
+
+    /// The degree bits of the block circuit.
+    pub fn block_degree_bits(&self) -> usize {
+        self.state.block.circuit.common.degree_bits()
+    }
+
+    /// Whether the circuits support tables with `degree_bits`, indexed like
+    /// [`TABLE_NAMES`](crate::types::TABLE_NAMES).
+    pub fn supports_degree_bits(&self, degree_bits: &[usize; NUM_TABLES]) -> bool {
+        self.circuit_sizes
+            .iter()
+            .zip(degree_bits.iter())
+            .all(|(supported, bits)| supported.contains(bits))
+    }
+
+    /// Serialize the circuits to `path`, so that later processes can load them
+    /// with [`ProverState::load`] instead of building them again.
+    ///
+    /// Only the circuits are saved. The rest of the prover state comes from
+    /// the builder that the circuits are loaded with.
+    pub fn save(&self, path: impl AsRef<Path>) -> ProofGenResult<()> {
+        let path = path.as_ref();
+        let fingerprint = circuit_params_fingerprint(&self.circuit_sizes, &self.stark_config);
+        let bytes = circuits_to_bytes(&self.state, fingerprint)?;
+
+        fs::write(path, bytes)
+            .map_err(|err| ProofGenError::Storage(format!("{}: {}", path.display(), err)))
+    }
+
+    /// Load circuits saved with [`ProverState::save`], using the defaults of
+    /// [`ProverStateBuilder`] for everything else.
+    pub fn load(path: impl AsRef<Path>) -> ProofGenResult<ProverState> {
+        ProverStateBuilder::default().load(path)
+    }
+}
+
+/// Which block heights are checkpoints.
+///
+/// A block at a checkpoint height does not need a parent block proof to be
+/// proven, while every other block does.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum CheckpointConfig {
+    /// Every height is a checkpoint, so it's entirely up to the caller when to
+    /// pass a parent block proof.
+    #[default]
+    Any,
+
+    /// Every height that is a multiple of the interval is a checkpoint.
+    Interval(BlockHeight),
+
+    /// Only these heights are checkpoints.
+    Heights(HashSet<BlockHeight>),
+}
+
+impl CheckpointConfig {
+    pub fn is_checkpoint(&self, b_height: BlockHeight) -> bool {
+        match self {
+            CheckpointConfig::Any => true,
+            CheckpointConfig::Interval(interval) => b_height.0.checked_rem(interval.0) == Some(0),
+            CheckpointConfig::Heights(heights) => heights.contains(&b_height),
+        }
+    }
+}
+
+/// A prover state that only gets built the first time that it is used.
+///
+/// Note that plonky2 builds the recursive circuits of every table for every
+/// degree in its range at once (the root circuit needs all of them), so the
+/// individual circuits can not be built on demand. Instead, the entire prover
+/// state is deferred until the first proof (or other access) needs it, which
+/// lets processes that may never prove (eg. verification-only nodes or health
+/// checks) start up immediately.
+pub struct LazyProverState {
+    circuit_sizes: [Range<usize>; NUM_TABLES],
+    builder: Mutex<Option<ProverStateBuilder>>,
+    state: OnceLock<ProverState>,
+}
+
+impl LazyProverState {
+    pub fn new(builder: ProverStateBuilder) -> Self {
+        Self {
+            circuit_sizes: builder.circuit_sizes(),
+            builder: Mutex::new(Some(builder)),
+            state: OnceLock::new(),
+        }
+    }
+
+    /// Get the prover state, building it if this is the first access. Note
+    /// that the first call is very expensive, and concurrent callers block
+    /// until it completes.
+    ///
+    /// If building fails, the builder is kept and the next call tries again.
+    pub fn get(&self) -> ProofGenResult<&ProverState> {
+        if let Some(state) = self.state.get() {
+            return Ok(state);
+        }
+
+        // Building does not panic while holding the lock (plonky2 panics are
+        // caught), so a poisoned lock still guards a consistent builder.
+        let mut builder = self.builder.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(state) = self.state.get() {
+            return Ok(state);
+        }
+
+        let taken = builder
+            .take()
+            .expect("The builder is kept until the prover state is built");
+        match catch_prover_panic(|| taken.build_circuits()) {
+            Ok(circuits) => Ok(self.state.get_or_init(|| taken.into_prover_state(circuits))),
+            Err(err) => {
+                *builder = Some(taken);
+                Err(err)
+            }
+        }
+    }
+
+    /// Get the prover state if it has already been built.
+    pub fn get_if_built(&self) -> Option<&ProverState> {
+        self.state.get()
+    }
+
+    /// The circuit sizes of the prover state, which are known without building
+    /// it.
+    pub fn circuit_sizes(&self) -> &[Range<usize>; NUM_TABLES] {
+        &self.circuit_sizes
+    }
+}
+
+impl From<ProverStateBuilder> for LazyProverState {
+    fn from(builder: ProverStateBuilder) -> Self {
+        Self::new(builder)
+    }
 }
 
 /// Builder for the prover state.
-#[derive(Debug)]
 pub struct ProverStateBuilder {
     arithmetic_circuit_size: Range<usize>,
     byte_packing_circuit_size: Range<usize>,
@@ -22,6 +294,37 @@ pub struct ProverStateBuilder {
     keccak_sponge_circuit_size: Range<usize>,
     logic_circuit_size: Range<usize>,
     memory_circuit_size: Range<usize>,
+    all_stark: AllStark,
+    stark_config: StarkConfig,
+    checkpoints: CheckpointConfig,
+    memory_ceiling: Option<usize>,
+    zero_knowledge: bool,
+    chain_config: Option<ChainConfig>,
+}
+
+// Neither `AllStark` nor `StarkConfig` implement `Debug`, so we only print the
+// parts of them that do.
+impl fmt::Debug for ProverStateBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProverStateBuilder")
+            .field("arithmetic_circuit_size", &self.arithmetic_circuit_size)
+            .field("byte_packing_circuit_size", &self.byte_packing_circuit_size)
+            .field("cpu_circuit_size", &self.cpu_circuit_size)
+            .field("keccak_circuit_size", &self.keccak_circuit_size)
+            .field(
+                "keccak_sponge_circuit_size",
+                &self.keccak_sponge_circuit_size,
+            )
+            .field("logic_circuit_size", &self.logic_circuit_size)
+            .field("memory_circuit_size", &self.memory_circuit_size)
+            .field("stark_security_bits", &self.stark_config.security_bits)
+            .field("stark_fri_config", &self.stark_config.fri_config)
+            .field("checkpoints", &self.checkpoints)
+            .field("memory_ceiling", &self.memory_ceiling)
+            .field("zero_knowledge", &self.zero_knowledge)
+            .field("chain_config", &self.chain_config)
+            .finish()
+    }
 }
 
 impl Default for ProverStateBuilder {
@@ -37,6 +340,12 @@ impl Default for ProverStateBuilder {
             keccak_sponge_circuit_size: 9..14,
             logic_circuit_size: 12..16,
             memory_circuit_size: 17..25,
+            all_stark: AllStark::default(),
+            stark_config: StarkConfig::standard_fast_config(),
+            checkpoints: CheckpointConfig::default(),
+            memory_ceiling: None,
+            zero_knowledge: false,
+            chain_config: None,
         }
     }
 }
@@ -44,6 +353,10 @@ impl Default for ProverStateBuilder {
 macro_rules! define_set_circuit_size_method {
     ($name:ident) => {
         paste! {
+            #[doc = concat!(
+                "Set the range of degree bits that the circuits of the `", stringify!($name),
+                "` table support."
+            )]
             pub fn [<set_ $name _circuit_size>](mut self, size: Range<usize>) -> Self {
                 self.[<$name _circuit_size>] = size;
                 self
@@ -53,6 +366,31 @@ macro_rules! define_set_circuit_size_method {
 }
 
 impl ProverStateBuilder {
+    /// A builder for a tiny prover state that is only meant for tests (and
+    /// CI). It builds in a fraction of the time of the default configuration
+    /// and proves toy txns (eg. simple transfers) in seconds.
+    ///
+    /// The circuits only support a single degree (or a few) per table, and the
+    /// `StarkConfig` uses a single FRI query without any proof of work, so the
+    /// generated proofs are not sound. Never use this in production!
+    pub fn test_only() -> Self {
+        let stark_config = StarkConfig {
+            security_bits: 1,
+            num_challenges: 1,
+            fri_config: FriConfig {
+                rate_bits: 1,
+                cap_height: 0,
+                proof_of_work_bits: 0,
+                reduction_strategy: FriReductionStrategy::ConstantArityBits(4, 5),
+                num_query_rounds: 1,
+            },
+        };
+
+        Self::default()
+            .set_circuit_sizes([16..17, 9..12, 12..16, 13..16, 9..12, 12..14, 17..19])
+            .set_stark_config(stark_config)
+    }
+
     define_set_circuit_size_method!(arithmetic);
     define_set_circuit_size_method!(byte_packing);
     define_set_circuit_size_method!(cpu);
@@ -61,29 +399,227 @@ impl ProverStateBuilder {
     define_set_circuit_size_method!(logic);
     define_set_circuit_size_method!(memory);
 
+    /// Set the range of degree bits that the circuits of `table` support.
+    ///
+    /// Every degree in the range gets its own recursive circuit, so small
+    /// ranges keep the prover state (and its build time) small, while blocks
+    /// with a trace that does not fit into the largest degree can not be
+    /// proven.
+    pub fn set_circuit_size(self, table: Table, size: Range<usize>) -> Self {
+        match table {
+            Table::Arithmetic => self.set_arithmetic_circuit_size(size),
+            Table::BytePacking => self.set_byte_packing_circuit_size(size),
+            Table::Cpu => self.set_cpu_circuit_size(size),
+            Table::Keccak => self.set_keccak_circuit_size(size),
+            Table::KeccakSponge => self.set_keccak_sponge_circuit_size(size),
+            Table::Logic => self.set_logic_circuit_size(size),
+            Table::Memory => self.set_memory_circuit_size(size),
+        }
+    }
+
+    /// Set the ranges of degree bits of all tables at once, indexed like
+    /// [`TABLE_NAMES`](crate::types::TABLE_NAMES).
+    pub fn set_circuit_sizes(mut self, sizes: [Range<usize>; NUM_TABLES]) -> Self {
+        let [arithmetic, byte_packing, cpu, keccak, keccak_sponge, logic, memory] = sizes;
+        self.arithmetic_circuit_size = arithmetic;
+        self.byte_packing_circuit_size = byte_packing;
+        self.cpu_circuit_size = cpu;
+        self.keccak_circuit_size = keccak;
+        self.keccak_sponge_circuit_size = keccak_sponge;
+        self.logic_circuit_size = logic;
+        self.memory_circuit_size = memory;
+        self
+    }
+
+    /// Set the STARK definitions used to build the circuits and to generate
+    /// txn proofs. Defaults to `AllStark::default()`.
+    pub fn set_all_stark(mut self, all_stark: AllStark) -> Self {
+        self.all_stark = all_stark;
+        self
+    }
+
+    /// Set the STARK config used both to build the circuits and to generate
+    /// txn proofs. Defaults to `StarkConfig::standard_fast_config()`.
+    ///
+    /// A higher FRI rate gives smaller proofs at the cost of proving time.
+    pub fn set_stark_config(mut self, config: StarkConfig) -> Self {
+        self.stark_config = config;
+        self
+    }
+
+    /// Set the STARK config to the one of `profile`. Same as calling
+    /// [`ProverStateBuilder::set_stark_config`] with `profile.stark_config()`.
+    pub fn set_profile(self, profile: ProverProfile) -> Self {
+        self.set_stark_config(profile.stark_config())
+    }
+
+    /// Set the STARK config to one with a (conjectured) security level of at
+    /// least `security_bits` (see [`stark_config_for_security_bits`]).
+    ///
+    /// Note that plonky2 builds the recursive circuits with its standard
+    /// recursion config, which targets 100 bits. The overall security level of
+    /// the prover state is reported by [`ProverState::security_bits`].
+    pub fn set_security_bits(self, security_bits: usize) -> Self {
+        self.set_stark_config(stark_config_for_security_bits(security_bits))
+    }
+
+    /// Set which block heights are checkpoints. Defaults to
+    /// `CheckpointConfig::Any`.
+    pub fn set_checkpoints(mut self, checkpoints: CheckpointConfig) -> Self {
+        self.checkpoints = checkpoints;
+        self
+    }
+
+    /// Reject txns that are estimated to need more than `bytes` of memory to
+    /// prove.
+    ///
+    /// Note that estimating the memory requires running witness generation
+    /// for every txn before it gets proven.
+    pub fn set_memory_ceiling(mut self, bytes: usize) -> Self {
+        self.memory_ceiling = Some(bytes);
+        self
+    }
+
+    /// Enable zero-knowledge blinding where plonky2 supports it. Defaults to
+    /// `false`.
+    ///
+    /// Plonky2 does not support blinding the STARK proofs of the tables, and
+    /// builds the recursive root, aggregation and block circuits without it.
+    /// Only the circuits of a
+    /// [`BlockProofWrapper`](crate::wrapper::BlockProofWrapper) built from
+    /// this prover state are blinded, so only wrapped block proofs are
+    /// zero-knowledge. Blinding makes those circuits larger and their proofs
+    /// slower to generate.
+    pub fn set_zero_knowledge(mut self, zero_knowledge: bool) -> Self {
+        self.zero_knowledge = zero_knowledge;
+        self
+    }
+
+    /// Reject txns and blocks that do not belong to the chain described by
+    /// `chain_config`. See [`ChainConfig`].
+    pub fn set_chain_config(mut self, chain_config: ChainConfig) -> Self {
+        self.chain_config = Some(chain_config);
+        self
+    }
+
     // TODO: Consider adding async version?
     /// Instantiate the prover state from the builder. Note that this is a very
     /// expensive call!
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "build_prover_state", skip_all)
+    )]
     pub fn build(self) -> ProverState {
+        let state = self.build_circuits();
+
+        self.into_prover_state(state)
+    }
+
+    /// Same as [`ProverStateBuilder::build`], but reports to `progress` while
+    /// building, with a [`CircuitBuildEvent::StillBuilding`] event every
+    /// `heartbeat`.
+    pub fn build_with_progress(
+        self,
+        progress: &dyn CircuitBuildProgress,
+        heartbeat: Duration,
+    ) -> ProverState {
+        let table_circuits = self.circuit_sizes().map(|size| size.len());
+        progress.on_event(CircuitBuildEvent::Started { table_circuits });
+
+        let started = Instant::now();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+
+        let p_state = thread::scope(|s| {
+            s.spawn(move || {
+                // Wakes up every heartbeat until the sender gets dropped.
+                while let Err(RecvTimeoutError::Timeout) = done_rx.recv_timeout(heartbeat) {
+                    progress.on_event(CircuitBuildEvent::StillBuilding {
+                        elapsed: started.elapsed(),
+                    });
+                }
+            });
+
+            let p_state = self.build();
+            drop(done_tx);
+
+            p_state
+        });
+
+        progress.on_event(CircuitBuildEvent::Finished {
+            elapsed: started.elapsed(),
+        });
+
+        p_state
+    }
+
+    /// Instantiate the prover state with circuits saved with
+    /// [`ProverState::save`] instead of building them.
+    ///
+    /// The circuits must have been saved by the same version of this crate and
+    /// built with the same circuit sizes and `StarkConfig` as the ones set on
+    /// this builder, or this fails with `ProofGenError::StaleCircuitCache` (in
+    /// which case the circuits need to be rebuilt). Note that a custom
+    /// `AllStark` can not be checked and must match as well.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "load_prover_state",
+            skip_all,
+            err,
+            fields(path = %path.as_ref().display())
+        )
+    )]
+    pub fn load(self, path: impl AsRef<Path>) -> ProofGenResult<ProverState> {
+        let path = path.as_ref();
+        info!(
+            "Loading Plonky2 aggregation prover state from {}...",
+            path.display()
+        );
+
+        let bytes = fs::read(path)
+            .map_err(|err| ProofGenError::Storage(format!("{}: {}", path.display(), err)))?;
+        let fingerprint = circuit_params_fingerprint(&self.circuit_sizes(), &self.stark_config);
+        let state = circuits_from_bytes(&bytes, fingerprint)?;
+
+        info!("Finished loading Plonky2 aggregation prover state!");
+
+        Ok(self.into_prover_state(state))
+    }
+
+    fn build_circuits(&self) -> AllRecursiveCircuits {
         info!("Initializing Plonky2 aggregation prover state (This may take a while)...");
 
         // ... Yeah I don't understand the mysterious ranges either :)
-        let state = AllRecursiveCircuits::new(
-            &AllStark::default(),
-            &[
-                self.arithmetic_circuit_size,
-                self.byte_packing_circuit_size,
-                self.cpu_circuit_size,
-                self.keccak_circuit_size,
-                self.keccak_sponge_circuit_size,
-                self.logic_circuit_size,
-                self.memory_circuit_size,
-            ],
-            &StarkConfig::standard_fast_config(),
-        );
+        let state =
+            AllRecursiveCircuits::new(&self.all_stark, &self.circuit_sizes(), &self.stark_config);
 
         info!("Finished initializing Plonky2 aggregation prover state!");
 
-        ProverState { state }
+        state
+    }
+
+    fn circuit_sizes(&self) -> [Range<usize>; NUM_TABLES] {
+        [
+            self.arithmetic_circuit_size.clone(),
+            self.byte_packing_circuit_size.clone(),
+            self.cpu_circuit_size.clone(),
+            self.keccak_circuit_size.clone(),
+            self.keccak_sponge_circuit_size.clone(),
+            self.logic_circuit_size.clone(),
+            self.memory_circuit_size.clone(),
+        ]
+    }
+
+    fn into_prover_state(self, state: AllRecursiveCircuits) -> ProverState {
+        ProverState {
+            state,
+            circuit_sizes: self.circuit_sizes(),
+            all_stark: self.all_stark,
+            stark_config: self.stark_config,
+            checkpoints: self.checkpoints,
+            memory_ceiling: self.memory_ceiling,
+            zero_knowledge: self.zero_knowledge,
+            chain_config: self.chain_config,
+        }
     }
 }
@@ -0,0 +1,260 @@
+//! The proof operations as serializable remote operations.
+//!
+//! Distributed task runners (eg. Paladin) ship an operation and its input to a
+//! worker and the output back. [`RemoteOperation`] bundles the inputs of each
+//! of the three proof operations into a single serializable type, and the
+//! output is a [`JobOutput`]. Proofs are large, so payloads can additionally be
+//! split into [`PayloadChunk`]s for transports with a message size limit.
+
+use std::collections::HashMap;
+
+use ethereum_types::H256;
+use keccak_hash::keccak;
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    backend::BlockProverBackend,
+    dummy::create_dummy_txn_ir,
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof},
+    scheduler::{JobOutput, ReadyJob},
+};
+
+/// A proof operation along with all of its inputs.
+///
+/// Proof generation options can not be sent along (they hold thread pools and
+/// callbacks), so every worker executes operations with its own options.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum RemoteOperation {
+    ProveTxn {
+        start_info: TxnProofGenIR,
+    },
+    ProveAgg {
+        lhs_child: AggregatableProof,
+        rhs_child: AggregatableProof,
+        other_data: OtherBlockData,
+    },
+    ProveBlock {
+        prev_opt_parent_b_proof: Option<GeneratedBlockProof>,
+        curr_block_agg_proof: GeneratedAggProof,
+        other_data: OtherBlockData,
+    },
+}
+
+impl RemoteOperation {
+    /// Execute the operation with `p_state`.
+    pub fn execute<B: BlockProverBackend + ?Sized>(
+        self,
+        p_state: &B,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<JobOutput> {
+        match self {
+            RemoteOperation::ProveTxn { start_info } => p_state
+                .generate_txn_proof(start_info, opts)
+                .map(JobOutput::Txn),
+            RemoteOperation::ProveAgg {
+                lhs_child,
+                rhs_child,
+                other_data,
+            } => p_state
+                .generate_agg_proof(&lhs_child, &rhs_child, other_data, opts)
+                .map(JobOutput::Agg),
+            RemoteOperation::ProveBlock {
+                prev_opt_parent_b_proof,
+                curr_block_agg_proof,
+                other_data,
+            } => p_state
+                .generate_block_proof(
+                    prev_opt_parent_b_proof.as_ref(),
+                    &curr_block_agg_proof,
+                    other_data,
+                    opts,
+                )
+                .map(JobOutput::Block),
+        }
+    }
+
+    pub fn to_bytes(&self) -> ProofGenResult<Vec<u8>> {
+        to_payload(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> ProofGenResult<Self> {
+        from_payload(bytes)
+    }
+}
+
+impl From<ReadyJob> for RemoteOperation {
+    fn from(job: ReadyJob) -> Self {
+        match job {
+            ReadyJob::Txn(start_info) => RemoteOperation::ProveTxn { start_info },
+            ReadyJob::DummyTxnAfter {
+                prev_txn_proof,
+                other_data,
+            } => RemoteOperation::ProveTxn {
+                start_info: create_dummy_txn_ir(
+                    prev_txn_proof.txn_idx + 1,
+                    &prev_txn_proof.common.roots_after,
+                    prev_txn_proof.common.deltas.gas_used_after,
+                    &other_data,
+                ),
+            },
+            ReadyJob::Agg {
+                lhs_child,
+                rhs_child,
+                other_data,
+            } => RemoteOperation::ProveAgg {
+                lhs_child,
+                rhs_child,
+                other_data,
+            },
+            ReadyJob::Block {
+                prev_opt_parent_b_proof,
+                curr_block_agg_proof,
+                other_data,
+            } => RemoteOperation::ProveBlock {
+                prev_opt_parent_b_proof,
+                curr_block_agg_proof,
+                other_data,
+            },
+        }
+    }
+}
+
+impl JobOutput {
+    pub fn to_bytes(&self) -> ProofGenResult<Vec<u8>> {
+        to_payload(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> ProofGenResult<Self> {
+        from_payload(bytes)
+    }
+}
+
+fn to_payload<T: Serialize>(val: &T) -> ProofGenResult<Vec<u8>> {
+    bincode::serialize(val).map_err(|err| ProofGenError::Serialization(err.to_string()))
+}
+
+fn from_payload<T: DeserializeOwned>(bytes: &[u8]) -> ProofGenResult<T> {
+    bincode::deserialize(bytes).map_err(|err| ProofGenError::Serialization(err.to_string()))
+}
+
+/// The most chunks that a [`PayloadAssembler`] accepts for a payload, so that
+/// a corrupted or malicious chunk can not make it allocate a huge buffer.
+pub const MAX_PAYLOAD_CHUNKS: u32 = 1 << 16;
+
+/// A piece of a payload that was split up with [`split_payload`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PayloadChunk {
+    /// Identifies the payload that the chunk belongs to.
+    pub payload_id: u64,
+
+    /// The digest of the entire payload, for checking the reassembled payload.
+    pub payload_hash: H256,
+
+    pub index: u32,
+    pub num_chunks: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// Split `payload` into chunks of at most `max_chunk_size` bytes. The chunks
+/// can be sent in any order and be reassembled with a [`PayloadAssembler`],
+/// as long as there are at most [`MAX_PAYLOAD_CHUNKS`] of them.
+pub fn split_payload(payload_id: u64, payload: &[u8], max_chunk_size: usize) -> Vec<PayloadChunk> {
+    let payload_hash = keccak(payload);
+
+    // An empty payload still needs a chunk to be reassembled from.
+    let chunks: Vec<_> = match payload.is_empty() {
+        true => vec![&[][..]],
+        false => payload.chunks(max_chunk_size.max(1)).collect(),
+    };
+    let num_chunks = chunks.len() as u32;
+
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, bytes)| PayloadChunk {
+            payload_id,
+            payload_hash,
+            index: index as u32,
+            num_chunks,
+            bytes: bytes.to_vec(),
+        })
+        .collect()
+}
+
+/// Reassembles payloads from their chunks, which may arrive in any order and
+/// interleaved with the chunks of other payloads.
+#[derive(Debug, Default)]
+pub struct PayloadAssembler {
+    partial: HashMap<u64, Vec<Option<Vec<u8>>>>,
+}
+
+impl PayloadAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `chunk`. Returns the reassembled payload once all of its chunks
+    /// have arrived.
+    pub fn add_chunk(&mut self, chunk: PayloadChunk) -> ProofGenResult<Option<Vec<u8>>> {
+        if chunk.num_chunks == 0
+            || chunk.num_chunks > MAX_PAYLOAD_CHUNKS
+            || chunk.index >= chunk.num_chunks
+        {
+            return Err(ProofGenError::Serialization(format!(
+                "Chunk {} of {} of payload {} is invalid (payloads have 1 to {} chunks)",
+                chunk.index, chunk.num_chunks, chunk.payload_id, MAX_PAYLOAD_CHUNKS
+            )));
+        }
+
+        let chunks = self
+            .partial
+            .entry(chunk.payload_id)
+            .or_insert_with(|| vec![None; chunk.num_chunks as usize]);
+
+        if chunks.len() != chunk.num_chunks as usize {
+            return Err(ProofGenError::Serialization(format!(
+                "Chunk {} of {} does not belong to payload {} with {} chunks",
+                chunk.index,
+                chunk.num_chunks,
+                chunk.payload_id,
+                chunks.len()
+            )));
+        }
+
+        chunks[chunk.index as usize] = Some(chunk.bytes);
+        if chunks.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+
+        let payload: Vec<u8> = self
+            .partial
+            .remove(&chunk.payload_id)
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect();
+
+        match keccak(&payload) == chunk.payload_hash {
+            true => Ok(Some(payload)),
+            false => Err(ProofGenError::Serialization(format!(
+                "Reassembled payload {} does not match its digest",
+                chunk.payload_id
+            ))),
+        }
+    }
+
+    /// The number of payloads that are still missing chunks.
+    pub fn num_incomplete(&self) -> usize {
+        self.partial.len()
+    }
+
+    /// Drop the chunks received so far for `payload_id` (eg. after the
+    /// transfer was abandoned).
+    pub fn discard(&mut self, payload_id: u64) {
+        self.partial.remove(&payload_id);
+    }
+}
@@ -0,0 +1,245 @@
+//! A durable queue of accepted proof requests, backed by an embedded SQLite
+//! database.
+//!
+//! Services that acknowledge requests before proving them (eg. because proving
+//! takes minutes) should enqueue every request before acknowledging it, and
+//! only remove it from the queue once its proof has been delivered. A prover
+//! that restarts in between then picks the request up again instead of
+//! silently dropping it.
+//!
+//! ```ignore
+//! let queue = SqliteRequestQueue::open("requests.db")?;
+//! queue.enqueue(&request_id, &operation)?;
+//! // Acknowledge the request to the caller.
+//!
+//! while let Some(request) = queue.take_next()? {
+//!     let result = request.operation.execute(&p_state, &opts);
+//!     // Deliver the result to the caller.
+//!     queue.ack(request.seq)?;
+//! }
+//! ```
+
+use std::{path::Path, sync::Mutex};
+
+use log::warn;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{
+    proof_gen::{ProofGenError, ProofGenResult},
+    remote::RemoteOperation,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS proof_requests (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        request_id TEXT NOT NULL UNIQUE,
+        operation BLOB NOT NULL,
+        taken INTEGER NOT NULL DEFAULT 0,
+        attempts INTEGER NOT NULL DEFAULT 0
+    );
+
+    CREATE TABLE IF NOT EXISTS undecodable_proof_requests (
+        seq INTEGER PRIMARY KEY,
+        request_id TEXT NOT NULL,
+        operation BLOB NOT NULL,
+        error TEXT NOT NULL
+    );
+";
+
+/// A request taken from a [`SqliteRequestQueue`].
+#[derive(Clone, Debug)]
+pub struct QueuedRequest {
+    /// The position of the request in the queue, for acknowledging it.
+    pub seq: i64,
+    pub request_id: String,
+    pub operation: RemoteOperation,
+
+    /// How many times the request has been taken, including this time. More
+    /// than one attempt means that a previous attempt failed or that the
+    /// prover restarted while proving it.
+    pub attempts: u32,
+}
+
+/// A persistent FIFO queue of proof requests.
+///
+/// Requests stay in the queue until they are acknowledged with
+/// [`SqliteRequestQueue::ack`]. Requests that were taken but not acknowledged
+/// when the queue is opened again are handed out again, so a queue file must
+/// only be used by one prover process at a time.
+pub struct SqliteRequestQueue {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteRequestQueue {
+    /// Open the queue in the database at `path`, creating it if it does not
+    /// exist yet.
+    pub fn open(path: impl AsRef<Path>) -> ProofGenResult<Self> {
+        let conn = Connection::open(path).map_err(storage_err)?;
+
+        // Every enqueued request has to survive a crash as soon as
+        // `enqueue` returns.
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = FULL;")
+            .map_err(storage_err)?;
+
+        Self::from_connection(conn)
+    }
+
+    /// Open a queue that only lives in memory, eg. for tests.
+    pub fn open_in_memory() -> ProofGenResult<Self> {
+        Self::from_connection(Connection::open_in_memory().map_err(storage_err)?)
+    }
+
+    fn from_connection(conn: Connection) -> ProofGenResult<Self> {
+        conn.execute_batch(SCHEMA).map_err(storage_err)?;
+
+        // The process that took these requests is gone.
+        conn.execute("UPDATE proof_requests SET taken = 0 WHERE taken = 1", [])
+            .map_err(storage_err)?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Durably add `operation` to the end of the queue. Returns `false` (and
+    /// leaves the queue as is) if a request with `request_id` is already
+    /// queued, so that callers can safely resubmit requests.
+    pub fn enqueue(&self, request_id: &str, operation: &RemoteOperation) -> ProofGenResult<bool> {
+        let operation = operation.to_bytes()?;
+        let inserted = self
+            .conn
+            .lock()
+            .expect("Lock is never poisoned")
+            .execute(
+                "INSERT OR IGNORE INTO proof_requests (request_id, operation) VALUES (?1, ?2)",
+                params![request_id, operation],
+            )
+            .map_err(storage_err)?;
+
+        Ok(inserted == 1)
+    }
+
+    /// Take the oldest request that is not taken yet. It stays in the queue
+    /// until it is acknowledged with [`SqliteRequestQueue::ack`] or given back
+    /// with [`SqliteRequestQueue::nack`].
+    ///
+    /// Requests that can not be decoded (eg. because they were enqueued by an
+    /// incompatible version of this crate) would block the queue forever, so
+    /// they are moved to the `undecodable_proof_requests` table instead of
+    /// being handed out.
+    pub fn take_next(&self) -> ProofGenResult<Option<QueuedRequest>> {
+        let mut conn = self.conn.lock().expect("Lock is never poisoned");
+        let tx = conn.transaction().map_err(storage_err)?;
+
+        loop {
+            let row = tx
+                .query_row(
+                    "SELECT seq, request_id, operation, attempts FROM proof_requests
+                     WHERE taken = 0 ORDER BY seq LIMIT 1",
+                    [],
+                    |row| {
+                        Ok((
+                            row.get::<_, i64>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, Vec<u8>>(2)?,
+                            row.get::<_, u32>(3)?,
+                        ))
+                    },
+                )
+                .optional()
+                .map_err(storage_err)?;
+            let Some((seq, request_id, operation, attempts)) = row else {
+                tx.commit().map_err(storage_err)?;
+                return Ok(None);
+            };
+
+            let operation = match RemoteOperation::from_bytes(&operation) {
+                Ok(operation) => operation,
+                Err(err) => {
+                    warn!(
+                        "Moving undecodable proof request {} out of the queue: {}",
+                        request_id, err
+                    );
+                    tx.execute(
+                        "INSERT INTO undecodable_proof_requests (seq, request_id, operation, error)
+                         SELECT seq, request_id, operation, ?2 FROM proof_requests WHERE seq = ?1",
+                        params![seq, err.to_string()],
+                    )
+                    .map_err(storage_err)?;
+                    tx.execute("DELETE FROM proof_requests WHERE seq = ?1", params![seq])
+                        .map_err(storage_err)?;
+                    continue;
+                }
+            };
+
+            tx.execute(
+                "UPDATE proof_requests SET taken = 1, attempts = attempts + 1 WHERE seq = ?1",
+                params![seq],
+            )
+            .map_err(storage_err)?;
+            tx.commit().map_err(storage_err)?;
+
+            return Ok(Some(QueuedRequest {
+                seq,
+                request_id,
+                operation,
+                attempts: attempts + 1,
+            }));
+        }
+    }
+
+    /// Remove the taken request `seq` from the queue, once its result has been
+    /// delivered.
+    pub fn ack(&self, seq: i64) -> ProofGenResult<()> {
+        self.update_taken(
+            seq,
+            "DELETE FROM proof_requests WHERE seq = ?1 AND taken = 1",
+        )
+    }
+
+    /// Give the taken request `seq` back, so that it is handed out again (eg.
+    /// after a transient failure).
+    pub fn nack(&self, seq: i64) -> ProofGenResult<()> {
+        self.update_taken(
+            seq,
+            "UPDATE proof_requests SET taken = 0 WHERE seq = ?1 AND taken = 1",
+        )
+    }
+
+    /// The number of requests in the queue, including the taken ones.
+    pub fn len(&self) -> ProofGenResult<usize> {
+        self.conn
+            .lock()
+            .expect("Lock is never poisoned")
+            .query_row("SELECT COUNT(*) FROM proof_requests", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|len| len as usize)
+            .map_err(storage_err)
+    }
+
+    pub fn is_empty(&self) -> ProofGenResult<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    fn update_taken(&self, seq: i64, sql: &str) -> ProofGenResult<()> {
+        let updated = self
+            .conn
+            .lock()
+            .expect("Lock is never poisoned")
+            .execute(sql, params![seq])
+            .map_err(storage_err)?;
+
+        match updated {
+            1 => Ok(()),
+            _ => Err(ProofGenError::InvalidInput(format!(
+                "Request {} is not taken",
+                seq
+            ))),
+        }
+    }
+}
+
+fn storage_err(err: rusqlite::Error) -> ProofGenError {
+    ProofGenError::Storage(err.to_string())
+}
@@ -0,0 +1,796 @@
+//! Scheduling of the txn → agg → block proof DAG.
+//!
+//! A [`ProofDag`] models the proofs that need to be generated and which proofs
+//! each of them depends on. It hands out the jobs whose dependencies are done
+//! and tracks their completion, so that services built on this crate do not
+//! have to reimplement the dependency tracking around
+//! [`generate_agg_proof`](crate::proof_gen::generate_agg_proof) and
+//! [`generate_block_proof`](crate::proof_gen::generate_block_proof).
+//! [`run_dag`] executes a DAG on a pool of local threads.
+
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::BlockProverBackend,
+    dummy::generate_dummy_txn_proof_after,
+    options::ProofGenOptions,
+    proof_gen::{catch_prover_panic, ProofGenError, ProofGenResult},
+    proof_key::ProofKey,
+    proof_store::{ProofStore, ProofStoreExt},
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    serialization::ProofKind,
+    types::CircuitDigests,
+};
+
+/// Identifies a job in a [`ProofDag`].
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct JobId(pub usize);
+
+impl Display for JobId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// The state of a job in a [`ProofDag`].
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum JobStatus {
+    /// Waiting for its dependencies or to be taken.
+    Pending,
+
+    /// Taken by [`ProofDag::take_job`] and not completed yet.
+    Running,
+
+    Done,
+
+    /// The job failed. Failed jobs are not handed out again until
+    /// [`ProofDag::retry`] is called for them.
+    Failed,
+}
+
+/// How urgently a job should run. Ready jobs with a higher priority are handed
+/// out before the ones with a lower priority.
+///
+/// Running jobs can not be interrupted, so a high-priority job only skips the
+/// queue of jobs that have not been taken yet.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+pub enum JobPriority {
+    /// Proving (already proven) blocks again.
+    Reproof,
+
+    /// Catching up on historical blocks.
+    #[default]
+    Backfill,
+
+    /// Proving the latest blocks of the chain, which is latency-critical.
+    ChainHead,
+}
+
+/// The proof generated by a job.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum JobOutput {
+    Txn(GeneratedTxnProof),
+    Agg(GeneratedAggProof),
+    Block(GeneratedBlockProof),
+}
+
+impl JobOutput {
+    /// The key of the proof, given the circuit digests of the prover that
+    /// generated it.
+    pub fn proof_key(&self, digests: &CircuitDigests) -> ProofKey {
+        match self {
+            JobOutput::Txn(proof) => ProofKey::for_txn_proof(proof, digests.root),
+            JobOutput::Agg(proof) => ProofKey::for_agg_proof(proof, digests.aggregation),
+            JobOutput::Block(proof) => ProofKey::for_block_proof(proof, digests.block),
+        }
+    }
+
+    /// Store the proof in `store` unless a proof with the same key is already
+    /// stored. Returns whether it was stored.
+    pub fn put_if_absent(
+        &self,
+        store: &dyn ProofStore,
+        digests: &CircuitDigests,
+    ) -> ProofGenResult<bool> {
+        let (_, stored) = match self {
+            JobOutput::Txn(proof) => store.put_proof_if_absent(proof, &digests.root)?,
+            JobOutput::Agg(proof) => store.put_proof_if_absent(proof, &digests.aggregation)?,
+            JobOutput::Block(proof) => store.put_proof_if_absent(proof, &digests.block)?,
+        };
+
+        Ok(stored)
+    }
+
+    pub fn kind(&self) -> ProofKind {
+        match self {
+            JobOutput::Txn(_) => ProofKind::Txn,
+            JobOutput::Agg(_) => ProofKind::Agg,
+            JobOutput::Block(_) => ProofKind::Block,
+        }
+    }
+
+    fn to_aggregatable(&self) -> Option<AggregatableProof> {
+        match self {
+            JobOutput::Txn(proof) => Some(proof.clone().into()),
+            JobOutput::Agg(proof) => Some(proof.clone().into()),
+            JobOutput::Block(_) => None,
+        }
+    }
+}
+
+/// A job whose dependencies are done, along with everything needed to
+/// execute it.
+///
+/// Ready jobs own all of their inputs, so they can be sent to wherever they
+/// get executed.
+#[derive(Clone, Debug)]
+pub enum ReadyJob {
+    Txn(TxnProofGenIR),
+    DummyTxnAfter {
+        prev_txn_proof: GeneratedTxnProof,
+        other_data: OtherBlockData,
+    },
+    Agg {
+        lhs_child: AggregatableProof,
+        rhs_child: AggregatableProof,
+        other_data: OtherBlockData,
+    },
+    Block {
+        prev_opt_parent_b_proof: Option<GeneratedBlockProof>,
+        curr_block_agg_proof: GeneratedAggProof,
+        other_data: OtherBlockData,
+    },
+}
+
+impl ReadyJob {
+    /// Generate the proof of the job with `p_state`.
+    pub fn execute<B: BlockProverBackend + ?Sized>(
+        self,
+        p_state: &B,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<JobOutput> {
+        match self {
+            ReadyJob::Txn(ir) => p_state.generate_txn_proof(ir, opts).map(JobOutput::Txn),
+            ReadyJob::DummyTxnAfter {
+                prev_txn_proof,
+                other_data,
+            } => generate_dummy_txn_proof_after(p_state, &prev_txn_proof, &other_data, opts)
+                .map(JobOutput::Txn),
+            ReadyJob::Agg {
+                lhs_child,
+                rhs_child,
+                other_data,
+            } => p_state
+                .generate_agg_proof(&lhs_child, &rhs_child, other_data, opts)
+                .map(JobOutput::Agg),
+            ReadyJob::Block {
+                prev_opt_parent_b_proof,
+                curr_block_agg_proof,
+                other_data,
+            } => p_state
+                .generate_block_proof(
+                    prev_opt_parent_b_proof.as_ref(),
+                    &curr_block_agg_proof,
+                    other_data,
+                    opts,
+                )
+                .map(JobOutput::Block),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+enum Job {
+    Txn(TxnProofGenIR),
+    DummyTxnAfter {
+        prev: JobId,
+        other_data: OtherBlockData,
+    },
+    Agg {
+        lhs: JobId,
+        rhs: JobId,
+        other_data: OtherBlockData,
+    },
+    Block {
+        agg: JobId,
+        parent: Option<JobId>,
+        other_data: OtherBlockData,
+    },
+
+    /// A proof that was added to the DAG as is.
+    Given,
+}
+
+impl Job {
+    fn deps(&self) -> Vec<JobId> {
+        match self {
+            Job::Txn(_) | Job::Given => Vec::new(),
+            Job::DummyTxnAfter { prev, .. } => vec![*prev],
+            Job::Agg { lhs, rhs, .. } => vec![*lhs, *rhs],
+            Job::Block { agg, parent, .. } => std::iter::once(*agg).chain(*parent).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct JobNode {
+    job: Job,
+    kind: ProofKind,
+    status: JobStatus,
+    priority: JobPriority,
+    output: Option<JobOutput>,
+
+    /// The jobs depending on this one.
+    dependents: Vec<JobId>,
+
+    /// The number of jobs depending on this one that are not done yet.
+    pending_dependents: usize,
+}
+
+/// The DAG of proofs to generate.
+///
+/// Jobs are added with their dependencies, which must already be in the DAG.
+/// Once every job that depends on a proof is done, the proof is dropped to save
+/// memory, so only the proofs that nothing depends on (ie. usually the block
+/// proofs) are kept until they are taken with [`ProofDag::take_output`].
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ProofDag {
+    jobs: Vec<JobNode>,
+}
+
+impl ProofDag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a job that proves the txn `ir`.
+    pub fn add_txn(&mut self, ir: TxnProofGenIR) -> JobId {
+        self.push(Job::Txn(ir), ProofKind::Txn, None)
+    }
+
+    /// Add a job that proves a dummy txn directly following the txn proven by
+    /// `prev`, so that a lone txn proof can be aggregated.
+    pub fn add_dummy_txn_after(
+        &mut self,
+        prev: JobId,
+        other_data: OtherBlockData,
+    ) -> ProofGenResult<JobId> {
+        self.check_dep(prev, &[ProofKind::Txn])?;
+
+        Ok(self.push(
+            Job::DummyTxnAfter { prev, other_data },
+            ProofKind::Txn,
+            None,
+        ))
+    }
+
+    /// Add a job that aggregates the proofs of `lhs` and `rhs`.
+    pub fn add_agg(
+        &mut self,
+        lhs: JobId,
+        rhs: JobId,
+        other_data: OtherBlockData,
+    ) -> ProofGenResult<JobId> {
+        self.check_dep(lhs, &[ProofKind::Txn, ProofKind::Agg])?;
+        self.check_dep(rhs, &[ProofKind::Txn, ProofKind::Agg])?;
+
+        Ok(self.push(
+            Job::Agg {
+                lhs,
+                rhs,
+                other_data,
+            },
+            ProofKind::Agg,
+            None,
+        ))
+    }
+
+    /// Add a job that proves a block from the agg proof of `agg`, on top of
+    /// the block proof of `parent` (which, like with
+    /// [`generate_block_proof`](crate::proof_gen::generate_block_proof), is
+    /// able to be `None` on checkpoint heights).
+    pub fn add_block(
+        &mut self,
+        agg: JobId,
+        parent: Option<JobId>,
+        other_data: OtherBlockData,
+    ) -> ProofGenResult<JobId> {
+        self.check_dep(agg, &[ProofKind::Agg])?;
+        if let Some(parent) = parent {
+            self.check_dep(parent, &[ProofKind::Block])?;
+        }
+
+        Ok(self.push(
+            Job::Block {
+                agg,
+                parent,
+                other_data,
+            },
+            ProofKind::Block,
+            None,
+        ))
+    }
+
+    /// Add an already generated proof (eg. the block proof of the parent of the
+    /// first block to prove), so that other jobs can depend on it.
+    pub fn add_proof(&mut self, proof: JobOutput) -> JobId {
+        let kind = proof.kind();
+        self.push(Job::Given, kind, Some(proof))
+    }
+
+    /// Add all of the jobs needed to prove a block from the IR of its txns,
+    /// aggregating the txn proofs with a balanced aggregation tree. Returns
+    /// the job of the block proof.
+    ///
+    /// `txns` must be in txn order and be non-empty.
+    pub fn add_block_from_ir(
+        &mut self,
+        txns: Vec<TxnProofGenIR>,
+        other_data: OtherBlockData,
+        parent: Option<JobId>,
+    ) -> ProofGenResult<JobId> {
+        if txns.is_empty() {
+            return Err(ProofGenError::InvalidInput(
+                "Proving a block requires at least one txn".to_string(),
+            ));
+        }
+
+        let mut leaves: Vec<_> = txns.into_iter().map(|ir| self.add_txn(ir)).collect();
+        if leaves.len() == 1 {
+            leaves.push(self.add_dummy_txn_after(leaves[0], other_data.clone())?);
+        }
+
+        let agg = self.add_agg_subtree(&leaves, &other_data)?;
+        self.add_block(agg, parent, other_data)
+    }
+
+    /// Adds a balanced aggregation tree over `leaves`, which must contain at
+    /// least two jobs.
+    fn add_agg_subtree(
+        &mut self,
+        leaves: &[JobId],
+        other_data: &OtherBlockData,
+    ) -> ProofGenResult<JobId> {
+        if leaves.len() == 1 {
+            return Ok(leaves[0]);
+        }
+
+        let (lhs, rhs) = leaves.split_at(leaves.len() / 2);
+        let lhs = self.add_agg_subtree(lhs, other_data)?;
+        let rhs = self.add_agg_subtree(rhs, other_data)?;
+
+        self.add_agg(lhs, rhs, other_data.clone())
+    }
+
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        self.jobs.get(id.0).map(|node| node.status)
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Whether every job in the DAG is done.
+    pub fn is_complete(&self) -> bool {
+        self.jobs.iter().all(|node| node.status == JobStatus::Done)
+    }
+
+    /// The number of jobs that have been taken but not completed yet.
+    pub fn num_running(&self) -> usize {
+        self.jobs
+            .iter()
+            .filter(|node| node.status == JobStatus::Running)
+            .count()
+    }
+
+    /// The pending jobs whose dependencies are all done, by descending
+    /// priority (and in the order they were added within a priority).
+    pub fn ready_jobs(&self) -> Vec<JobId> {
+        let mut ready: Vec<_> = (0..self.jobs.len())
+            .map(JobId)
+            .filter(|id| self.is_ready(*id))
+            .collect();
+        ready.sort_by_cached_key(|id| Reverse(self.effective_priority(*id)));
+
+        ready
+    }
+
+    /// The priority that job `id` was given with [`ProofDag::set_priority`].
+    pub fn priority(&self, id: JobId) -> Option<JobPriority> {
+        self.jobs.get(id.0).map(|node| node.priority)
+    }
+
+    /// Give job `id` the priority `priority`.
+    ///
+    /// A job can not run before the jobs that it depends on, so jobs are
+    /// handed out by their effective priority, which is the highest priority
+    /// of the job itself and of every job depending on it (directly or
+    /// indirectly). Setting the priority of a block proof job is thus enough
+    /// to prioritize all of the proofs of the block.
+    pub fn set_priority(&mut self, id: JobId, priority: JobPriority) -> ProofGenResult<()> {
+        match self.jobs.get_mut(id.0) {
+            Some(node) => {
+                node.priority = priority;
+                Ok(())
+            }
+            None => Err(ProofGenError::InvalidInput(format!(
+                "Job {} is not part of the DAG",
+                id
+            ))),
+        }
+    }
+
+    /// The highest priority of job `id` and of every job depending on it.
+    fn effective_priority(&self, id: JobId) -> JobPriority {
+        let mut priority = self.jobs[id.0].priority;
+        let mut visited = HashSet::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if visited.insert(id) {
+                let node = &self.jobs[id.0];
+                priority = priority.max(node.priority);
+                stack.extend(&node.dependents);
+            }
+        }
+
+        priority
+    }
+
+    /// Take the ready job `id` to execute it. The job is running until either
+    /// [`ProofDag::complete`] or [`ProofDag::fail`] are called for it.
+    pub fn take_job(&mut self, id: JobId) -> ProofGenResult<ReadyJob> {
+        if !self.is_ready(id) {
+            return Err(ProofGenError::InvalidInput(format!(
+                "Job {} is not ready to run",
+                id
+            )));
+        }
+
+        let output = |dep: &JobId| self.jobs[dep.0].output.as_ref().unwrap();
+        let job = match &self.jobs[id.0].job {
+            Job::Txn(ir) => ReadyJob::Txn(ir.clone()),
+            Job::DummyTxnAfter { prev, other_data } => match output(prev) {
+                JobOutput::Txn(prev_txn_proof) => ReadyJob::DummyTxnAfter {
+                    prev_txn_proof: prev_txn_proof.clone(),
+                    other_data: other_data.clone(),
+                },
+                _ => unreachable!("Checked the kind of the dependency"),
+            },
+            Job::Agg {
+                lhs,
+                rhs,
+                other_data,
+            } => ReadyJob::Agg {
+                lhs_child: output(lhs).to_aggregatable().unwrap(),
+                rhs_child: output(rhs).to_aggregatable().unwrap(),
+                other_data: other_data.clone(),
+            },
+            Job::Block {
+                agg,
+                parent,
+                other_data,
+            } => {
+                let parent = parent.map(|parent| match output(&parent) {
+                    JobOutput::Block(b_proof) => b_proof.clone(),
+                    _ => unreachable!("Checked the kind of the dependency"),
+                });
+
+                match output(agg) {
+                    JobOutput::Agg(agg_proof) => ReadyJob::Block {
+                        prev_opt_parent_b_proof: parent,
+                        curr_block_agg_proof: agg_proof.clone(),
+                        other_data: other_data.clone(),
+                    },
+                    _ => unreachable!("Checked the kind of the dependency"),
+                }
+            }
+            Job::Given => unreachable!("Given proofs are always done"),
+        };
+
+        self.jobs[id.0].status = JobStatus::Running;
+
+        Ok(job)
+    }
+
+    /// Record that the running job `id` generated `output`.
+    pub fn complete(&mut self, id: JobId, output: JobOutput) -> ProofGenResult<()> {
+        let node = self.running_job(id)?;
+        if output.kind() != node.kind {
+            return Err(ProofGenError::IncompatibleProof(format!(
+                "Job {} generates a {} proof, but got a {} proof",
+                id,
+                node.kind,
+                output.kind()
+            )));
+        }
+
+        node.status = JobStatus::Done;
+        node.output = Some(output);
+
+        // Txn IR can be large and is not needed anymore.
+        if let Job::Txn(_) = node.job {
+            node.job = Job::Given;
+        }
+
+        for dep in self.jobs[id.0].job.deps() {
+            let dep = &mut self.jobs[dep.0];
+            dep.pending_dependents -= 1;
+            if dep.pending_dependents == 0 {
+                dep.output = None;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record that the running job `id` failed.
+    pub fn fail(&mut self, id: JobId) -> ProofGenResult<()> {
+        self.running_job(id)?.status = JobStatus::Failed;
+        Ok(())
+    }
+
+    /// Make the failed job `id` pending again.
+    pub fn retry(&mut self, id: JobId) -> ProofGenResult<()> {
+        match self.jobs.get_mut(id.0) {
+            Some(node) if node.status == JobStatus::Failed => {
+                node.status = JobStatus::Pending;
+                Ok(())
+            }
+            _ => Err(ProofGenError::InvalidInput(format!(
+                "Job {} has not failed",
+                id
+            ))),
+        }
+    }
+
+    /// Make the running job `id` pending again, eg. because the worker that
+    /// was executing it went away. Its result is expected to be lost.
+    pub fn requeue(&mut self, id: JobId) -> ProofGenResult<()> {
+        self.running_job(id)?.status = JobStatus::Pending;
+        Ok(())
+    }
+
+    /// Serialize the DAG, including every proof that it still holds, so that it
+    /// can be continued with [`ProofDag::restore`] (eg. after draining and
+    /// restarting a prover node).
+    pub fn snapshot(&self) -> ProofGenResult<Vec<u8>> {
+        bincode::serialize(self).map_err(|err| ProofGenError::Serialization(err.to_string()))
+    }
+
+    /// Deserialize a DAG from a snapshot. Jobs that were running when the
+    /// snapshot was taken are pending again, since their results were lost.
+    pub fn restore(bytes: &[u8]) -> ProofGenResult<Self> {
+        let mut dag: Self = bincode::deserialize(bytes)
+            .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+
+        for node in &mut dag.jobs {
+            if node.status == JobStatus::Running {
+                node.status = JobStatus::Pending;
+            }
+        }
+
+        Ok(dag)
+    }
+
+    /// The proof generated by job `id`, if it is done and the proof has not
+    /// been dropped or taken yet.
+    pub fn output(&self, id: JobId) -> Option<&JobOutput> {
+        self.jobs.get(id.0)?.output.as_ref()
+    }
+
+    /// Store every proof that the DAG still holds in `store`, eg. before
+    /// shutting down. Returns the number of proofs stored.
+    ///
+    /// `digests` must be the circuit digests of the prover that generated the
+    /// proofs.
+    pub fn persist_outputs(
+        &self,
+        store: &dyn ProofStore,
+        digests: &CircuitDigests,
+    ) -> ProofGenResult<usize> {
+        let mut num_stored = 0;
+        for output in self.jobs.iter().filter_map(|node| node.output.as_ref()) {
+            match output {
+                JobOutput::Txn(proof) => store.put_proof(proof, &digests.root)?,
+                JobOutput::Agg(proof) => store.put_proof(proof, &digests.aggregation)?,
+                JobOutput::Block(proof) => store.put_proof(proof, &digests.block)?,
+            };
+            num_stored += 1;
+        }
+
+        Ok(num_stored)
+    }
+
+    /// Take the proof generated by job `id` out of the DAG.
+    ///
+    /// Jobs depending on `id` that have not been taken yet can not run
+    /// anymore afterwards.
+    pub fn take_output(&mut self, id: JobId) -> Option<JobOutput> {
+        self.jobs.get_mut(id.0)?.output.take()
+    }
+
+    fn push(&mut self, job: Job, kind: ProofKind, output: Option<JobOutput>) -> JobId {
+        let id = JobId(self.jobs.len());
+        for dep in job.deps() {
+            self.jobs[dep.0].dependents.push(id);
+            self.jobs[dep.0].pending_dependents += 1;
+        }
+
+        let status = match output.is_some() {
+            true => JobStatus::Done,
+            false => JobStatus::Pending,
+        };
+
+        self.jobs.push(JobNode {
+            job,
+            kind,
+            status,
+            priority: JobPriority::default(),
+            output,
+            dependents: Vec::new(),
+            pending_dependents: 0,
+        });
+
+        id
+    }
+
+    fn check_dep(&self, dep: JobId, kinds: &[ProofKind]) -> ProofGenResult<()> {
+        let Some(node) = self.jobs.get(dep.0) else {
+            return Err(ProofGenError::InvalidInput(format!(
+                "Job {} is not part of the DAG",
+                dep
+            )));
+        };
+
+        if !kinds.contains(&node.kind) {
+            return Err(ProofGenError::IncompatibleProof(format!(
+                "Job {} generates a {} proof, which can not be used here",
+                dep, node.kind
+            )));
+        }
+
+        if node.status == JobStatus::Done && node.output.is_none() {
+            return Err(ProofGenError::InvalidInput(format!(
+                "The proof of job {} is no longer in the DAG",
+                dep
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn is_ready(&self, id: JobId) -> bool {
+        match self.jobs.get(id.0) {
+            Some(node) => {
+                node.status == JobStatus::Pending
+                    && node
+                        .job
+                        .deps()
+                        .iter()
+                        .all(|dep| self.jobs[dep.0].output.is_some())
+            }
+            None => false,
+        }
+    }
+
+    fn running_job(&mut self, id: JobId) -> ProofGenResult<&mut JobNode> {
+        match self.jobs.get_mut(id.0) {
+            Some(node) if node.status == JobStatus::Running => Ok(node),
+            _ => Err(ProofGenError::InvalidInput(format!(
+                "Job {} is not running",
+                id
+            ))),
+        }
+    }
+}
+
+/// Execute every job in `dag` with `p_state`, running up to
+/// `max_concurrent_jobs` jobs at a time on their own threads.
+///
+/// Plonky2 already parallelizes each proof, so running a few jobs concurrently
+/// mainly helps to keep the cores busy during the sequential parts of proving.
+/// On the first failure, no more jobs are started and the error is returned
+/// once the running jobs have finished. The DAG records which jobs failed, so
+/// they can be retried.
+pub fn run_dag<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    dag: &mut ProofDag,
+    max_concurrent_jobs: usize,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<()> {
+    run_dag_until(
+        p_state,
+        dag,
+        max_concurrent_jobs,
+        opts,
+        &AtomicBool::new(false),
+    )
+}
+
+/// Like [`run_dag`], but stops starting jobs once `shutdown` is set.
+///
+/// The jobs that are already running are finished rather than aborted, so that
+/// a shutdown does not throw away proofs that are almost done. Once they have
+/// finished, this returns `Ok(())` with the rest of the jobs still pending. To
+/// pick up where it left off after a restart, persist the finished proofs with
+/// [`ProofDag::persist_outputs`] and the DAG itself with
+/// [`ProofDag::snapshot`].
+pub fn run_dag_until<B: BlockProverBackend + ?Sized>(
+    p_state: &B,
+    dag: &mut ProofDag,
+    max_concurrent_jobs: usize,
+    opts: &ProofGenOptions,
+    shutdown: &AtomicBool,
+) -> ProofGenResult<()> {
+    let max_concurrent_jobs = max_concurrent_jobs.max(1);
+
+    thread::scope(|scope| {
+        let (result_tx, result_rx) = mpsc::channel();
+        let mut first_err = None;
+        let mut running = 0;
+
+        loop {
+            if first_err.is_none() && !shutdown.load(Ordering::Relaxed) {
+                for id in dag
+                    .ready_jobs()
+                    .into_iter()
+                    .take(max_concurrent_jobs - running)
+                {
+                    let job = dag.take_job(id)?;
+                    let result_tx = result_tx.clone();
+                    scope.spawn(move || {
+                        // Other backends than `ProverState` may panic, and a
+                        // job that panics without sending its result would
+                        // block the loop forever.
+                        let result = catch_prover_panic(|| job.execute(p_state, opts))
+                            .and_then(|result| result);
+
+                        // The receiver outlives every job.
+                        let _ = result_tx.send((id, result));
+                    });
+                    running += 1;
+                }
+            }
+
+            if running == 0 {
+                break;
+            }
+
+            let (id, result) = result_rx
+                .recv()
+                .expect("Every running job sends its result, even if it panics");
+            running -= 1;
+
+            match result {
+                Ok(output) => dag.complete(id, output)?,
+                Err(err) => {
+                    dag.fail(id)?;
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    })
+}
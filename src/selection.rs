@@ -0,0 +1,113 @@
+use std::ops::Range;
+
+use log::{info, warn};
+use plonky2_evm::all_stark::NUM_TABLES;
+use proof_protocol_decoder::types::TxnProofGenIR;
+
+use crate::{
+    estimation::DegreeBitsEstimator,
+    options::ProofGenOptions,
+    proof_gen::{generate_txn_proof, ProofGenResult},
+    proof_types::GeneratedTxnProof,
+    prover_state::{LazyProverState, ProverState, ProverStateBuilder},
+};
+
+/// Picks the prover state to prove txns with based on their estimated sizes.
+///
+/// Candidates are tried in the order they were added, so the default (and
+/// usually smallest) circuits should come first. Every candidate is only built
+/// the first time a txn gets routed to it.
+///
+/// Note that txn proofs can only be aggregated by the prover state that
+/// generated them. If the txns of a block need to be aggregated, select a
+/// single prover state for all of them with
+/// [`CircuitSelector::select_for_block`].
+pub struct CircuitSelector {
+    candidates: Vec<LazyProverState>,
+    estimator: DegreeBitsEstimator,
+}
+
+impl CircuitSelector {
+    pub fn new(default: ProverStateBuilder) -> Self {
+        Self {
+            candidates: vec![default.into()],
+            estimator: DegreeBitsEstimator::default(),
+        }
+    }
+
+    /// Add circuits to use for txns that the previously added circuits are not
+    /// estimated to fit.
+    pub fn add_candidate(mut self, builder: ProverStateBuilder) -> Self {
+        self.candidates.push(builder.into());
+        self
+    }
+
+    /// Estimate the sizes of txns with `estimator` instead of with the default
+    /// estimator.
+    pub fn set_estimator(mut self, estimator: DegreeBitsEstimator) -> Self {
+        self.estimator = estimator;
+        self
+    }
+
+    /// Select the prover state to prove `ir` with.
+    ///
+    /// Fails if the selected prover state has not been built yet and building
+    /// it fails (see [`LazyProverState::get`]).
+    pub fn select(&self, ir: &TxnProofGenIR) -> ProofGenResult<&ProverState> {
+        self.select_for_block(std::slice::from_ref(ir))
+    }
+
+    /// Select a single prover state that is estimated to fit every txn in
+    /// `txns`.
+    ///
+    /// If no candidate is estimated to fit them all, the last (and presumably
+    /// largest) candidate gets selected anyways, since the estimate is only a
+    /// heuristic.
+    pub fn select_for_block(&self, txns: &[TxnProofGenIR]) -> ProofGenResult<&ProverState> {
+        let mut required = [0; NUM_TABLES];
+        for ir in txns {
+            for (required, bits) in required.iter_mut().zip(self.estimator.estimate(ir)) {
+                *required = (*required).max(bits);
+            }
+        }
+
+        let fits = |sizes: &[Range<usize>; NUM_TABLES]| {
+            sizes
+                .iter()
+                .zip(required.iter())
+                .all(|(supported, bits)| supported.contains(bits))
+        };
+
+        let candidate = match self.candidates.iter().find(|c| fits(c.circuit_sizes())) {
+            Some(candidate) => candidate,
+            None => {
+                let largest = self.candidates.last().expect("There is always a default");
+                warn!(
+                    "No circuits are estimated to fit degree bits {:?}, falling back to circuit \
+                     sizes {:?}",
+                    required,
+                    largest.circuit_sizes()
+                );
+
+                largest
+            }
+        };
+
+        info!(
+            "Selected circuit sizes {:?} for estimated degree bits {:?}",
+            candidate.circuit_sizes(),
+            required
+        );
+
+        candidate.get()
+    }
+
+    /// Generate a txn proof with the prover state selected for `ir`.
+    pub fn generate_txn_proof(
+        &self,
+        ir: TxnProofGenIR,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedTxnProof> {
+        generate_txn_proof(self.select(&ir)?, ir, opts)
+    }
+}
@@ -0,0 +1,232 @@
+use std::fmt::{self, Display, Formatter};
+
+use ethereum_types::H256;
+use keccak_hash::keccak;
+use plonky2::field::types::PrimeField64;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    metadata::ProverMetadata,
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_types::{GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    types::CircuitDigest,
+};
+
+/// Every serialized proof starts with these bytes.
+const MAGIC: [u8; 4] = *b"PBPG";
+
+/// Bumped whenever the layout of the serialized proofs changes.
+pub const FORMAT_VERSION: u16 = 4;
+
+/// `MAGIC`, the format version, the proof kind and the circuit digest.
+const HEADER_LEN: usize = 4 + 2 + 1 + 32;
+
+/// The header is followed by the length of the serialized prover metadata.
+const METADATA_LEN_LEN: usize = 4;
+
+/// The kind of a generated proof.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ProofKind {
+    Txn,
+    Agg,
+    Block,
+}
+
+impl Display for ProofKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ProofKind::Txn => write!(f, "txn"),
+            ProofKind::Agg => write!(f, "agg"),
+            ProofKind::Block => write!(f, "block"),
+        }
+    }
+}
+
+impl ProofKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            ProofKind::Txn => 0,
+            ProofKind::Agg => 1,
+            ProofKind::Block => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(ProofKind::Txn),
+            1 => Some(ProofKind::Agg),
+            2 => Some(ProofKind::Block),
+            _ => None,
+        }
+    }
+}
+
+/// Serializes `proof` behind a header that identifies the format version, the
+/// kind of the proof and the digest of the circuit that it is a proof of,
+/// followed by `metadata`.
+fn proof_to_bytes<P: Serialize>(
+    proof: &P,
+    kind: ProofKind,
+    circuit_digest: &CircuitDigest,
+    metadata: &ProverMetadata,
+) -> ProofGenResult<Vec<u8>> {
+    let metadata = bincode::serialize(metadata)
+        .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + METADATA_LEN_LEN + metadata.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.push(kind.to_byte());
+    bytes.extend(digest_to_bytes(circuit_digest));
+    bytes.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+    bytes.extend(metadata);
+
+    bincode::serialize_into(&mut bytes, proof)
+        .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+
+    Ok(bytes)
+}
+
+/// The parts of a serialized proof.
+struct SerializedProof<'a> {
+    header: &'a [u8],
+    metadata: &'a [u8],
+    body: &'a [u8],
+}
+
+/// Splits a proof created by `proof_to_bytes` into its parts, checking that it
+/// was created with the current format version.
+fn split_proof_bytes(bytes: &[u8]) -> ProofGenResult<SerializedProof<'_>> {
+    if bytes.len() < HEADER_LEN + METADATA_LEN_LEN || bytes[..4] != MAGIC {
+        return Err(ProofGenError::IncompatibleProof(
+            "Bytes are not a serialized proof".to_string(),
+        ));
+    }
+
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != FORMAT_VERSION {
+        return Err(ProofGenError::IncompatibleProof(format!(
+            "Proof has format version {} but expected version {}",
+            version, FORMAT_VERSION
+        )));
+    }
+
+    let (header, rest) = bytes.split_at(HEADER_LEN);
+    let (metadata_len, rest) = rest.split_at(METADATA_LEN_LEN);
+    let metadata_len =
+        u32::from_le_bytes(metadata_len.try_into().expect("Length has 4 bytes")) as usize;
+    if rest.len() < metadata_len {
+        return Err(ProofGenError::IncompatibleProof(
+            "Serialized proof is truncated".to_string(),
+        ));
+    }
+    let (metadata, body) = rest.split_at(metadata_len);
+
+    Ok(SerializedProof {
+        header,
+        metadata,
+        body,
+    })
+}
+
+/// Deserializes a proof created by `proof_to_bytes`, checking that it was
+/// created with the current format version and is a proof of the expected kind
+/// and circuit.
+fn proof_from_bytes<P: DeserializeOwned>(
+    bytes: &[u8],
+    kind: ProofKind,
+    expected_circuit_digest: &CircuitDigest,
+) -> ProofGenResult<P> {
+    let SerializedProof { header, body, .. } = split_proof_bytes(bytes)?;
+
+    let actual_kind = ProofKind::from_byte(header[6]);
+    if actual_kind != Some(kind) {
+        return Err(ProofGenError::IncompatibleProof(format!(
+            "Expected a {:?} proof but got {:?}",
+            kind, actual_kind
+        )));
+    }
+
+    if header[7..HEADER_LEN] != digest_to_bytes(expected_circuit_digest) {
+        return Err(ProofGenError::IncompatibleProof(
+            "Proof was generated for a different circuit".to_string(),
+        ));
+    }
+
+    bincode::deserialize(body).map_err(|err| ProofGenError::Serialization(err.to_string()))
+}
+
+/// Read the metadata of the prover that serialized a proof in the versioned
+/// binary proof format, without deserializing the proof itself.
+pub fn read_prover_metadata(bytes: &[u8]) -> ProofGenResult<ProverMetadata> {
+    let SerializedProof { metadata, .. } = split_proof_bytes(bytes)?;
+
+    bincode::deserialize(metadata).map_err(|err| ProofGenError::Serialization(err.to_string()))
+}
+
+/// The keccak hash of a proof in the versioned binary proof format, excluding
+/// its prover metadata.
+///
+/// Serializing the same proof twice gives different bytes (the metadata
+/// records when the proof was serialized), but always the same hash.
+pub fn proof_hash(bytes: &[u8]) -> ProofGenResult<H256> {
+    let SerializedProof { header, body, .. } = split_proof_bytes(bytes)?;
+
+    Ok(keccak([header, body].concat()))
+}
+
+fn digest_to_bytes(digest: &CircuitDigest) -> Vec<u8> {
+    digest
+        .elements
+        .iter()
+        .flat_map(|elem| elem.to_canonical_u64().to_le_bytes())
+        .collect()
+}
+
+macro_rules! impl_versioned_serialization {
+    ($proof:ty, $kind:expr, $circuit:literal) => {
+        impl $proof {
+            #[doc = concat!(
+                "Serialize the proof into the versioned binary proof format, along with ",
+                "the metadata of this process and the measured peak memory usage of the ",
+                "proof (see `ProverMetadata`).\n\n",
+                "`circuit_digest` must be the digest of the ", $circuit, " circuit."
+            )]
+            pub fn to_bytes(&self, circuit_digest: &CircuitDigest) -> ProofGenResult<Vec<u8>> {
+                let metadata = ProverMetadata {
+                    peak_rss_bytes: self.peak_rss_bytes,
+                    ..ProverMetadata::current(circuit_digest)
+                };
+
+                proof_to_bytes(self, $kind, circuit_digest, &metadata)
+            }
+
+            /// Same as `to_bytes`, but embeds `metadata` instead of the
+            /// metadata of this process, eg. to keep the metadata of the prover
+            /// that originally serialized the proof.
+            pub fn to_bytes_with_metadata(
+                &self,
+                circuit_digest: &CircuitDigest,
+                metadata: &ProverMetadata,
+            ) -> ProofGenResult<Vec<u8>> {
+                proof_to_bytes(self, $kind, circuit_digest, metadata)
+            }
+
+            #[doc = concat!(
+                "Deserialize a proof from the versioned binary proof format, failing with ",
+                "`ProofGenError::IncompatibleProof` if it was not generated for the ", $circuit,
+                " circuit with the digest `circuit_digest`."
+            )]
+            pub fn from_bytes(bytes: &[u8], circuit_digest: &CircuitDigest) -> ProofGenResult<Self> {
+                let mut proof: Self = proof_from_bytes(bytes, $kind, circuit_digest)?;
+                proof.peak_rss_bytes = read_prover_metadata(bytes)?.peak_rss_bytes;
+
+                Ok(proof)
+            }
+        }
+    };
+}
+
+impl_versioned_serialization!(GeneratedTxnProof, ProofKind::Txn, "root");
+impl_versioned_serialization!(GeneratedAggProof, ProofKind::Agg, "aggregation");
+impl_versioned_serialization!(GeneratedBlockProof, ProofKind::Block, "block");
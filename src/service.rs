@@ -0,0 +1,365 @@
+//! A gRPC proving service (see `proto/prover.proto`) backed by a shared
+//! [`ProverState`], for running the prover as a standalone sidecar.
+//!
+//! ```ignore
+//! tonic::transport::Server::builder()
+//!     .add_service(ProverService::new(p_state).into_server())
+//!     .serve(addr)
+//!     .await?;
+//! ```
+
+use std::{pin::Pin, sync::Arc};
+
+use futures::Stream;
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+use serde::de::DeserializeOwned;
+use tokio::task;
+use tonic::{Request, Response, Status};
+
+use crate::{
+    admission::{AdmissionControl, AdmissionLimits, AdmissionPermit},
+    async_proof_gen::{
+        generate_agg_proof_async, generate_block_proof_async, generate_txn_proof_async,
+    },
+    in_flight::{InFlightGuard, InFlightProof, InFlightProofs, ProofPhase},
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenErrorKind, ProofGenResult},
+    proof_key::ProofKey,
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    prover_state::ProverState,
+    serialization::ProofKind,
+    types::circuit_digest_hex,
+    verification::{verify_agg_proof, verify_block_proof, verify_txn_proof},
+};
+
+/// The types generated from `proto/prover.proto`.
+pub mod proto {
+    tonic::include_proto!("plonky_block_proof_gen");
+}
+
+use proto::{
+    prover_server::{Prover, ProverServer},
+    ListInFlightRequest, ListInFlightResponse, ProofChunk, ProveAggRequest, ProveBlockRequest,
+    ProveTxnRequest, VerifyRequest, VerifyResponse,
+};
+
+/// The size of the chunks that proofs are streamed back in by default.
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// The stream of chunks that a proof is returned in.
+pub type ProofStream = Pin<Box<dyn Stream<Item = Result<ProofChunk, Status>> + Send>>;
+
+/// Implements the `Prover` gRPC service with a shared prover state.
+#[derive(Clone)]
+pub struct ProverService {
+    p_state: Arc<ProverState>,
+    opts: ProofGenOptions,
+    chunk_size: usize,
+    admission: Arc<AdmissionControl>,
+    in_flight: InFlightProofs,
+}
+
+impl ProverService {
+    pub fn new(p_state: Arc<ProverState>) -> Self {
+        Self {
+            p_state,
+            opts: ProofGenOptions::default(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            admission: Arc::new(AdmissionControl::new(AdmissionLimits::default())),
+            in_flight: InFlightProofs::new(),
+        }
+    }
+
+    /// Limit the proofs that are generated and queued at the same time to
+    /// `limits` instead of to the default limits. Requests beyond the limits
+    /// fail with `RESOURCE_EXHAUSTED` and a `retry-after` (in seconds) in the
+    /// metadata.
+    pub fn set_admission_limits(mut self, limits: AdmissionLimits) -> Self {
+        self.admission = Arc::new(AdmissionControl::new(limits));
+        self
+    }
+
+    /// Generate every proof with `opts` instead of with the default options.
+    pub fn set_options(mut self, opts: ProofGenOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    /// Stream proofs back in chunks of `chunk_size` bytes instead of
+    /// [`DEFAULT_CHUNK_SIZE`].
+    pub fn set_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Track the proofs of the service in `in_flight` instead of in a registry
+    /// of its own, eg. to list the proofs of several services together.
+    pub fn set_in_flight(mut self, in_flight: InFlightProofs) -> Self {
+        self.in_flight = in_flight;
+        self
+    }
+
+    /// The proofs that the service is currently working on.
+    pub fn in_flight(&self) -> &InFlightProofs {
+        &self.in_flight
+    }
+
+    /// Reject new requests with `UNAVAILABLE` and wait for the proofs in
+    /// flight to be streamed back (see [`AdmissionControl::shutdown`]).
+    ///
+    /// Every clone of the service shares the same admission control, so
+    /// calling this on a clone of the service passed to the server drains the
+    /// server, which can then be stopped, eg. with the shutdown signal of
+    /// `Server::serve_with_shutdown`.
+    pub async fn shutdown(&self) {
+        self.admission.shutdown().await
+    }
+
+    pub fn into_server(self) -> ProverServer<Self> {
+        ProverServer::new(self)
+    }
+
+    /// List the proof of `key` as queued until there is a slot to prove it in.
+    async fn admit(&self, key: ProofKey) -> Result<(InFlightGuard, AdmissionPermit), Status> {
+        let in_flight = self.in_flight.track(key);
+        let permit = self.admission.admit().await.map_err(to_status)?;
+        in_flight.set_phase(ProofPhase::Proving);
+
+        Ok((in_flight, permit))
+    }
+
+    fn stream_proof(
+        &self,
+        bytes: ProofGenResult<Vec<u8>>,
+    ) -> Result<Response<ProofStream>, Status> {
+        let chunks: Vec<_> = bytes
+            .map_err(to_status)?
+            .chunks(self.chunk_size)
+            .map(|chunk| {
+                Ok(ProofChunk {
+                    bytes: chunk.to_vec(),
+                })
+            })
+            .collect();
+
+        Ok(Response::new(Box::pin(futures::stream::iter(chunks))))
+    }
+
+    fn decode_aggregatable(
+        &self,
+        proof: Option<proto::Proof>,
+    ) -> Result<AggregatableProof, Status> {
+        let proof = proof.ok_or_else(|| Status::invalid_argument("Missing child proof"))?;
+        let digests = self.p_state.circuit_digests();
+
+        let proof = match proof.kind() {
+            proto::ProofKind::Txn => {
+                GeneratedTxnProof::from_bytes(&proof.bytes, &digests.root).map(Into::into)
+            }
+            proto::ProofKind::Agg => {
+                GeneratedAggProof::from_bytes(&proof.bytes, &digests.aggregation).map(Into::into)
+            }
+            proto::ProofKind::Block => {
+                return Err(Status::invalid_argument(
+                    "Block proofs can not be aggregated",
+                ))
+            }
+        };
+
+        proof.map_err(to_status)
+    }
+}
+
+#[tonic::async_trait]
+impl Prover for ProverService {
+    type ProveTxnStream = ProofStream;
+    type ProveAggStream = ProofStream;
+    type ProveBlockStream = ProofStream;
+
+    async fn prove_txn(
+        &self,
+        request: Request<ProveTxnRequest>,
+    ) -> Result<Response<Self::ProveTxnStream>, Status> {
+        let start_info: TxnProofGenIR = decode(&request.into_inner().txn_ir)?;
+        let digest = self.p_state.circuit_digests().root;
+        let _admitted = self
+            .admit(ProofKey::for_txn_ir(&start_info, digest))
+            .await?;
+
+        let proof =
+            generate_txn_proof_async(self.p_state.clone(), start_info, self.opts.clone()).await;
+
+        self.stream_proof(proof.and_then(|proof| proof.to_bytes(&digest)))
+    }
+
+    async fn prove_agg(
+        &self,
+        request: Request<ProveAggRequest>,
+    ) -> Result<Response<Self::ProveAggStream>, Status> {
+        let request = request.into_inner();
+        let lhs_child = self.decode_aggregatable(request.lhs_child)?;
+        let rhs_child = self.decode_aggregatable(request.rhs_child)?;
+        let other_data: OtherBlockData = decode(&request.other_data)?;
+        let digest = self.p_state.circuit_digests().aggregation;
+
+        let txns = lhs_child
+            .underlying_txns()
+            .combine(&rhs_child.underlying_txns());
+        let _admitted = self
+            .admit(ProofKey::for_agg(lhs_child.b_height(), txns, digest))
+            .await?;
+
+        let proof = generate_agg_proof_async(
+            self.p_state.clone(),
+            lhs_child,
+            rhs_child,
+            other_data,
+            self.opts.clone(),
+        )
+        .await;
+
+        self.stream_proof(proof.and_then(|proof| proof.to_bytes(&digest)))
+    }
+
+    async fn prove_block(
+        &self,
+        request: Request<ProveBlockRequest>,
+    ) -> Result<Response<Self::ProveBlockStream>, Status> {
+        let request = request.into_inner();
+        let digests = self.p_state.circuit_digests();
+
+        let prev_opt_parent_b_proof = request
+            .prev_opt_parent_b_proof
+            .map(|bytes| GeneratedBlockProof::from_bytes(&bytes, &digests.block))
+            .transpose()
+            .map_err(to_status)?;
+        let curr_block_agg_proof =
+            GeneratedAggProof::from_bytes(&request.curr_block_agg_proof, &digests.aggregation)
+                .map_err(to_status)?;
+        let other_data: OtherBlockData = decode(&request.other_data)?;
+        let _admitted = self
+            .admit(ProofKey::for_block(
+                curr_block_agg_proof.common.b_height,
+                digests.block,
+            ))
+            .await?;
+
+        let proof = generate_block_proof_async(
+            self.p_state.clone(),
+            prev_opt_parent_b_proof,
+            curr_block_agg_proof,
+            other_data,
+            self.opts.clone(),
+        )
+        .await;
+
+        self.stream_proof(proof.and_then(|proof| proof.to_bytes(&digests.block)))
+    }
+
+    async fn verify(
+        &self,
+        request: Request<VerifyRequest>,
+    ) -> Result<Response<VerifyResponse>, Status> {
+        let proof = request
+            .into_inner()
+            .proof
+            .ok_or_else(|| Status::invalid_argument("Missing proof"))?;
+        let p_state = self.p_state.clone();
+        let digests = p_state.circuit_digests();
+
+        // Verification takes long enough that it should not block the
+        // executor.
+        let result = task::spawn_blocking(move || match proof.kind() {
+            proto::ProofKind::Txn => GeneratedTxnProof::from_bytes(&proof.bytes, &digests.root)
+                .and_then(|proof| verify_txn_proof(&p_state, &proof)),
+            proto::ProofKind::Agg => {
+                GeneratedAggProof::from_bytes(&proof.bytes, &digests.aggregation)
+                    .and_then(|proof| verify_agg_proof(&p_state, &proof))
+            }
+            proto::ProofKind::Block => {
+                GeneratedBlockProof::from_bytes(&proof.bytes, &digests.block)
+                    .and_then(|proof| verify_block_proof(&p_state, &proof))
+            }
+        })
+        .await
+        .map_err(|err| Status::internal(err.to_string()))?;
+
+        let response = match result {
+            Ok(()) => VerifyResponse {
+                valid: true,
+                error: String::new(),
+            },
+            Err(err @ ProofGenError::Verification(_)) => VerifyResponse {
+                valid: false,
+                error: err.to_string(),
+            },
+            Err(err) => return Err(to_status(err)),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn list_in_flight(
+        &self,
+        _request: Request<ListInFlightRequest>,
+    ) -> Result<Response<ListInFlightResponse>, Status> {
+        let proofs = self.in_flight.list().into_iter().map(to_proto).collect();
+
+        Ok(Response::new(ListInFlightResponse { proofs }))
+    }
+}
+
+fn to_proto(proof: InFlightProof) -> proto::InFlightProof {
+    let kind = match proof.key.kind {
+        ProofKind::Txn => proto::ProofKind::Txn,
+        ProofKind::Agg => proto::ProofKind::Agg,
+        ProofKind::Block => proto::ProofKind::Block,
+    };
+    let phase = match proof.phase {
+        ProofPhase::Queued => proto::ProofPhase::Queued,
+        ProofPhase::Proving => proto::ProofPhase::Proving,
+    };
+
+    proto::InFlightProof {
+        kind: kind.into(),
+        b_height: proof.key.b_height.0,
+        txn_start: proof
+            .key
+            .txns
+            .as_ref()
+            .map(|txns| txns.txn_idxs.start.0 as u64),
+        txn_end: proof
+            .key
+            .txns
+            .as_ref()
+            .map(|txns| txns.txn_idxs.end.0 as u64),
+        circuit_digest: circuit_digest_hex(&proof.key.circuit_digest),
+        key: proof.key.to_string(),
+        phase: phase.into(),
+        elapsed_ms: proof.elapsed.as_millis() as u64,
+        in_phase_ms: proof.in_phase.as_millis() as u64,
+    }
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Status> {
+    bincode::deserialize(bytes).map_err(|err| Status::invalid_argument(err.to_string()))
+}
+
+fn to_status(err: ProofGenError) -> Status {
+    match (&err, err.kind()) {
+        (ProofGenError::Overloaded { retry_after }, _) => {
+            let mut status = Status::resource_exhausted(err.to_string());
+            let retry_after = retry_after.as_secs().max(1).to_string();
+            status.metadata_mut().insert(
+                "retry-after",
+                retry_after.parse().expect("Digits are valid metadata"),
+            );
+            status
+        }
+        (ProofGenError::Cancelled, _) => Status::cancelled(err.to_string()),
+        (ProofGenError::TimedOut(_), _) => Status::deadline_exceeded(err.to_string()),
+        (_, ProofGenErrorKind::Transient) => Status::unavailable(err.to_string()),
+        (_, ProofGenErrorKind::InvalidInput) => Status::invalid_argument(err.to_string()),
+        (_, ProofGenErrorKind::Prover) => Status::internal(err.to_string()),
+    }
+}
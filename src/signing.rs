@@ -0,0 +1,131 @@
+//! Signed proof envelopes, so that aggregator nodes can authenticate which
+//! member of a prover fleet produced a proof.
+//!
+//! A [`SignedProofEnvelope`] wraps a proof in the versioned binary proof format
+//! with an ed25519 signature from the operator key of the prover. Proofs are
+//! verifiable on their own, so the signature does not make a proof any more
+//! valid. It attests who generated it, which matters before trusting anything
+//! that comes along with the proof (eg. timings or metadata), or for
+//! attributing a bad proof to a fleet member.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use keccak_hash::keccak;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_store::StorableProof,
+    types::CircuitDigest,
+};
+
+/// Separates envelope signatures from anything else signed with the same key.
+const SIGNATURE_DOMAIN: &[u8] = b"plonky_block_proof_gen/signed_proof_envelope/v1";
+
+/// A serialized proof signed by the prover that generated it.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SignedProofEnvelope {
+    /// The proof in the versioned binary proof format.
+    pub proof: Vec<u8>,
+
+    /// The ed25519 public key of the signer.
+    pub signer: [u8; 32],
+
+    /// The ed25519 signature over the proof.
+    pub signature: Vec<u8>,
+}
+
+impl SignedProofEnvelope {
+    /// Sign the serialized proof `proof` with `signing_key`.
+    pub fn sign(signing_key: &SigningKey, proof: Vec<u8>) -> Self {
+        let signature = signing_key.sign(&signed_message(&proof));
+
+        Self {
+            proof,
+            signer: signing_key.verifying_key().to_bytes(),
+            signature: signature.to_bytes().to_vec(),
+        }
+    }
+
+    /// Serialize `proof` and sign it with `signing_key`.
+    ///
+    /// `circuit_digest` must be the digest of the circuit that the proof is a
+    /// proof of.
+    pub fn sign_proof<P: StorableProof>(
+        signing_key: &SigningKey,
+        proof: &P,
+        circuit_digest: &CircuitDigest,
+    ) -> ProofGenResult<Self> {
+        Ok(Self::sign(signing_key, proof.to_bytes(circuit_digest)?))
+    }
+
+    /// Check that the envelope was signed by its signer. Returns the key of the
+    /// signer.
+    ///
+    /// This only checks that the signature is valid, not that the signer is
+    /// trusted. Use [`SignedProofEnvelope::verify_from`] for that.
+    pub fn verify_signature(&self) -> ProofGenResult<VerifyingKey> {
+        let signer = VerifyingKey::from_bytes(&self.signer)
+            .map_err(|err| signature_err(format!("Invalid signer key: {}", err)))?;
+        let signature = Signature::from_slice(&self.signature)
+            .map_err(|err| signature_err(format!("Malformed signature: {}", err)))?;
+
+        signer
+            .verify(&signed_message(&self.proof), &signature)
+            .map_err(|_| signature_err("Signature does not match the proof".to_string()))?;
+
+        Ok(signer)
+    }
+
+    /// Check that the envelope was signed by one of the `trusted` keys.
+    /// Returns the key of the signer.
+    pub fn verify_from(&self, trusted: &[VerifyingKey]) -> ProofGenResult<VerifyingKey> {
+        let signer = self.verify_signature()?;
+
+        match trusted.contains(&signer) {
+            true => Ok(signer),
+            false => Err(signature_err(format!(
+                "Proof was signed by the untrusted key {}",
+                hex_key(&signer)
+            ))),
+        }
+    }
+
+    /// Check that the envelope was signed by one of the `trusted` keys and
+    /// deserialize the proof.
+    pub fn open<P: StorableProof>(
+        &self,
+        trusted: &[VerifyingKey],
+        circuit_digest: &CircuitDigest,
+    ) -> ProofGenResult<P> {
+        self.verify_from(trusted)?;
+
+        P::from_bytes(&self.proof, circuit_digest)
+    }
+
+    pub fn to_bytes(&self) -> ProofGenResult<Vec<u8>> {
+        bincode::serialize(self).map_err(|err| ProofGenError::Serialization(err.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> ProofGenResult<Self> {
+        bincode::deserialize(bytes).map_err(|err| ProofGenError::Serialization(err.to_string()))
+    }
+}
+
+/// The message that gets signed for `proof`.
+fn signed_message(proof: &[u8]) -> Vec<u8> {
+    let mut message = SIGNATURE_DOMAIN.to_vec();
+    message.extend_from_slice(keccak(proof).as_bytes());
+
+    message
+}
+
+fn hex_key(key: &VerifyingKey) -> String {
+    key.to_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn signature_err(msg: String) -> ProofGenError {
+    ProofGenError::InvalidSignature(msg)
+}
@@ -0,0 +1,127 @@
+//! A [`ProofStore`] backed by a [sled](https://docs.rs/sled) database.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_key::ProofKey,
+    proof_store::{ProofStore, ProofStoreEntry},
+    types::BlockHeight,
+};
+
+#[derive(Deserialize, Serialize)]
+struct StoredProof {
+    key: ProofKey,
+    bytes: Vec<u8>,
+}
+
+/// A [`ProofStore`] that keeps proofs in a sled database.
+///
+/// Keys are prefixed with the (big endian) block height, so listing the proofs
+/// of a block is a prefix scan.
+#[derive(Clone, Debug)]
+pub struct SledProofStore {
+    db: sled::Db,
+}
+
+impl SledProofStore {
+    /// Open (or create) the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> ProofGenResult<Self> {
+        let db = sled::open(path).map_err(storage_err)?;
+
+        Ok(Self { db })
+    }
+
+    /// Use an already opened database.
+    pub fn from_db(db: sled::Db) -> Self {
+        Self { db }
+    }
+
+    fn db_key(key: &ProofKey) -> ProofGenResult<Vec<u8>> {
+        let mut db_key = key.b_height.0.to_be_bytes().to_vec();
+        bincode::serialize_into(&mut db_key, key)
+            .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+
+        Ok(db_key)
+    }
+}
+
+impl ProofStore for SledProofStore {
+    fn put(&self, key: &ProofKey, bytes: &[u8]) -> ProofGenResult<()> {
+        let value = bincode::serialize(&StoredProof {
+            key: key.clone(),
+            bytes: bytes.to_vec(),
+        })
+        .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+
+        self.db
+            .insert(Self::db_key(key)?, value)
+            .map_err(storage_err)?;
+        self.db.flush().map_err(storage_err)?;
+
+        Ok(())
+    }
+
+    fn get(&self, key: &ProofKey) -> ProofGenResult<Option<Vec<u8>>> {
+        self.db
+            .get(Self::db_key(key)?)
+            .map_err(storage_err)?
+            .map(|value| deserialize_stored_proof(&value).map(|stored| stored.bytes))
+            .transpose()
+    }
+
+    fn put_if_absent(&self, key: &ProofKey, bytes: &[u8]) -> ProofGenResult<bool> {
+        let value = bincode::serialize(&StoredProof {
+            key: key.clone(),
+            bytes: bytes.to_vec(),
+        })
+        .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+
+        let swapped = self
+            .db
+            .compare_and_swap(Self::db_key(key)?, None as Option<&[u8]>, Some(value))
+            .map_err(storage_err)?;
+        if swapped.is_err() {
+            return Ok(false);
+        }
+        self.db.flush().map_err(storage_err)?;
+
+        Ok(true)
+    }
+
+    fn delete(&self, key: &ProofKey) -> ProofGenResult<bool> {
+        let removed = self
+            .db
+            .remove(Self::db_key(key)?)
+            .map_err(storage_err)?
+            .is_some();
+        self.db.flush().map_err(storage_err)?;
+
+        Ok(removed)
+    }
+
+    fn list_by_block(&self, b_height: BlockHeight) -> ProofGenResult<Vec<ProofStoreEntry>> {
+        self.db
+            .scan_prefix(b_height.0.to_be_bytes())
+            .map(|res| {
+                let (_, value) = res.map_err(storage_err)?;
+                let stored = deserialize_stored_proof(&value)?;
+
+                Ok(ProofStoreEntry {
+                    key: stored.key,
+                    size_bytes: stored.bytes.len(),
+                })
+            })
+            .collect()
+    }
+}
+
+fn deserialize_stored_proof(value: &[u8]) -> ProofGenResult<StoredProof> {
+    bincode::deserialize(value).map_err(|err| ProofGenError::Serialization(err.to_string()))
+}
+
+fn storage_err(err: sled::Error) -> ProofGenError {
+    ProofGenError::Storage(err.to_string())
+}
@@ -0,0 +1,123 @@
+//! Exports the `tracing` spans of proof generation (see the `tracing` feature)
+//! to an OpenTelemetry collector over OTLP.
+//!
+//! Spans of one proving call nest as follows:
+//! - `generate_txn_proof`, with a `witness_generation` child if the trace sizes
+//!   are computed up front (eg. to check a memory ceiling). Plonky2 runs
+//!   witness generation, the STARK proving of every table and the recursive
+//!   root proof inside a single call without emitting spans, so those do not
+//!   show up as separate spans. Use `generate_txn_proof_with_timing` for a
+//!   breakdown of them.
+//! - `generate_agg_proof`
+//! - `generate_block_proof`
+//!
+//! To show a proof as part of the trace of the orchestrator that requested it,
+//! the orchestrator sends its trace context along with the request (see
+//! [`current_trace_context`]) and the prover makes it the parent of its spans
+//! with [`set_remote_parent`].
+
+use std::collections::HashMap;
+
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::proof_gen::{ProofGenError, ProofGenResult};
+
+/// The default endpoint of an OTLP collector accepting gRPC.
+pub const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4317";
+
+/// Where and under what name to export spans to.
+#[derive(Clone, Debug)]
+pub struct OtlpConfig {
+    /// The gRPC endpoint of the collector.
+    pub endpoint: String,
+
+    /// Reported as the `service.name` resource of every span.
+    pub service_name: String,
+
+    /// Which spans to export, in the syntax of `RUST_LOG` (eg.
+    /// `plonky_block_proof_gen=info`).
+    pub filter: String,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: DEFAULT_OTLP_ENDPOINT.to_string(),
+            service_name: "plonky-block-prover".to_string(),
+            filter: "info".to_string(),
+        }
+    }
+}
+
+/// Flushes the spans that are not exported yet when dropped.
+#[must_use = "Dropping the guard stops exporting spans"]
+#[derive(Debug)]
+pub struct OtlpGuard(());
+
+impl Drop for OtlpGuard {
+    fn drop(&mut self) {
+        global::shutdown_tracer_provider();
+    }
+}
+
+/// Install a global `tracing` subscriber that exports spans to the collector
+/// in `config`, and the W3C trace context propagator used by
+/// [`set_remote_parent`] and [`current_trace_context`].
+///
+/// Spans are exported in batches from the tokio runtime, so this must be
+/// called from within one. Fails if a global subscriber is already installed.
+pub fn init_otlp_tracing(config: &OtlpConfig) -> ProofGenResult<OtlpGuard> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&config.endpoint),
+        )
+        .with_trace_config(trace::config().with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            config.service_name.clone(),
+        )])))
+        .install_batch(runtime::Tokio)
+        .map_err(|err| telemetry_err(err.to_string()))?;
+
+    let filter = EnvFilter::try_new(&config.filter)
+        .map_err(|err| ProofGenError::InvalidInput(format!("Invalid span filter: {}", err)))?;
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()
+        .map_err(|err| telemetry_err(err.to_string()))?;
+
+    Ok(OtlpGuard(()))
+}
+
+/// The trace context of the current span, as W3C trace context headers (ie.
+/// `traceparent` and possibly `tracestate`), for sending along with a proof
+/// request.
+pub fn current_trace_context() -> HashMap<String, String> {
+    let mut carrier = HashMap::new();
+    let cx = tracing::Span::current().context();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&cx, &mut carrier));
+
+    carrier
+}
+
+/// Make the trace context in `carrier` (as created by
+/// [`current_trace_context`]) the parent of `span`, so that the span shows up
+/// in the trace of the requester.
+pub fn set_remote_parent(span: &tracing::Span, carrier: &HashMap<String, String>) {
+    let cx = global::get_text_map_propagator(|propagator| propagator.extract(carrier));
+    span.set_parent(cx);
+}
+
+fn telemetry_err(msg: String) -> ProofGenError {
+    ProofGenError::Transport(format!("OTLP export: {}", msg))
+}
@@ -0,0 +1,265 @@
+//! Structured exports of plonky2's [`TimingTree`].
+//!
+//! Plonky2 records where proving time goes in a `TimingTree`, but can only
+//! print it to the `log` output, and does not expose its contents otherwise.
+//! [`TimingReport::capture`] reads the printed tree back into a
+//! [`TimingReport`], which serializes to JSON and to the folded stack format
+//! that flamegraph tools (`inferno`, `flamegraph.pl`, speedscope, ...) read,
+//! so that proving profiles can be compared across plonky2_evm versions and
+//! config changes.
+//!
+//! Capturing needs [`TimingCaptureLogger`] to be installed as the global
+//! logger, wrapping the logger that the application would use otherwise.
+//!
+//! ```ignore
+//! let logger = env_logger::Builder::from_default_env().build();
+//! let max_level = logger.filter();
+//! TimingCaptureLogger::install(Box::new(logger), max_level)?;
+//!
+//! let (proof, report) = generate_txn_proof_with_timing_report(&p_state, ir, &opts)?;
+//! fs::write("txn.folded", report.to_folded())?;
+//! ```
+//!
+//! Note that plonky2 only records timings when its `timing` feature is enabled
+//! (which it is by default), and that it does not take a `TimingTree` for
+//! aggregation and block proofs, so only txn proofs can be profiled.
+
+use std::{
+    cell::RefCell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use plonky2::util::timing::TimingTree;
+use proof_protocol_decoder::types::TxnProofGenIR;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    options::ProofGenOptions,
+    proof_gen::{generate_txn_proof_with_timing, ProofGenError, ProofGenResult},
+    proof_types::GeneratedTxnProof,
+    prover_state::ProverState,
+};
+
+/// Plonky2 indents every level of the printed tree with this.
+const INDENT: &str = "| ";
+
+/// Whether a [`TimingCaptureLogger`] is the global logger.
+static CAPTURE_LOGGER_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// The lines logged on this thread while capturing a timing tree.
+    static CAPTURED_LINES: RefCell<Option<Vec<String>>> = RefCell::new(None);
+}
+
+/// A scope of a [`TimingTree`] and the time spent in it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TimingReport {
+    pub name: String,
+    pub duration_secs: f64,
+    pub children: Vec<TimingReport>,
+}
+
+impl TimingReport {
+    /// Read the contents of `timing` by printing it through the installed
+    /// [`TimingCaptureLogger`].
+    ///
+    /// Fails if no `TimingCaptureLogger` is installed, or if `timing` is empty
+    /// (eg. because plonky2's `timing` feature is disabled).
+    pub fn capture(timing: &TimingTree) -> ProofGenResult<Self> {
+        if !CAPTURE_LOGGER_INSTALLED.load(Ordering::SeqCst) {
+            return Err(ProofGenError::InvalidInput(
+                "Capturing a timing tree requires the TimingCaptureLogger to be installed"
+                    .to_string(),
+            ));
+        }
+
+        // Plonky2 prints most scopes at the debug level, which the log macros
+        // drop before they reach the logger if the max level is lower. Other
+        // threads may pass records of lower levels to the inner logger in the
+        // meantime, which loggers usually filter themselves.
+        let max_level = log::max_level();
+        log::set_max_level(LevelFilter::Trace);
+        CAPTURED_LINES.with(|lines| *lines.borrow_mut() = Some(Vec::new()));
+
+        timing.print();
+
+        let lines = CAPTURED_LINES.with(|lines| lines.borrow_mut().take().unwrap_or_default());
+        log::set_max_level(max_level);
+
+        Self::parse_printed(&lines)
+    }
+
+    /// Parse the lines printed by [`TimingTree::print`], which prints every
+    /// scope as `{indent}{duration}s to {name}`, with its children (indented
+    /// one level deeper) right after it.
+    pub fn parse_printed(lines: &[String]) -> ProofGenResult<Self> {
+        let mut scopes = lines.iter().map(|line| parse_line(line));
+        let (depth, root) = scopes
+            .next()
+            .ok_or_else(|| timing_err("The timing tree is empty".to_string()))??;
+        if depth != 0 {
+            return Err(timing_err("The timing tree has no root".to_string()));
+        }
+
+        // The scopes from the root down to the last parsed scope.
+        let mut stack = vec![root];
+        for scope in scopes {
+            let (depth, scope) = scope?;
+            if depth == 0 || depth > stack.len() {
+                return Err(timing_err(format!(
+                    "Unexpected nesting of scope {}",
+                    scope.name
+                )));
+            }
+
+            while stack.len() > depth {
+                let child = stack.pop().expect("The stack is longer than the depth");
+                stack.last_mut().unwrap().children.push(child);
+            }
+            stack.push(scope);
+        }
+
+        while stack.len() > 1 {
+            let child = stack.pop().unwrap();
+            stack.last_mut().unwrap().children.push(child);
+        }
+
+        Ok(stack.pop().unwrap())
+    }
+
+    /// The time spent in this scope but not in any of its children.
+    pub fn self_duration_secs(&self) -> f64 {
+        let in_children: f64 = self.children.iter().map(|child| child.duration_secs).sum();
+
+        (self.duration_secs - in_children).max(0.0)
+    }
+
+    pub fn to_json(&self) -> ProofGenResult<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|err| ProofGenError::Serialization(err.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> ProofGenResult<Self> {
+        serde_json::from_str(json).map_err(|err| ProofGenError::Serialization(err.to_string()))
+    }
+
+    /// The report in the folded stack format, with one line per scope of the
+    /// form `root;child;grandchild <self time in microseconds>`.
+    pub fn to_folded(&self) -> String {
+        let mut folded = String::new();
+        self.fold_into(&mut Vec::new(), &mut folded);
+
+        folded
+    }
+
+    fn fold_into<'a>(&'a self, path: &mut Vec<&'a str>, folded: &mut String) {
+        path.push(&self.name);
+
+        // Frame names may not contain the separators of the format.
+        let frames: Vec<_> = path
+            .iter()
+            .map(|name| name.replace([';', ' '], "_"))
+            .collect();
+        let self_micros = (self.self_duration_secs() * 1_000_000.0).round() as u64;
+        folded.push_str(&format!("{} {}\n", frames.join(";"), self_micros));
+
+        for child in &self.children {
+            child.fold_into(path, folded);
+        }
+
+        path.pop();
+    }
+}
+
+/// Generate a txn proof and capture where proving time went (see
+/// [`TimingReport::capture`]).
+pub fn generate_txn_proof_with_timing_report(
+    p_state: &ProverState,
+    start_info: TxnProofGenIR,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<(GeneratedTxnProof, TimingReport)> {
+    let mut timing = TimingTree::new("generate txn proof", Level::Info);
+    let proof = generate_txn_proof_with_timing(p_state, start_info, opts, &mut timing)?;
+    timing.pop();
+
+    Ok((proof, TimingReport::capture(&timing)?))
+}
+
+/// A logger that forwards every record to `inner`, except for the lines of
+/// timing trees that are being captured with [`TimingReport::capture`].
+pub struct TimingCaptureLogger {
+    inner: Box<dyn Log>,
+}
+
+impl TimingCaptureLogger {
+    /// Install the logger as the global logger, forwarding to `inner`, and
+    /// set the max level of the `log` macros to `max_level`. Fails if a global
+    /// logger is already installed.
+    pub fn install(inner: Box<dyn Log>, max_level: LevelFilter) -> ProofGenResult<()> {
+        log::set_logger(Box::leak(Box::new(Self { inner })))
+            .map_err(|err| ProofGenError::InvalidInput(err.to_string()))?;
+        log::set_max_level(max_level);
+        CAPTURE_LOGGER_INSTALLED.store(true, Ordering::SeqCst);
+
+        Ok(())
+    }
+}
+
+impl Log for TimingCaptureLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        is_capturing() || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        let captured = CAPTURED_LINES.with(|lines| match lines.borrow_mut().as_mut() {
+            Some(lines) => {
+                lines.push(record.args().to_string());
+                true
+            }
+            None => false,
+        });
+
+        if !captured {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+fn is_capturing() -> bool {
+    CAPTURED_LINES.with(|lines| lines.borrow().is_some())
+}
+
+/// Parse a line printed by [`TimingTree::print`] into its depth and scope.
+fn parse_line(line: &str) -> ProofGenResult<(usize, TimingReport)> {
+    let mut rest = line;
+    let mut depth = 0;
+    while let Some(stripped) = rest.strip_prefix(INDENT) {
+        rest = stripped;
+        depth += 1;
+    }
+
+    let (duration, name) = rest
+        .split_once("s to ")
+        .ok_or_else(|| timing_err(format!("Unexpected timing line: {}", line)))?;
+    let duration_secs = duration
+        .parse()
+        .map_err(|_| timing_err(format!("Unexpected duration in timing line: {}", line)))?;
+
+    Ok((
+        depth,
+        TimingReport {
+            name: name.to_string(),
+            duration_secs,
+            children: Vec::new(),
+        },
+    ))
+}
+
+fn timing_err(msg: String) -> ProofGenError {
+    ProofGenError::InvalidInput(format!("Can not read timing tree: {}", msg))
+}
@@ -0,0 +1,162 @@
+//! Caching of txn proofs by the digest of the IR that they were generated
+//! from.
+//!
+//! Retried or replayed blocks (eg. after a restart of the orchestrator) tend
+//! to contain the exact same txns as before, and proving them again is the
+//! most expensive part of proving a block.
+
+use std::{collections::HashMap, mem, sync::Mutex};
+
+use ethereum_types::H256;
+use keccak_hash::keccak;
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+
+use crate::{
+    backend::BlockProverBackend,
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    types::{CircuitDigest, CircuitDigests},
+};
+
+/// A digest of `ir` and of the circuit that it gets proven with.
+///
+/// Two IRs have the same digest iff they would result in the same txn proof,
+/// so the digest can be used to look up previously generated proofs.
+pub fn txn_ir_digest(ir: &TxnProofGenIR, circuit_digest: &CircuitDigest) -> ProofGenResult<H256> {
+    // The contract code is a `HashMap`, which does not serialize in a
+    // deterministic order.
+    let mut ir = ir.clone();
+    let mut contract_code: Vec<_> = mem::take(&mut ir.gen_inputs.contract_code)
+        .into_iter()
+        .collect();
+    contract_code.sort_unstable_by_key(|(hash, _)| *hash);
+
+    let bytes = bincode::serialize(&(ir, contract_code, circuit_digest))
+        .map_err(|err| ProofGenError::Serialization(err.to_string()))?;
+
+    Ok(keccak(bytes))
+}
+
+/// Stores txn proofs by the digest of their IR (see [`txn_ir_digest`]).
+pub trait TxnProofCache: Send + Sync {
+    fn get(&self, ir_digest: &H256) -> Option<GeneratedTxnProof>;
+
+    fn insert(&self, ir_digest: H256, proof: GeneratedTxnProof);
+}
+
+/// A [`TxnProofCache`] that keeps every proof in memory.
+#[derive(Debug, Default)]
+pub struct InMemoryTxnProofCache {
+    proofs: Mutex<HashMap<H256, GeneratedTxnProof>>,
+}
+
+impl InMemoryTxnProofCache {
+    pub fn len(&self) -> usize {
+        self.proofs.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.proofs.lock().unwrap().clear();
+    }
+}
+
+impl TxnProofCache for InMemoryTxnProofCache {
+    fn get(&self, ir_digest: &H256) -> Option<GeneratedTxnProof> {
+        self.proofs.lock().unwrap().get(ir_digest).cloned()
+    }
+
+    fn insert(&self, ir_digest: H256, proof: GeneratedTxnProof) {
+        self.proofs.lock().unwrap().insert(ir_digest, proof);
+    }
+}
+
+/// A [`BlockProverBackend`] that returns cached txn proofs for IR that it has
+/// already proven, and otherwise defers to the wrapped backend.
+///
+/// Agg and block proofs are always generated by the wrapped backend.
+pub struct CachingBackend<B, C> {
+    backend: B,
+    cache: C,
+}
+
+impl<B: BlockProverBackend, C: TxnProofCache> CachingBackend<B, C> {
+    pub fn new(backend: B, cache: C) -> Self {
+        Self { backend, cache }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn cache(&self) -> &C {
+        &self.cache
+    }
+}
+
+impl<B: BlockProverBackend, C: TxnProofCache> BlockProverBackend for CachingBackend<B, C> {
+    fn generate_txn_proof(
+        &self,
+        start_info: TxnProofGenIR,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedTxnProof> {
+        let ir_digest = txn_ir_digest(&start_info, &self.backend.circuit_digests().root)?;
+        if let Some(proof) = self.cache.get(&ir_digest) {
+            return Ok(proof);
+        }
+
+        let proof = self.backend.generate_txn_proof(start_info, opts)?;
+        self.cache.insert(ir_digest, proof.clone());
+
+        Ok(proof)
+    }
+
+    fn generate_agg_proof(
+        &self,
+        lhs_child: &AggregatableProof,
+        rhs_child: &AggregatableProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedAggProof> {
+        self.backend
+            .generate_agg_proof(lhs_child, rhs_child, other_data, opts)
+    }
+
+    fn generate_block_proof(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: &GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        self.backend.generate_block_proof(
+            prev_opt_parent_b_proof,
+            curr_block_agg_proof,
+            other_data,
+            opts,
+        )
+    }
+
+    fn generate_block_proof_from_owned(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        self.backend.generate_block_proof_from_owned(
+            prev_opt_parent_b_proof,
+            curr_block_agg_proof,
+            other_data,
+            opts,
+        )
+    }
+
+    fn circuit_digests(&self) -> CircuitDigests {
+        self.backend.circuit_digests()
+    }
+}
@@ -1,21 +1,182 @@
 use std::{
     cmp::Ordering,
     fmt::{self, Display, Formatter},
-    ops::{Range, RangeInclusive},
+    ops::{Add, Range, RangeInclusive},
 };
 
+use ethereum_types::U256;
 use plonky2::{
-    field::goldilocks_field::GoldilocksField,
-    plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
+    field::{goldilocks_field::GoldilocksField, types::PrimeField64},
+    hash::hash_types::HashOut,
+    plonk::{
+        circuit_data::{CommonCircuitData, VerifierCircuitData},
+        config::{KeccakGoldilocksConfig, PoseidonGoldilocksConfig},
+        proof::{CompressedProofWithPublicInputs, ProofWithPublicInputs},
+    },
 };
+use plonky2_evm::all_stark::NUM_TABLES;
 use serde::{Deserialize, Serialize};
 
-pub type BlockHeight = u64;
+/// The height (number) of a block.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+#[serde(transparent)]
+pub struct BlockHeight(pub u64);
 
-pub type TxnIdx = usize;
+/// The index of a txn within its block.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+#[serde(transparent)]
+pub struct TxnIdx(pub usize);
+
+impl Display for BlockHeight {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u64> for BlockHeight {
+    fn from(b_height: u64) -> Self {
+        Self(b_height)
+    }
+}
+
+impl From<BlockHeight> for u64 {
+    fn from(b_height: BlockHeight) -> Self {
+        b_height.0
+    }
+}
+
+impl From<BlockHeight> for U256 {
+    fn from(b_height: BlockHeight) -> Self {
+        b_height.0.into()
+    }
+}
+
+impl Add<u64> for BlockHeight {
+    type Output = Self;
+
+    fn add(self, rhs: u64) -> Self {
+        Self(self.0 + rhs)
+    }
+}
+
+impl Display for TxnIdx {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<usize> for TxnIdx {
+    fn from(txn_idx: usize) -> Self {
+        Self(txn_idx)
+    }
+}
+
+impl From<TxnIdx> for usize {
+    fn from(txn_idx: TxnIdx) -> Self {
+        txn_idx.0
+    }
+}
+
+impl From<TxnIdx> for U256 {
+    fn from(txn_idx: TxnIdx) -> Self {
+        txn_idx.0.into()
+    }
+}
+
+impl Add<usize> for TxnIdx {
+    type Output = Self;
+
+    fn add(self, rhs: usize) -> Self {
+        Self(self.0 + rhs)
+    }
+}
+
+/// The names of the STARK tables, in the order that plonky2 indexes them in.
+pub const TABLE_NAMES: [&str; NUM_TABLES] = [
+    "arithmetic",
+    "byte_packing",
+    "cpu",
+    "keccak",
+    "keccak_sponge",
+    "logic",
+    "memory",
+];
+
+/// Ethereum hard forks, in activation order.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum HardFork {
+    Shanghai,
+}
+
+impl HardFork {
+    /// The hard forks whose EVM rules the pinned version of plonky2_evm
+    /// implements. Blocks of other forks can not be proven.
+    pub const SUPPORTED: &'static [HardFork] = &[HardFork::Shanghai];
+}
+
+impl Display for HardFork {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HardFork::Shanghai => write!(f, "shanghai"),
+        }
+    }
+}
+
+/// The digest of a plonky2 circuit, which uniquely identifies it.
+pub type CircuitDigest = HashOut<GoldilocksField>;
+
+/// The digests of the root, aggregation and block circuits.
+///
+/// Provers and verifiers built from identical circuits have identical digests,
+/// so comparing these is a cheap way to check that a prover fleet and its
+/// verifiers agree before accepting any proofs.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct CircuitDigests {
+    pub root: CircuitDigest,
+    pub aggregation: CircuitDigest,
+    pub block: CircuitDigest,
+}
+
+/// Hex encode `digest` (as the big-endian hex of each of its elements).
+pub fn circuit_digest_hex(digest: &CircuitDigest) -> String {
+    digest
+        .elements
+        .iter()
+        .map(|elem| format!("{:016x}", elem.to_canonical_u64()))
+        .collect()
+}
+
+impl Display for CircuitDigests {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "root: {}, aggregation: {}, block: {}",
+            circuit_digest_hex(&self.root),
+            circuit_digest_hex(&self.aggregation),
+            circuit_digest_hex(&self.block)
+        )
+    }
+}
 
 pub type PlonkyProofIntern = ProofWithPublicInputs<GoldilocksField, PoseidonGoldilocksConfig, 2>;
 
+/// A plonky2 proof that uses Keccak instead of Poseidon as its hasher.
+pub type KeccakPlonkyProofIntern =
+    ProofWithPublicInputs<GoldilocksField, KeccakGoldilocksConfig, 2>;
+
+pub type CompressedPlonkyProofIntern =
+    CompressedProofWithPublicInputs<GoldilocksField, PoseidonGoldilocksConfig, 2>;
+
+pub type PlonkyCommonData = CommonCircuitData<GoldilocksField, 2>;
+
+pub type VerifierData = VerifierCircuitData<GoldilocksField, PoseidonGoldilocksConfig, 2>;
+
+pub type AllStark = plonky2_evm::all_stark::AllStark<GoldilocksField, 2>;
+
 pub type AllRecursiveCircuits = plonky2_evm::fixed_recursive_verifier::AllRecursiveCircuits<
     GoldilocksField,
     PoseidonGoldilocksConfig,
@@ -33,7 +194,7 @@ impl Display for ProofUnderlyingTxns {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self.num_txns() {
             0 => write!(f, "EMPTY_TXN"),
-            _ => write!(f, "{}-{}", self.txn_idxs.start, self.txn_idxs.end - 1),
+            _ => write!(f, "{}-{}", self.txn_idxs.start, self.txn_idxs.end.0 - 1),
         }
     }
 }
@@ -47,7 +208,7 @@ impl ProofUnderlyingTxns {
     }
 
     pub fn num_txns(&self) -> usize {
-        self.txn_idxs.end - self.txn_idxs.start
+        self.txn_idxs.end.0 - self.txn_idxs.start.0
     }
 
     pub fn contains_all_txns_in_block(&self, num_txns_in_block: usize) -> bool {
@@ -85,10 +246,10 @@ impl Ord for ProofUnderlyingTxns {
     fn cmp(&self, other: &Self) -> Ordering {
         match self == other {
             true => Ordering::Equal,
-            false => match (self.txn_idxs.end - 1).cmp(&other.txn_idxs.start) {
+            false => match (self.txn_idxs.end.0 - 1).cmp(&other.txn_idxs.start.0) {
                 Ordering::Less => Ordering::Less,
                 Ordering::Greater => Ordering::Greater,
-                Ordering::Equal => match self.txn_idxs.start.cmp(&(other.txn_idxs.end - 1)) {
+                Ordering::Equal => match self.txn_idxs.start.0.cmp(&(other.txn_idxs.end.0 - 1)) {
                     Ordering::Less => Ordering::Greater,
                     Ordering::Equal => Ordering::Equal,
                     Ordering::Greater => Ordering::Less,
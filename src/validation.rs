@@ -0,0 +1,157 @@
+use eth_trie_utils::partial_trie::PartialTrie;
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+
+use crate::proof_gen::{ProofGenError, ProofGenResult};
+
+/// The EIP-2718 txn type of OP-stack deposit (and system) txns.
+pub const DEPOSIT_TXN_TYPE: u8 = 0x7e;
+
+/// Whether `ir` executes an OP-stack deposit txn.
+///
+/// Deposit txns are not signed and do not follow the normal fee rules, and the
+/// plonky2_evm kernel only parses legacy, EIP-2930 and EIP-1559 txns, so they
+/// can not be proven. [`validate_ir`] rejects them before proving instead of
+/// letting them fail deep inside plonky2.
+pub fn is_deposit_txn(ir: &TxnProofGenIR) -> bool {
+    ir.gen_inputs
+        .signed_txn
+        .as_ref()
+        .and_then(|txn| txn.first())
+        == Some(&DEPOSIT_TXN_TYPE)
+}
+
+/// Cheaply sanity check txn proof IR before spending minutes proving it.
+///
+/// This catches IR that is guaranteed to fail deep inside plonky2, but passing
+/// it does not guarantee that the txn can be proven.
+pub fn validate_ir(ir: &TxnProofGenIR) -> ProofGenResult<()> {
+    let inputs = &ir.gen_inputs;
+    let b_meta = &inputs.block_metadata;
+
+    if inputs.txn_number_before != ir.txn_idx.into() {
+        return Err(invalid_ir(
+            ir,
+            format!(
+                "txn number before ({}) does not match the txn index",
+                inputs.txn_number_before
+            ),
+        ));
+    }
+
+    if inputs.gas_used_before > inputs.gas_used_after {
+        return Err(invalid_ir(
+            ir,
+            format!(
+                "gas used before ({}) is greater than gas used after ({})",
+                inputs.gas_used_before, inputs.gas_used_after
+            ),
+        ));
+    }
+
+    if inputs.gas_used_after > b_meta.block_gas_used {
+        return Err(invalid_ir(
+            ir,
+            format!(
+                "gas used after ({}) is greater than the gas used by the block ({})",
+                inputs.gas_used_after, b_meta.block_gas_used
+            ),
+        ));
+    }
+
+    if b_meta.block_gas_used > b_meta.block_gaslimit {
+        return Err(invalid_ir(
+            ir,
+            format!(
+                "gas used by the block ({}) is greater than the block gas limit ({})",
+                b_meta.block_gas_used, b_meta.block_gaslimit
+            ),
+        ));
+    }
+
+    let tries = &inputs.tries;
+    let roots_after = &inputs.trie_roots_after;
+    let txns_root_before = tries.transactions_trie.hash();
+
+    match &inputs.signed_txn {
+        // A dummy txn does not execute anything, so none of the tries change.
+        None => {
+            if tries.state_trie.hash() != roots_after.state_root
+                || txns_root_before != roots_after.transactions_root
+                || tries.receipts_trie.hash() != roots_after.receipts_root
+            {
+                return Err(invalid_ir(
+                    ir,
+                    "trie roots change without a txn being executed".to_string(),
+                ));
+            }
+        }
+        Some(signed_txn) => {
+            if signed_txn.is_empty() {
+                return Err(invalid_ir(ir, "signed txn is empty".to_string()));
+            }
+
+            if is_deposit_txn(ir) {
+                return Err(invalid_ir(
+                    ir,
+                    "OP-stack deposit txns are not supported by plonky2_evm".to_string(),
+                ));
+            }
+
+            // Every executed txn gets inserted into the txn trie.
+            if txns_root_before == roots_after.transactions_root {
+                return Err(invalid_ir(
+                    ir,
+                    "txn trie root does not change after executing a txn".to_string(),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the withdrawals of a block are only passed to its last txn, and
+/// that they match the withdrawals in `other_data`.
+///
+/// Plonky2 processes the withdrawals of a block after its last txn, so
+/// withdrawals in the IR of any other txn (or withdrawals that differ from the
+/// ones recorded in the block proof) would make the proven state root disagree
+/// with the block.
+pub fn validate_withdrawals(
+    txns: &[TxnProofGenIR],
+    other_data: &OtherBlockData,
+) -> ProofGenResult<()> {
+    let Some((last, rest)) = txns.split_last() else {
+        return Ok(());
+    };
+
+    if let Some(ir) = rest.iter().find(|ir| !ir.gen_inputs.withdrawals.is_empty()) {
+        return Err(invalid_ir(
+            ir,
+            "withdrawals can only be processed by the last txn of the block".to_string(),
+        ));
+    }
+
+    if last.gen_inputs.withdrawals != other_data.b_data.withdrawals {
+        return Err(invalid_ir(
+            last,
+            format!(
+                "the txn processes {} withdrawal(s) that do not match the {} withdrawal(s) of the \
+                 block",
+                last.gen_inputs.withdrawals.len(),
+                other_data.b_data.withdrawals.len()
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+fn invalid_ir(ir: &TxnProofGenIR, reason: String) -> ProofGenError {
+    ProofGenError::InvalidInput(format!(
+        "Invalid IR for txn {} of block {}: {}",
+        ir.txn_idx,
+        ir.b_height(),
+        reason
+    ))
+}
@@ -0,0 +1,32 @@
+use crate::{
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_types::{GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    prover_state::ProverState,
+};
+
+/// Verify a txn proof against the root circuit of the prover state.
+pub fn verify_txn_proof(p_state: &ProverState, proof: &GeneratedTxnProof) -> ProofGenResult<()> {
+    p_state
+        .state
+        .verify_root(proof.intern.clone())
+        .map_err(|err| ProofGenError::Verification(format!("{:#}", err)))
+}
+
+/// Verify an agg proof against the aggregation circuit of the prover state.
+pub fn verify_agg_proof(p_state: &ProverState, proof: &GeneratedAggProof) -> ProofGenResult<()> {
+    p_state
+        .state
+        .verify_aggregation(&proof.intern)
+        .map_err(|err| ProofGenError::Verification(format!("{:#}", err)))
+}
+
+/// Verify a block proof against the block circuit of the prover state.
+pub fn verify_block_proof(
+    p_state: &ProverState,
+    proof: &GeneratedBlockProof,
+) -> ProofGenResult<()> {
+    p_state
+        .state
+        .verify_block(&proof.intern)
+        .map_err(|err| ProofGenError::Verification(format!("{:#}", err)))
+}
@@ -0,0 +1,154 @@
+use plonky2::{
+    recursion::cyclic_recursion::check_cyclic_proof_verifier_data,
+    util::serialization::DefaultGateSerializer,
+};
+
+use crate::{
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_types::{GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    prover_state::ProverState,
+    types::{CircuitDigest, CircuitDigests, PlonkyProofIntern, VerifierData},
+};
+
+/// Plonky2 verifier state.
+///
+/// Only holds the verifier data of the root, aggregation and block circuits, so
+/// unlike [`ProverState`] it is cheap to keep in memory. Verification-only
+/// nodes should create this once from a prover state and then load it with
+/// [`VerifierState::from_bytes`] on startup.
+pub struct VerifierState {
+    pub root: VerifierData,
+    pub aggregation: VerifierData,
+    pub block: VerifierData,
+}
+
+impl From<&ProverState> for VerifierState {
+    fn from(p_state: &ProverState) -> Self {
+        Self {
+            root: p_state.state.root.circuit.verifier_data(),
+            aggregation: p_state.state.aggregation.circuit.verifier_data(),
+            block: p_state.state.block.circuit.verifier_data(),
+        }
+    }
+}
+
+impl VerifierState {
+    /// The digest of the root (txn) circuit.
+    pub fn root_circuit_digest(&self) -> CircuitDigest {
+        self.root.verifier_only.circuit_digest
+    }
+
+    /// The digest of the aggregation circuit.
+    pub fn aggregation_circuit_digest(&self) -> CircuitDigest {
+        self.aggregation.verifier_only.circuit_digest
+    }
+
+    /// The digest of the block circuit.
+    pub fn block_circuit_digest(&self) -> CircuitDigest {
+        self.block.verifier_only.circuit_digest
+    }
+
+    /// The digests of the root, aggregation and block circuits.
+    pub fn circuit_digests(&self) -> CircuitDigests {
+        CircuitDigests {
+            root: self.root_circuit_digest(),
+            aggregation: self.aggregation_circuit_digest(),
+            block: self.block_circuit_digest(),
+        }
+    }
+
+    /// Check that the circuits of this verifier state have the `expected`
+    /// digests (eg. those reported by the provers whose proofs get verified).
+    pub fn check_circuit_digests(&self, expected: &CircuitDigests) -> ProofGenResult<()> {
+        let actual = self.circuit_digests();
+        match actual == *expected {
+            true => Ok(()),
+            false => Err(ProofGenError::IncompatibleProof(format!(
+                "Expected circuits with digests ({}) but the verifier has ({})",
+                expected, actual
+            ))),
+        }
+    }
+
+    /// Verify a txn proof against the root circuit.
+    pub fn verify_txn_proof(&self, proof: &GeneratedTxnProof) -> ProofGenResult<()> {
+        self.root
+            .verify(proof.intern.clone())
+            .map_err(|err| ProofGenError::Verification(format!("{:#}", err)))
+    }
+
+    /// Verify an agg proof against the aggregation circuit.
+    pub fn verify_agg_proof(&self, proof: &GeneratedAggProof) -> ProofGenResult<()> {
+        verify_cyclic(&self.aggregation, &proof.intern)
+    }
+
+    /// Verify a block proof against the block circuit.
+    pub fn verify_block_proof(&self, proof: &GeneratedBlockProof) -> ProofGenResult<()> {
+        verify_cyclic(&self.block, &proof.intern)
+    }
+
+    /// Serialize the verifier data of all three circuits.
+    pub fn to_bytes(&self) -> ProofGenResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+
+        for circuit in [&self.root, &self.aggregation, &self.block] {
+            let circuit_bytes = circuit.to_bytes(&DefaultGateSerializer).map_err(|err| {
+                ProofGenError::Serialization(format!(
+                    "Failed to serialize verifier data: {:?}",
+                    err
+                ))
+            })?;
+
+            bytes.extend_from_slice(&(circuit_bytes.len() as u64).to_le_bytes());
+            bytes.extend(circuit_bytes);
+        }
+
+        Ok(bytes)
+    }
+
+    /// Deserialize the verifier state from bytes created by
+    /// [`VerifierState::to_bytes`].
+    pub fn from_bytes(mut bytes: &[u8]) -> ProofGenResult<Self> {
+        let mut read_circuit = || -> ProofGenResult<VerifierData> {
+            let len_bytes = bytes.get(..8).ok_or_else(truncated_err)?;
+            let len = u64::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+            let circuit_bytes = bytes[8..].get(..len).ok_or_else(truncated_err)?;
+
+            let circuit = VerifierData::from_bytes(circuit_bytes.to_vec(), &DefaultGateSerializer)
+                .map_err(|err| {
+                    ProofGenError::Serialization(format!(
+                        "Failed to deserialize verifier data: {:?}",
+                        err
+                    ))
+                })?;
+            bytes = &bytes[8 + len..];
+
+            Ok(circuit)
+        };
+
+        Ok(Self {
+            root: read_circuit()?,
+            aggregation: read_circuit()?,
+            block: read_circuit()?,
+        })
+    }
+}
+
+/// Verify a proof of a cyclic circuit (ie. the aggregation or block circuit),
+/// like plonky2's `verify_aggregation` and `verify_block` do.
+///
+/// The verifier data of a cyclic circuit is a public input of its proofs, so
+/// besides verifying the proof, this checks that those public inputs are the
+/// verifier data of `circuit` instead of forged ones.
+fn verify_cyclic(circuit: &VerifierData, proof: &PlonkyProofIntern) -> ProofGenResult<()> {
+    circuit
+        .verify(proof.clone())
+        .and_then(|()| {
+            check_cyclic_proof_verifier_data(proof, &circuit.verifier_only, &circuit.common)
+        })
+        .map_err(|err| ProofGenError::Verification(format!("{:#}", err)))
+}
+
+fn truncated_err() -> ProofGenError {
+    ProofGenError::Serialization("Verifier state bytes are truncated".to_string())
+}
@@ -0,0 +1,236 @@
+//! Execution of proof jobs by (possibly remote) workers.
+//!
+//! [`ProofWorker`] is the seam between scheduling proofs and generating them:
+//! [`run_dag_on_worker`] dispatches the jobs of a [`ProofDag`] to a worker
+//! without knowing where the worker runs. [`LocalProofWorker`] proves on the
+//! local machine, and remote workers only need to implement the trait.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+use tokio::task;
+
+use crate::{
+    backend::BlockProverBackend,
+    dummy::create_dummy_txn_ir,
+    options::ProofGenOptions,
+    proof_gen::ProofGenResult,
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    prover_state::ProverState,
+    scheduler::{JobOutput, ProofDag, ReadyJob},
+};
+
+/// The future returned by the methods of [`ProofWorker`].
+pub type WorkerFuture<'a, T> = Pin<Box<dyn Future<Output = ProofGenResult<T>> + Send + 'a>>;
+
+/// Something that generates proofs, eg. a local prover state or a client for
+/// a prover running on another machine.
+///
+/// The methods mirror the `generate_*_proof` functions, but take all of their
+/// inputs by value so that they can be sent elsewhere.
+pub trait ProofWorker: Send + Sync {
+    /// See [`generate_txn_proof`](crate::proof_gen::generate_txn_proof).
+    fn prove_txn(
+        &self,
+        start_info: TxnProofGenIR,
+        opts: ProofGenOptions,
+    ) -> WorkerFuture<'_, GeneratedTxnProof>;
+
+    /// See [`generate_agg_proof`](crate::proof_gen::generate_agg_proof).
+    fn prove_agg(
+        &self,
+        lhs_child: AggregatableProof,
+        rhs_child: AggregatableProof,
+        other_data: OtherBlockData,
+        opts: ProofGenOptions,
+    ) -> WorkerFuture<'_, GeneratedAggProof>;
+
+    /// See [`generate_block_proof`](crate::proof_gen::generate_block_proof).
+    fn prove_block(
+        &self,
+        prev_opt_parent_b_proof: Option<GeneratedBlockProof>,
+        curr_block_agg_proof: GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: ProofGenOptions,
+    ) -> WorkerFuture<'_, GeneratedBlockProof>;
+}
+
+/// A [`ProofWorker`] that proves in-process with a backend (by default a
+/// [`ProverState`]), on tokio's blocking thread pool.
+pub struct LocalProofWorker<B: BlockProverBackend + 'static = ProverState> {
+    backend: Arc<B>,
+}
+
+impl<B: BlockProverBackend + 'static> LocalProofWorker<B> {
+    pub fn new(backend: Arc<B>) -> Self {
+        Self { backend }
+    }
+
+    pub fn backend(&self) -> &Arc<B> {
+        &self.backend
+    }
+}
+
+impl<B: BlockProverBackend + 'static> ProofWorker for LocalProofWorker<B> {
+    fn prove_txn(
+        &self,
+        start_info: TxnProofGenIR,
+        opts: ProofGenOptions,
+    ) -> WorkerFuture<'_, GeneratedTxnProof> {
+        let backend = self.backend.clone();
+        Box::pin(async move {
+            task::spawn_blocking(move || backend.generate_txn_proof(start_info, &opts)).await?
+        })
+    }
+
+    fn prove_agg(
+        &self,
+        lhs_child: AggregatableProof,
+        rhs_child: AggregatableProof,
+        other_data: OtherBlockData,
+        opts: ProofGenOptions,
+    ) -> WorkerFuture<'_, GeneratedAggProof> {
+        let backend = self.backend.clone();
+        Box::pin(async move {
+            task::spawn_blocking(move || {
+                backend.generate_agg_proof(&lhs_child, &rhs_child, other_data, &opts)
+            })
+            .await?
+        })
+    }
+
+    fn prove_block(
+        &self,
+        prev_opt_parent_b_proof: Option<GeneratedBlockProof>,
+        curr_block_agg_proof: GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: ProofGenOptions,
+    ) -> WorkerFuture<'_, GeneratedBlockProof> {
+        let backend = self.backend.clone();
+        Box::pin(async move {
+            task::spawn_blocking(move || {
+                backend.generate_block_proof(
+                    prev_opt_parent_b_proof.as_ref(),
+                    &curr_block_agg_proof,
+                    other_data,
+                    &opts,
+                )
+            })
+            .await?
+        })
+    }
+}
+
+/// Generate the proof of `job` with `worker`.
+pub async fn dispatch_job<W: ProofWorker + ?Sized>(
+    worker: &W,
+    job: ReadyJob,
+    opts: ProofGenOptions,
+) -> ProofGenResult<JobOutput> {
+    match job {
+        ReadyJob::Txn(ir) => worker.prove_txn(ir, opts).await.map(JobOutput::Txn),
+        ReadyJob::DummyTxnAfter {
+            prev_txn_proof,
+            other_data,
+        } => {
+            let dummy_ir = create_dummy_txn_ir(
+                prev_txn_proof.txn_idx + 1,
+                &prev_txn_proof.common.roots_after,
+                prev_txn_proof.common.deltas.gas_used_after,
+                &other_data,
+            );
+
+            worker.prove_txn(dummy_ir, opts).await.map(JobOutput::Txn)
+        }
+        ReadyJob::Agg {
+            lhs_child,
+            rhs_child,
+            other_data,
+        } => worker
+            .prove_agg(lhs_child, rhs_child, other_data, opts)
+            .await
+            .map(JobOutput::Agg),
+        ReadyJob::Block {
+            prev_opt_parent_b_proof,
+            curr_block_agg_proof,
+            other_data,
+        } => worker
+            .prove_block(
+                prev_opt_parent_b_proof,
+                curr_block_agg_proof,
+                other_data,
+                opts,
+            )
+            .await
+            .map(JobOutput::Block),
+    }
+}
+
+/// Async version of [`run_dag`](crate::scheduler::run_dag) that dispatches
+/// every job to `worker`, with up to `max_concurrent_jobs` jobs in flight.
+pub async fn run_dag_on_worker<W: ProofWorker + ?Sized>(
+    worker: &W,
+    dag: &mut ProofDag,
+    max_concurrent_jobs: usize,
+    opts: &ProofGenOptions,
+) -> ProofGenResult<()> {
+    run_dag_on_worker_until(
+        worker,
+        dag,
+        max_concurrent_jobs,
+        opts,
+        &AtomicBool::new(false),
+    )
+    .await
+}
+
+/// Async version of [`run_dag_until`](crate::scheduler::run_dag_until): stops
+/// dispatching jobs to `worker` once `shutdown` is set, and returns once the
+/// jobs in flight have finished.
+pub async fn run_dag_on_worker_until<W: ProofWorker + ?Sized>(
+    worker: &W,
+    dag: &mut ProofDag,
+    max_concurrent_jobs: usize,
+    opts: &ProofGenOptions,
+    shutdown: &AtomicBool,
+) -> ProofGenResult<()> {
+    let max_concurrent_jobs = max_concurrent_jobs.max(1);
+    let mut in_flight = FuturesUnordered::new();
+    let mut first_err = None;
+
+    loop {
+        if first_err.is_none() && !shutdown.load(Ordering::Relaxed) {
+            let num_free = max_concurrent_jobs - in_flight.len();
+            for id in dag.ready_jobs().into_iter().take(num_free) {
+                let job = dag.take_job(id)?;
+                let opts = opts.clone();
+                in_flight.push(async move { (id, dispatch_job(worker, job, opts).await) });
+            }
+        }
+
+        let Some((id, result)) = in_flight.next().await else {
+            break;
+        };
+
+        match result {
+            Ok(output) => dag.complete(id, output)?,
+            Err(err) => {
+                dag.fail(id)?;
+                first_err.get_or_insert(err);
+            }
+        }
+    }
+
+    match first_err {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,283 @@
+use log::info;
+use plonky2::{
+    field::goldilocks_field::GoldilocksField,
+    iop::{
+        target::Target,
+        witness::{PartialWitness, WitnessWrite},
+    },
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{
+            CircuitConfig, CircuitData, CommonCircuitData, VerifierCircuitTarget,
+            VerifierOnlyCircuitData,
+        },
+        config::{GenericConfig, KeccakGoldilocksConfig, PoseidonGoldilocksConfig},
+        proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget},
+    },
+};
+
+use crate::{
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_types::{
+        GeneratedBlockProof, GeneratedKeccakWrappedBlockProof, GeneratedWrappedBlockProof,
+    },
+    prover_state::ProverState,
+    types::{CircuitDigest, PlonkyProofIntern},
+};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Wrapping layers are added until the outer circuit is at most this size.
+const WRAPPER_THRESHOLD_DEGREE_BITS: usize = 13;
+
+/// A chain of recursive circuits that shrink a block proof into a small outer
+/// circuit of a fixed size.
+///
+/// The outer circuit (and therefore the wrapped proof and its verifier) stays
+/// the same no matter what was in the block. Building the wrapper takes a
+/// little while, so it should be built once and reused.
+///
+/// Note that the outer circuit is still a Goldilocks/Poseidon plonky2 circuit.
+/// Verifying it on-chain requires wrapping it in a BN254 SNARK outside of this
+/// crate.
+pub struct BlockProofWrapper {
+    layers: Vec<WrapperLayer>,
+}
+
+/// A single recursive circuit that verifies a proof of the previous layer and
+/// forwards its public inputs.
+///
+/// The inner proof is always a Poseidon proof, but the layer itself may use
+/// another hasher config `OC`.
+struct WrapperLayer<OC: GenericConfig<D, F = F> = C> {
+    circuit: CircuitData<F, OC, D>,
+    proof_with_pis_target: ProofWithPublicInputsTarget<D>,
+}
+
+impl<OC: GenericConfig<D, F = F>> WrapperLayer<OC> {
+    /// If the inner circuit is cyclic (ie. the block circuit), its proofs
+    /// carry the verifier data that they were verified against as public
+    /// inputs. Plonky2's `verify_block` checks those against the actual
+    /// verifier data outside of the circuit, so the layer has to constrain
+    /// them for the wrapped proof to attest as much as `verify_block` does.
+    fn new(
+        inner_common: &CommonCircuitData<F, D>,
+        inner_verifier_only: &VerifierOnlyCircuitData<C, D>,
+        inner_is_cyclic: bool,
+        config: CircuitConfig,
+    ) -> Self {
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+
+        let proof_with_pis_target = builder.add_virtual_proof_with_pis(inner_common);
+        let inner_verifier_data = builder.constant_verifier_data(inner_verifier_only);
+        builder.verify_proof::<C>(&proof_with_pis_target, &inner_verifier_data, inner_common);
+        if inner_is_cyclic {
+            connect_cyclic_verifier_data(
+                &mut builder,
+                &proof_with_pis_target.public_inputs,
+                &inner_verifier_data,
+            );
+        }
+        builder.register_public_inputs(&proof_with_pis_target.public_inputs);
+
+        Self {
+            circuit: builder.build::<OC>(),
+            proof_with_pis_target,
+        }
+    }
+
+    fn prove(
+        &self,
+        inner_proof: &PlonkyProofIntern,
+    ) -> anyhow::Result<ProofWithPublicInputs<F, OC, D>> {
+        let mut witness = PartialWitness::new();
+        witness.set_proof_with_pis_target(&self.proof_with_pis_target, inner_proof);
+
+        self.circuit.prove(witness)
+    }
+}
+
+impl BlockProofWrapper {
+    /// Build the wrapping circuits for the block circuit of the prover state.
+    ///
+    /// The circuits are blinded if zero-knowledge is enabled on the prover
+    /// state.
+    pub fn new(p_state: &ProverState) -> Self {
+        info!("Building block proof wrapper circuits...");
+
+        let config = CircuitConfig {
+            zero_knowledge: p_state.zero_knowledge,
+            ..CircuitConfig::standard_recursion_config()
+        };
+
+        let block_circuit = &p_state.state.block.circuit;
+        let mut layers = vec![WrapperLayer::new(
+            &block_circuit.common,
+            &block_circuit.verifier_only,
+            true,
+            config.clone(),
+        )];
+
+        loop {
+            let outer = &layers.last().unwrap().circuit;
+            if outer.common.degree_bits() <= WRAPPER_THRESHOLD_DEGREE_BITS {
+                break;
+            }
+
+            let layer =
+                WrapperLayer::new(&outer.common, &outer.verifier_only, false, config.clone());
+
+            // Blinded recursive circuits may not get below the threshold, in
+            // which case wrapping again would not shrink them any further.
+            if layer.circuit.common.degree_bits() >= outer.common.degree_bits() {
+                break;
+            }
+
+            layers.push(layer);
+        }
+
+        info!(
+            "Finished building {} block proof wrapper circuit(s)!",
+            layers.len()
+        );
+
+        Self { layers }
+    }
+
+    /// Shrink a block proof into the outer wrapper circuit.
+    pub fn wrap_block_proof(
+        &self,
+        block_proof: &GeneratedBlockProof,
+    ) -> ProofGenResult<GeneratedWrappedBlockProof> {
+        let mut intern = block_proof.intern.clone();
+        for layer in self.layers.iter() {
+            intern = layer
+                .prove(&intern)
+                .map_err(|err| ProofGenError::BlockProving {
+                    b_height: block_proof.b_height,
+                    msg: format!("{:#}", err),
+                })?;
+        }
+
+        Ok(GeneratedWrappedBlockProof {
+            b_height: block_proof.b_height,
+            intern,
+        })
+    }
+
+    /// Verify a wrapped block proof against the outer wrapper circuit.
+    pub fn verify_wrapped_block_proof(
+        &self,
+        proof: &GeneratedWrappedBlockProof,
+    ) -> ProofGenResult<()> {
+        self.outer_circuit()
+            .verify(proof.intern.clone())
+            .map_err(|err| ProofGenError::Verification(format!("{:#}", err)))
+    }
+
+    /// The outer circuit that wrapped block proofs are proofs of.
+    pub fn outer_circuit(&self) -> &CircuitData<F, C, D> {
+        &self.layers.last().unwrap().circuit
+    }
+
+    /// The digests of the wrapping circuits, from the one that verifies the
+    /// block proof to the outer circuit.
+    pub fn layer_circuit_digests(&self) -> Vec<CircuitDigest> {
+        self.layers
+            .iter()
+            .map(|layer| layer.circuit.verifier_only.circuit_digest)
+            .collect()
+    }
+}
+
+/// A [`BlockProofWrapper`] with one more layer on top, which uses Keccak
+/// instead of Poseidon as its hasher.
+///
+/// Keccak is far cheaper to compute in the EVM than Poseidon, so the outer
+/// proof is cheaper to verify in EVM contexts. The Keccak layer has to be the
+/// last one, as verifying Keccak proofs in a plonky2 circuit is very expensive.
+pub struct KeccakBlockProofWrapper {
+    wrapper: BlockProofWrapper,
+    keccak_layer: WrapperLayer<KeccakGoldilocksConfig>,
+}
+
+impl KeccakBlockProofWrapper {
+    /// Build the wrapping circuits for the block circuit of the prover state.
+    pub fn new(p_state: &ProverState) -> Self {
+        let wrapper = BlockProofWrapper::new(p_state);
+
+        let config = CircuitConfig {
+            zero_knowledge: p_state.zero_knowledge,
+            ..CircuitConfig::standard_recursion_config()
+        };
+        let outer = wrapper.outer_circuit();
+        let keccak_layer = WrapperLayer::new(&outer.common, &outer.verifier_only, false, config);
+
+        Self {
+            wrapper,
+            keccak_layer,
+        }
+    }
+
+    /// Shrink a block proof into the outer Keccak circuit.
+    pub fn wrap_block_proof(
+        &self,
+        block_proof: &GeneratedBlockProof,
+    ) -> ProofGenResult<GeneratedKeccakWrappedBlockProof> {
+        let wrapped = self.wrapper.wrap_block_proof(block_proof)?;
+        let intern = self.keccak_layer.prove(&wrapped.intern).map_err(|err| {
+            ProofGenError::BlockProving {
+                b_height: block_proof.b_height,
+                msg: format!("{:#}", err),
+            }
+        })?;
+
+        Ok(GeneratedKeccakWrappedBlockProof {
+            b_height: block_proof.b_height,
+            intern,
+        })
+    }
+
+    /// Verify a wrapped block proof against the outer Keccak circuit.
+    pub fn verify_wrapped_block_proof(
+        &self,
+        proof: &GeneratedKeccakWrappedBlockProof,
+    ) -> ProofGenResult<()> {
+        self.outer_circuit()
+            .verify(proof.intern.clone())
+            .map_err(|err| ProofGenError::Verification(format!("{:#}", err)))
+    }
+
+    /// The outer circuit that wrapped block proofs are proofs of.
+    pub fn outer_circuit(&self) -> &CircuitData<F, KeccakGoldilocksConfig, D> {
+        &self.keccak_layer.circuit
+    }
+}
+
+/// Constrain the verifier data public inputs of a proof of a cyclic circuit to
+/// `verifier_data`.
+///
+/// Plonky2 appends the verifier data to the public inputs of cyclic circuits,
+/// as the circuit digest followed by the constants and sigmas cap (see
+/// `check_cyclic_proof_verifier_data`).
+fn connect_cyclic_verifier_data(
+    builder: &mut CircuitBuilder<F, D>,
+    public_inputs: &[Target],
+    verifier_data: &VerifierCircuitTarget,
+) {
+    let expected = verifier_data.circuit_digest.elements.iter().chain(
+        verifier_data
+            .constants_sigmas_cap
+            .0
+            .iter()
+            .flat_map(|hash| hash.elements.iter()),
+    );
+    let num_verifier_data_pis = 4 + 4 * verifier_data.constants_sigmas_cap.0.len();
+    let verifier_data_pis = &public_inputs[public_inputs.len() - num_verifier_data_pis..];
+
+    for (&pi, &expected) in verifier_data_pis.iter().zip(expected) {
+        builder.connect(pi, expected);
+    }
+}
@@ -0,0 +1,56 @@
+//! Mock inputs shared by the integration tests that run against
+//! `MockProverState`.
+
+use eth_trie_utils::partial_trie::{HashedPartialTrie, Node, PartialTrie};
+use ethereum_types::{H256, U256};
+use plonky2_evm::{
+    generation::GenerationInputs,
+    proof::{BlockHashes, BlockMetadata},
+};
+use plonky_block_proof_gen::{
+    backend::BlockProverBackend, mock::MockProverState, options::ProofGenOptions,
+    proof_types::GeneratedTxnProof, types::TxnIdx,
+};
+use proof_protocol_decoder::types::{BlockLevelData, OtherBlockData, TxnProofGenIR};
+
+pub fn other_data() -> OtherBlockData {
+    OtherBlockData {
+        b_data: BlockLevelData {
+            b_meta: BlockMetadata {
+                block_number: U256::one(),
+                ..Default::default()
+            },
+            b_hashes: BlockHashes {
+                prev_hashes: vec![H256::zero(); 256],
+                cur_hash: H256::zero(),
+            },
+            withdrawals: Vec::new(),
+        },
+        genesis_state_trie_root: HashedPartialTrie::new(Node::Empty).hash(),
+    }
+}
+
+/// Mock proofs of `num_txns` consecutive txns of a block, each of which
+/// changes the txn root.
+pub fn txn_proofs(p_state: &MockProverState, num_txns: usize) -> Vec<GeneratedTxnProof> {
+    let base = p_state
+        .generate_txn_proof(
+            TxnProofGenIR {
+                txn_idx: 0,
+                gen_inputs: GenerationInputs::default(),
+            },
+            &ProofGenOptions::default(),
+        )
+        .expect("The mock prover does not fail");
+
+    (0..num_txns)
+        .map(|i| {
+            let mut proof = base.clone();
+            proof.txn_idx = TxnIdx(i);
+            proof.common.roots_after = proof.common.roots_before.clone();
+            proof.common.roots_before.transactions_root = H256::from_low_u64_be(i as u64);
+            proof.common.roots_after.transactions_root = H256::from_low_u64_be(i as u64 + 1);
+            proof
+        })
+        .collect()
+}
@@ -0,0 +1,227 @@
+//! Runs proof DAGs with the mock prover. Run with `--features mock`.
+#![cfg(feature = "mock")]
+
+mod common;
+
+use plonky_block_proof_gen::{
+    backend::BlockProverBackend,
+    mock::MockProverState,
+    options::ProofGenOptions,
+    proof_gen::{ProofGenError, ProofGenResult},
+    proof_types::{AggregatableProof, GeneratedAggProof, GeneratedBlockProof, GeneratedTxnProof},
+    scheduler::{run_dag, JobId, JobOutput, JobPriority, JobStatus, ProofDag},
+    types::CircuitDigests,
+};
+use proof_protocol_decoder::types::{OtherBlockData, TxnProofGenIR};
+
+use crate::common::{other_data, txn_proofs};
+
+/// The jobs of a block with four txns, whose proofs are given.
+struct BlockDag {
+    dag: ProofDag,
+    txns: Vec<JobId>,
+    lhs: JobId,
+    rhs: JobId,
+    root: JobId,
+    block: JobId,
+}
+
+fn block_dag(p_state: &MockProverState) -> BlockDag {
+    let mut dag = ProofDag::new();
+    let txns: Vec<_> = txn_proofs(p_state, 4)
+        .into_iter()
+        .map(|proof| dag.add_proof(JobOutput::Txn(proof)))
+        .collect();
+
+    let lhs = dag.add_agg(txns[0], txns[1], other_data()).unwrap();
+    let rhs = dag.add_agg(txns[2], txns[3], other_data()).unwrap();
+    let root = dag.add_agg(lhs, rhs, other_data()).unwrap();
+    let block = dag.add_block(root, None, other_data()).unwrap();
+
+    BlockDag {
+        dag,
+        txns,
+        lhs,
+        rhs,
+        root,
+        block,
+    }
+}
+
+/// Execute the ready job `id` and record its result.
+fn execute(dag: &mut ProofDag, p_state: &MockProverState, id: JobId) {
+    let output = dag
+        .take_job(id)
+        .unwrap()
+        .execute(p_state, &ProofGenOptions::default())
+        .expect("The mock prover does not fail");
+    dag.complete(id, output).unwrap();
+}
+
+#[test]
+fn runs_the_jobs_of_a_block() {
+    let p_state = MockProverState::default();
+    let BlockDag {
+        mut dag,
+        txns,
+        block,
+        ..
+    } = block_dag(&p_state);
+
+    run_dag(&p_state, &mut dag, 2, &ProofGenOptions::default()).unwrap();
+
+    assert!(dag.is_complete());
+    assert_eq!(dag.num_running(), 0);
+    // Proofs that every dependent job used are dropped.
+    assert!(txns.iter().all(|id| dag.output(*id).is_none()));
+    assert!(matches!(dag.take_output(block), Some(JobOutput::Block(_))));
+}
+
+#[test]
+fn hands_out_jobs_once_their_dependencies_are_done() {
+    let p_state = MockProverState::default();
+    let BlockDag {
+        mut dag,
+        lhs,
+        rhs,
+        root,
+        block,
+        ..
+    } = block_dag(&p_state);
+
+    assert_eq!(dag.ready_jobs(), vec![lhs, rhs]);
+
+    dag.set_priority(rhs, JobPriority::ChainHead).unwrap();
+    assert_eq!(dag.ready_jobs(), vec![rhs, lhs]);
+
+    execute(&mut dag, &p_state, rhs);
+    assert_eq!(dag.ready_jobs(), vec![lhs]);
+
+    execute(&mut dag, &p_state, lhs);
+    assert_eq!(dag.ready_jobs(), vec![root]);
+
+    execute(&mut dag, &p_state, root);
+    assert_eq!(dag.ready_jobs(), vec![block]);
+
+    execute(&mut dag, &p_state, block);
+    assert!(dag.ready_jobs().is_empty());
+    assert!(dag.is_complete());
+}
+
+#[test]
+fn retries_failed_and_requeued_jobs() {
+    let p_state = MockProverState::default();
+    let BlockDag {
+        mut dag, lhs, rhs, ..
+    } = block_dag(&p_state);
+
+    let job = dag.take_job(lhs).unwrap();
+    assert_eq!(dag.status(lhs), Some(JobStatus::Running));
+    assert_eq!(dag.ready_jobs(), vec![rhs]);
+    assert!(dag.take_job(lhs).is_err());
+
+    dag.fail(lhs).unwrap();
+    assert_eq!(dag.status(lhs), Some(JobStatus::Failed));
+    assert_eq!(dag.ready_jobs(), vec![rhs]);
+
+    dag.retry(lhs).unwrap();
+    assert_eq!(dag.status(lhs), Some(JobStatus::Pending));
+    assert_eq!(dag.ready_jobs(), vec![lhs, rhs]);
+    assert!(dag.retry(lhs).is_err());
+
+    dag.take_job(lhs).unwrap();
+    dag.requeue(lhs).unwrap();
+    assert_eq!(dag.status(lhs), Some(JobStatus::Pending));
+
+    // A late result of the job is rejected once it is not running anymore.
+    let output = job.execute(&p_state, &ProofGenOptions::default()).unwrap();
+    assert!(dag.complete(lhs, output.clone()).is_err());
+
+    dag.take_job(lhs).unwrap();
+    dag.complete(lhs, output).unwrap();
+    assert_eq!(dag.status(lhs), Some(JobStatus::Done));
+}
+
+#[test]
+fn restores_running_jobs_as_pending() {
+    let p_state = MockProverState::default();
+    let BlockDag {
+        mut dag,
+        lhs,
+        rhs,
+        block,
+        ..
+    } = block_dag(&p_state);
+
+    execute(&mut dag, &p_state, lhs);
+    dag.take_job(rhs).unwrap();
+
+    let mut restored = ProofDag::restore(&dag.snapshot().unwrap()).unwrap();
+    assert_eq!(restored.status(lhs), Some(JobStatus::Done));
+    assert_eq!(restored.status(rhs), Some(JobStatus::Pending));
+    assert_eq!(restored.ready_jobs(), vec![rhs]);
+
+    run_dag(&p_state, &mut restored, 2, &ProofGenOptions::default()).unwrap();
+    assert!(restored.is_complete());
+    assert!(matches!(
+        restored.take_output(block),
+        Some(JobOutput::Block(_))
+    ));
+}
+
+/// A prover whose agg proofs panic.
+struct PanickingAggProver(MockProverState);
+
+impl BlockProverBackend for PanickingAggProver {
+    fn generate_txn_proof(
+        &self,
+        start_info: TxnProofGenIR,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedTxnProof> {
+        self.0.generate_txn_proof(start_info, opts)
+    }
+
+    fn generate_agg_proof(
+        &self,
+        _lhs_child: &AggregatableProof,
+        _rhs_child: &AggregatableProof,
+        _other_data: OtherBlockData,
+        _opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedAggProof> {
+        panic!("Agg proving panicked");
+    }
+
+    fn generate_block_proof(
+        &self,
+        prev_opt_parent_b_proof: Option<&GeneratedBlockProof>,
+        curr_block_agg_proof: &GeneratedAggProof,
+        other_data: OtherBlockData,
+        opts: &ProofGenOptions,
+    ) -> ProofGenResult<GeneratedBlockProof> {
+        self.0.generate_block_proof(
+            prev_opt_parent_b_proof,
+            curr_block_agg_proof,
+            other_data,
+            opts,
+        )
+    }
+
+    fn circuit_digests(&self) -> CircuitDigests {
+        self.0.circuit_digests()
+    }
+}
+
+#[test]
+fn fails_jobs_that_panic() {
+    let p_state = PanickingAggProver(MockProverState::default());
+    let BlockDag {
+        mut dag, lhs, rhs, ..
+    } = block_dag(&p_state.0);
+
+    let err = run_dag(&p_state, &mut dag, 2, &ProofGenOptions::default()).unwrap_err();
+
+    assert!(matches!(err, ProofGenError::ProverPanic(_)));
+    assert_eq!(dag.status(lhs), Some(JobStatus::Failed));
+    assert_eq!(dag.status(rhs), Some(JobStatus::Failed));
+    assert_eq!(dag.num_running(), 0);
+}
@@ -0,0 +1,92 @@
+//! Aggregates the txn proofs of a block with the mock prover, split between
+//! partial aggregators that get merged. Run with `--features mock`.
+#![cfg(feature = "mock")]
+
+mod common;
+
+use ethereum_types::H256;
+use plonky_block_proof_gen::{
+    aggregation::StreamingAggregator,
+    mock::MockProverState,
+    options::ProofGenOptions,
+    proof_types::{AggregatableProof, GeneratedTxnProof},
+    types::TxnIdx,
+};
+
+use crate::common::{other_data, txn_proofs};
+
+const NUM_TXNS: usize = 4;
+
+fn push_all(
+    aggregator: &mut StreamingAggregator<'_, MockProverState>,
+    proofs: &[GeneratedTxnProof],
+) {
+    for proof in proofs {
+        let agg_proof = aggregator
+            .push(AggregatableProof::from(proof.clone()))
+            .expect("Failed to aggregate a txn proof");
+        assert!(
+            agg_proof.is_none(),
+            "A partial aggregator returned a final proof"
+        );
+    }
+}
+
+#[test]
+fn merges_two_completed_halves() {
+    let p_state = MockProverState::default();
+    let proofs = txn_proofs(&p_state, NUM_TXNS);
+    let opts = ProofGenOptions::default();
+
+    let mut lhs = StreamingAggregator::new_partial(
+        &p_state,
+        TxnIdx(0)..TxnIdx(2),
+        NUM_TXNS,
+        other_data(),
+        opts.clone(),
+    )
+    .expect("Txns 0..2 are a part of the block");
+    let mut rhs = StreamingAggregator::new_partial(
+        &p_state,
+        TxnIdx(2)..TxnIdx(4),
+        NUM_TXNS,
+        other_data(),
+        opts,
+    )
+    .expect("Txns 2..4 are a part of the block");
+
+    push_all(&mut lhs, &proofs[..2]);
+    push_all(&mut rhs, &proofs[2..]);
+    assert!(lhs.is_covered() && !lhs.is_complete());
+    assert!(rhs.is_covered() && !rhs.is_complete());
+
+    let agg_proof = lhs
+        .merge(rhs)
+        .expect("Failed to merge the halves")
+        .expect("The merged halves cover the whole block");
+
+    assert_eq!(
+        agg_proof.underlying_txns.txn_idxs,
+        TxnIdx(0)..TxnIdx(NUM_TXNS)
+    );
+    assert_eq!(
+        agg_proof.common.roots_after.transactions_root,
+        H256::from_low_u64_be(NUM_TXNS as u64)
+    );
+    assert!(lhs.is_complete());
+}
+
+#[test]
+fn rejects_sub_ranges_outside_of_the_block() {
+    let p_state = MockProverState::default();
+
+    let res = StreamingAggregator::new_partial(
+        &p_state,
+        TxnIdx(2)..TxnIdx(NUM_TXNS + 1),
+        NUM_TXNS,
+        other_data(),
+        ProofGenOptions::default(),
+    );
+
+    assert!(res.is_err());
+}
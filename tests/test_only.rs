@@ -0,0 +1,46 @@
+//! Proves with the tiny prover state of `ProverStateBuilder::test_only`, which
+//! builds in a fraction of the time of the default configuration.
+
+use eth_trie_utils::partial_trie::{HashedPartialTrie, Node, PartialTrie};
+use ethereum_types::{H256, U256};
+use plonky2_evm::proof::{BlockHashes, BlockMetadata};
+use plonky_block_proof_gen::{
+    options::ProofGenOptions, pipeline::prove_empty_block, prover_state::ProverStateBuilder,
+    verification::verify_block_proof,
+};
+use proof_protocol_decoder::types::{BlockLevelData, OtherBlockData};
+
+#[test]
+fn test_only_proves_and_verifies_an_empty_block() {
+    let p_state = ProverStateBuilder::test_only().build();
+
+    // The first proven block of a chain starts from the genesis state, which
+    // is empty here. Plonky2 links a block without a parent block proof to a
+    // dummy parent at the height before it, so it can not be block 0.
+    let state_root = HashedPartialTrie::new(Node::Empty).hash();
+    let other_data = OtherBlockData {
+        b_data: BlockLevelData {
+            b_meta: BlockMetadata {
+                block_number: U256::one(),
+                ..Default::default()
+            },
+            b_hashes: BlockHashes {
+                prev_hashes: vec![H256::zero(); 256],
+                cur_hash: H256::zero(),
+            },
+            withdrawals: Vec::new(),
+        },
+        genesis_state_trie_root: state_root,
+    };
+
+    let b_proof = prove_empty_block(
+        &p_state,
+        state_root,
+        other_data,
+        None,
+        &ProofGenOptions::default(),
+    )
+    .expect("Failed to prove the empty block");
+
+    verify_block_proof(&p_state, &b_proof).expect("The block proof does not verify");
+}